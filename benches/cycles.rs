@@ -0,0 +1,59 @@
+use chip8_core::CPU;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+const INSTRUCTIONS: u64 = 10_000;
+
+// A tight loop mixing ALU ops, a sprite draw and a jump:
+//   0x200: A000  LD I, 0x000
+//   0x202: 6105  LD V1, 0x05
+//   0x204: 8013  XOR V0, V1
+//   0x206: D015  DRW V0, V1, 5
+//   0x208: 1204  JP 0x204
+const PROGRAM: [u8; 10] = [0xA0, 0x00, 0x61, 0x05, 0x80, 0x13, 0xD0, 0x15, 0x12, 0x04];
+
+fn cpu_with_program() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.memory[0x200..0x200 + PROGRAM.len()].copy_from_slice(&PROGRAM);
+    cpu
+}
+
+// The pre-`step` API shape, which moved the whole CPU in and out of every cycle.
+fn emulate_cycle_by_value(mut cpu: CPU) -> CPU {
+    cpu.step().unwrap();
+    cpu
+}
+
+fn cycles(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cycles");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+
+    group.bench_function("by_value", |b| {
+        b.iter_batched(
+            cpu_with_program,
+            |mut cpu| {
+                for _ in 0..INSTRUCTIONS {
+                    cpu = black_box(emulate_cycle_by_value(black_box(cpu)));
+                }
+                cpu
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("in_place", |b| {
+        b.iter_batched_ref(
+            cpu_with_program,
+            |cpu| {
+                for _ in 0..INSTRUCTIONS {
+                    black_box(&mut *cpu).step().unwrap();
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, cycles);
+criterion_main!(benches);
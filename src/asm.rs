@@ -0,0 +1,535 @@
+//! Assembles Cowgod-style CHIP-8 source, the syntax `disasm` writes with
+//! `Syntax::Cowgod`, into a ROM for `CPU::load_rom`.
+//!
+//! Source has one statement per line and `;` starts a comment. A line may
+//! begin with a `label:`. Besides the instructions there are directives:
+//!
+//! - `NAME equ VALUE` defines a constant
+//! - `db` and `dw` emit comma-separated bytes and big-endian words
+//! - `include "FILE"` assembles another file in place, found relative to the
+//!   file that includes it
+//!
+//! Numbers are decimal, hex with `#`, `$` or `0x`, or binary with `%` or `0b`.
+//! Wherever a number goes, labels, constants and numbers can be added and
+//! subtracted. Mnemonics and register names are case-insensitive; labels and
+//! constants are not.
+
+use crate::disasm::{AluOp, Instruction, START};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// How deeply `include`s may nest before the assembler assumes a cycle.
+const MAX_INCLUDE_DEPTH: usize = 16;
+/// How deeply constants may refer to other constants.
+const MAX_CONSTANT_DEPTH: usize = 32;
+/// The error for a program that runs past the end of 64 KB of memory.
+const FULL: &str = "program does not fit in 64 KB of memory";
+
+/// Every instruction mnemonic, to tell bad operands from unknown instructions.
+const MNEMONICS: [&str; 31] = [
+    "cls", "ret", "scd", "scu", "scr", "scl", "exit", "low", "high", "jp", "call", "se", "sne",
+    "save", "load", "ld", "add", "or", "and", "xor", "sub", "subn", "shr", "shl", "rnd", "drw",
+    "skp", "sknp", "plane", "audio", "pitch",
+];
+
+/// Names that are operands rather than symbols.
+const RESERVED: [&str; 10] = ["i", "dt", "st", "k", "f", "hf", "b", "r", "long", "equ"];
+
+/// An error in the source, with the file and line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assembles `source` into a ROM to be loaded at 0x200. Includes are found
+/// relative to the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::default();
+    assembler.read(source, "<source>", Path::new(""), 0)?;
+    assembler.emit()
+}
+
+/// Assembles the file at `path` into a ROM to be loaded at 0x200.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::default();
+    assembler.include(path.as_ref(), None, 0)?;
+    assembler.emit()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    /// Index into `Assembler::files`.
+    file: usize,
+    line: usize,
+}
+
+/// Numbers and symbols to be added up once every label is known.
+#[derive(Debug, Clone)]
+struct Expr(Vec<(i64, Term)>);
+
+#[derive(Debug, Clone)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    V(u8),
+    I,
+    /// `[I]`, the memory I points at.
+    AtI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    /// `LONG addr`, the 16-bit address of `LD I, LONG addr`.
+    Long(Expr),
+    Value(Expr),
+}
+
+enum Body {
+    Instruction(String, Vec<Operand>),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+struct Statement {
+    location: Location,
+    body: Body,
+}
+
+enum Symbol {
+    Label(u16),
+    Constant(Expr),
+}
+
+/// Reads the source into statements and symbols in a first pass, so that
+/// labels can be used before they are defined, then emits the ROM.
+#[derive(Default)]
+struct Assembler {
+    files: Vec<String>,
+    statements: Vec<Statement>,
+    symbols: HashMap<String, Symbol>,
+    /// Bytes emitted so far by the statements read.
+    size: usize,
+}
+
+impl Assembler {
+    fn error(&self, location: Location, message: String) -> AsmError {
+        AsmError {
+            file: self.files[location.file].clone(),
+            line: location.line,
+            message,
+        }
+    }
+
+    fn include(
+        &mut self,
+        path: &Path,
+        from: Option<Location>,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let source = fs::read_to_string(path).map_err(|e| {
+            let message = format!("cannot read {}: {}", path.display(), e);
+            match from {
+                Some(location) => self.error(location, message),
+                None => AsmError {
+                    file: path.display().to_string(),
+                    line: 0,
+                    message,
+                },
+            }
+        })?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        self.read(&source, &path.display().to_string(), dir, depth)
+    }
+
+    /// First pass over one file: records labels and constants and parses each
+    /// statement's operands.
+    fn read(&mut self, source: &str, file: &str, dir: &Path, depth: usize) -> Result<(), AsmError> {
+        self.files.push(file.to_owned());
+        let file = self.files.len() - 1;
+        for (i, line) in source.lines().enumerate() {
+            let location = Location { file, line: i + 1 };
+            let included = self
+                .read_line(line, location)
+                .map_err(|message| self.error(location, message))?;
+            if let Some(name) = included {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    let message = "includes are nested too deeply".to_owned();
+                    return Err(self.error(location, message));
+                }
+                self.include(&dir.join(name), Some(location), depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads one line, returning the file name if it is an `include`.
+    fn read_line(&mut self, line: &str, location: Location) -> Result<Option<String>, String> {
+        let mut line = strip_comment(line).trim();
+
+        if let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if is_identifier(label) {
+                self.define(label, Symbol::Label(self.address()?))?;
+                line = rest.trim();
+            }
+        }
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let (first, rest) = split_word(line);
+        let (second, value) = split_word(rest);
+        if second.eq_ignore_ascii_case("equ") {
+            self.define(first, Symbol::Constant(parse_expr(value)?))?;
+            return Ok(None);
+        }
+
+        let body = match first.to_ascii_lowercase().as_str() {
+            "include" => {
+                let name = rest
+                    .strip_prefix('"')
+                    .and_then(|r| r.strip_suffix('"'))
+                    .ok_or("include expects a quoted file name")?;
+                return Ok(Some(name.to_owned()));
+            }
+            "db" => Body::Bytes(parse_list(rest)?),
+            "dw" => Body::Words(parse_list(rest)?),
+            mnemonic => {
+                let operands = if rest.is_empty() {
+                    Vec::new()
+                } else {
+                    rest.split(',')
+                        .map(parse_operand)
+                        .collect::<Result<_, _>>()?
+                };
+                Body::Instruction(mnemonic.to_owned(), operands)
+            }
+        };
+        self.address()?;
+        self.size += match &body {
+            Body::Bytes(values) => values.len(),
+            Body::Words(values) => values.len() * 2,
+            Body::Instruction(mnemonic, operands) => {
+                match (mnemonic.as_str(), operands.as_slice()) {
+                    ("ld", [Operand::I, Operand::Long(_)]) => 4,
+                    _ => 2,
+                }
+            }
+        };
+        if START as usize + self.size > 0x10000 {
+            return Err(FULL.to_owned());
+        }
+        self.statements.push(Statement { location, body });
+        Ok(None)
+    }
+
+    /// The address the next statement is assembled at.
+    fn address(&self) -> Result<u16, String> {
+        let address = START as usize + self.size;
+        if address >= 0x10000 {
+            return Err(FULL.to_owned());
+        }
+        Ok(address as u16)
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol) -> Result<(), String> {
+        if !is_identifier(name) {
+            return Err(format!("`{}` is not a valid name", name));
+        }
+        if RESERVED.contains(&name.to_ascii_lowercase().as_str()) || parse_register(name).is_some()
+        {
+            return Err(format!("`{}` is a reserved name", name));
+        }
+        if self.symbols.contains_key(name) {
+            return Err(format!("`{}` is already defined", name));
+        }
+        self.symbols.insert(name.to_owned(), symbol);
+        Ok(())
+    }
+
+    /// Second pass: evaluates every operand and encodes the statements.
+    fn emit(&self) -> Result<Vec<u8>, AsmError> {
+        let mut rom = Vec::with_capacity(self.size);
+        for statement in &self.statements {
+            let error = |message| self.error(statement.location, message);
+            match &statement.body {
+                Body::Bytes(values) => {
+                    for value in values {
+                        rom.push(self.eval_in(value, -0x80, 0xFF, "byte").map_err(error)? as u8);
+                    }
+                }
+                Body::Words(values) => {
+                    for value in values {
+                        let word = self
+                            .eval_in(value, -0x8000, 0xFFFF, "word")
+                            .map_err(error)?;
+                        rom.extend_from_slice(&(word as u16).to_be_bytes());
+                    }
+                }
+                Body::Instruction(mnemonic, operands) => {
+                    let instruction = self.instruction(mnemonic, operands).map_err(error)?;
+                    rom.extend(instruction.encode());
+                }
+            }
+        }
+        Ok(rom)
+    }
+
+    fn eval(&self, expr: &Expr, depth: usize) -> Result<i64, String> {
+        let mut total: i64 = 0;
+        for (sign, term) in &expr.0 {
+            let value = match term {
+                Term::Number(n) => *n,
+                Term::Symbol(name) => match self.symbols.get(name) {
+                    Some(Symbol::Label(address)) => *address as i64,
+                    Some(Symbol::Constant(_)) if depth >= MAX_CONSTANT_DEPTH => {
+                        return Err(format!("constant `{}` is defined in terms of itself", name));
+                    }
+                    Some(Symbol::Constant(value)) => self.eval(value, depth + 1)?,
+                    None => return Err(format!("undefined symbol `{}`", name)),
+                },
+            };
+            total = value
+                .checked_mul(*sign)
+                .and_then(|value| total.checked_add(value))
+                .ok_or("value out of range")?;
+        }
+        Ok(total)
+    }
+
+    /// Evaluates `expr`, which must lie in `min..=max`.
+    fn eval_in(&self, expr: &Expr, min: i64, max: i64, what: &str) -> Result<i64, String> {
+        let value = self.eval(expr, 0)?;
+        if value < min || value > max {
+            return Err(format!("{} {} is out of range", what, value));
+        }
+        Ok(value)
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+        use Instruction::*;
+        use Operand::*;
+        let value = |operand: &Operand, min, max, what| match operand {
+            Value(expr) => self.eval_in(expr, min, max, what),
+            _ => Err(format!(
+                "`{}` expects a {} there",
+                mnemonic.to_uppercase(),
+                what
+            )),
+        };
+        let byte = |o| value(o, -0x80, 0xFF, "byte").map(|v| v as u8);
+        let nibble = |o| value(o, 0, 0xF, "nibble").map(|v| v as u8);
+        let address = |o| value(o, 0, 0xFFF, "address").map(|v| v as u16);
+        let alu = |op| match operands {
+            [V(x), V(y)] => Ok(Alu(op, *x, *y)),
+            [V(x)] if matches!(op, AluOp::Shr | AluOp::Shl) => Ok(Alu(op, *x, *x)),
+            _ => Err(format!(
+                "invalid operands for `{}`",
+                mnemonic.to_uppercase()
+            )),
+        };
+
+        let instruction = match (mnemonic, operands) {
+            ("cls", []) => Cls,
+            ("ret", []) => Ret,
+            ("scd", [n]) => ScrollDown(nibble(n)?),
+            ("scu", [n]) => ScrollUp(nibble(n)?),
+            ("scr", []) => ScrollRight,
+            ("scl", []) => ScrollLeft,
+            ("exit", []) => Exit,
+            ("low", []) => Lores,
+            ("high", []) => Hires,
+            ("jp", [V(0), a]) => JumpV0(address(a)?),
+            ("jp", [a]) => Jump(address(a)?),
+            ("call", [a]) => Call(address(a)?),
+            ("se", [V(x), V(y)]) => SkipEqReg(*x, *y),
+            ("se", [V(x), nn]) => SkipEq(*x, byte(nn)?),
+            ("sne", [V(x), V(y)]) => SkipNeReg(*x, *y),
+            ("sne", [V(x), nn]) => SkipNe(*x, byte(nn)?),
+            ("save", [V(x), V(y)]) => SaveRange(*x, *y),
+            ("load", [V(x), V(y)]) => LoadRange(*x, *y),
+            ("ld", [V(x), V(y)]) => Alu(AluOp::Mov, *x, *y),
+            ("ld", [V(x), Dt]) => GetDelay(*x),
+            ("ld", [V(x), K]) => WaitKey(*x),
+            ("ld", [V(x), AtI]) => Fill(*x),
+            ("ld", [V(x), R]) => LoadFlags(*x),
+            ("ld", [V(x), nn]) => LoadByte(*x, byte(nn)?),
+            ("ld", [I, Long(expr)]) => LoadILong(self.eval_in(expr, 0, 0xFFFF, "address")? as u16),
+            ("ld", [I, a]) => LoadI(address(a)?),
+            ("ld", [Dt, V(x)]) => SetDelay(*x),
+            ("ld", [St, V(x)]) => SetSound(*x),
+            ("ld", [F, V(x)]) => Font(*x),
+            ("ld", [Hf, V(x)]) => BigFont(*x),
+            ("ld", [B, V(x)]) => Bcd(*x),
+            ("ld", [AtI, V(x)]) => Store(*x),
+            ("ld", [R, V(x)]) => SaveFlags(*x),
+            ("add", [V(x), V(y)]) => Alu(AluOp::Add, *x, *y),
+            ("add", [I, V(x)]) => AddI(*x),
+            ("add", [V(x), nn]) => AddByte(*x, byte(nn)?),
+            ("or", _) => alu(AluOp::Or)?,
+            ("and", _) => alu(AluOp::And)?,
+            ("xor", _) => alu(AluOp::Xor)?,
+            ("sub", _) => alu(AluOp::Sub)?,
+            ("subn", _) => alu(AluOp::SubN)?,
+            ("shr", _) => alu(AluOp::Shr)?,
+            ("shl", _) => alu(AluOp::Shl)?,
+            ("rnd", [V(x), nn]) => Random(*x, byte(nn)?),
+            ("drw", [V(x), V(y), n]) => Draw(*x, *y, nibble(n)?),
+            ("skp", [V(x)]) => SkipKey(*x),
+            ("sknp", [V(x)]) => SkipNotKey(*x),
+            ("plane", [n]) => Plane(value(n, 0, 3, "plane mask")? as u8),
+            ("audio", []) => Audio,
+            ("pitch", [V(x)]) => Pitch(*x),
+            _ if MNEMONICS.contains(&mnemonic) => {
+                return Err(format!(
+                    "invalid operands for `{}`",
+                    mnemonic.to_uppercase()
+                ))
+            }
+            _ => return Err(format!("unknown instruction `{}`", mnemonic)),
+        };
+        Ok(instruction)
+    }
+}
+
+/// Cuts off a `;` comment, unless it is inside a quoted string.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits off the first whitespace-separated word.
+fn split_word(s: &str) -> (&str, &str) {
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (s, ""),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_register(s: &str) -> Option<u8> {
+    let digit = s.strip_prefix(|c| c == 'v' || c == 'V')?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn parse_operand(s: &str) -> Result<Operand, String> {
+    let s = s.trim();
+    if let Some(r) = parse_register(s) {
+        return Ok(Operand::V(r));
+    }
+    let operand = match s.to_ascii_lowercase().as_str() {
+        "i" => Operand::I,
+        "[i]" => Operand::AtI,
+        "dt" => Operand::Dt,
+        "st" => Operand::St,
+        "k" => Operand::K,
+        "f" => Operand::F,
+        "hf" => Operand::Hf,
+        "b" => Operand::B,
+        "r" => Operand::R,
+        "" => return Err("missing operand".to_owned()),
+        _ => {
+            let (first, rest) = split_word(s);
+            if first.eq_ignore_ascii_case("long") {
+                Operand::Long(parse_expr(rest)?)
+            } else {
+                Operand::Value(parse_expr(s)?)
+            }
+        }
+    };
+    Ok(operand)
+}
+
+fn parse_list(s: &str) -> Result<Vec<Expr>, String> {
+    if s.is_empty() {
+        return Err("expected at least one value".to_owned());
+    }
+    s.split(',').map(parse_expr).collect()
+}
+
+/// Parses numbers and names joined by `+` and `-`.
+fn parse_expr(s: &str) -> Result<Expr, String> {
+    let mut terms = Vec::new();
+    let mut rest = s.trim();
+    let mut sign = 1;
+    loop {
+        let (sign_here, term) = match rest.strip_prefix('-') {
+            Some(r) => (-sign, r.trim_start()),
+            None => (sign, rest),
+        };
+        let end = term
+            .find(|c: char| c == '+' || c == '-' || c.is_whitespace())
+            .unwrap_or(term.len());
+        if end == 0 {
+            return Err(format!("expected a value in `{}`", s.trim()));
+        }
+        terms.push((sign_here, parse_term(&term[..end])?));
+        rest = term[end..].trim_start();
+        sign = match rest.chars().next() {
+            None => break,
+            Some('+') => 1,
+            Some('-') => -1,
+            Some(_) => return Err(format!("unexpected `{}`", rest)),
+        };
+        rest = rest[1..].trim_start();
+    }
+    Ok(Expr(terms))
+}
+
+fn parse_term(s: &str) -> Result<Term, String> {
+    let lower = s.to_ascii_lowercase();
+    let number = if let Some(hex) = lower
+        .strip_prefix('#')
+        .or_else(|| lower.strip_prefix('$'))
+        .or_else(|| lower.strip_prefix("0x"))
+    {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix('%').or_else(|| lower.strip_prefix("0b")) {
+        i64::from_str_radix(bin, 2)
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse()
+    } else if is_identifier(s) {
+        return Ok(Term::Symbol(s.to_owned()));
+    } else {
+        return Err(format!("invalid value `{}`", s));
+    };
+    number
+        .map(Term::Number)
+        .map_err(|_| format!("invalid number `{}`", s))
+}
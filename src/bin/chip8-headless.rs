@@ -0,0 +1,417 @@
+//! Runs a ROM without a window or audio device, for CI and batch runs.
+//!
+//! The ROM runs for a fixed number of frames or cycles with optional scripted
+//! key presses, then the final registers, a hash of memory and optionally the
+//! framebuffer are written out. The exit code is 0 when the run completes (or
+//! the program exits through 00FD), 1 on a CPU fault, 2 on bad arguments or
+//! a ROM that cannot be loaded and 3 when a played back movie desyncs.
+
+use chip8_core::gdb::GdbStub;
+use chip8_core::headless::{self, render_text};
+use chip8_core::trace::Format;
+use chip8_core::{CpuError, Movie, Rng, Trace, CPU};
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
+use std::process;
+
+const USAGE: &str = "\
+usage: chip8-headless <rom> [options]
+
+options:
+  --frames N              run N 60 Hz frames (default 600)
+  --cycles N              run N instructions instead of whole frames
+  --cycles-per-frame N    instructions per frame (default 10)
+  --quirks NAME           platform or quirks preset: chip8, schip, xochip, vip, chip48
+  --seed N                seed for the CXNN random number generator (default 0)
+  --vip-rng               use the COSMAC VIP style random number routine
+  --press KEY@FRAME[:N]   hold hex KEY from FRAME for N frames (default 1); repeatable
+  --keys FILE             read --press entries from FILE, whitespace separated, # comments
+  --screen PATH           write the framebuffer to PATH: `-` for text on stdout,
+                          a .png file, or any other file as text
+  --scale N               PNG pixels per CHIP-8 pixel (default 8)
+  --record FILE           record the keypad of every frame to a movie file
+  --play FILE             play back a movie, replacing the run options and key
+                          presses, and check it ends in the recorded state
+  --trace FILE            write a line per instruction (PC, opcode, disassembly,
+                          registers and timers) to FILE, `-` for stdout
+  --trace-format NAME     native (default) or cadmium, to diff against Cadmium
+  --gdb PORT              instead of running, wait for a GDB remote protocol
+                          client on localhost:PORT and let it drive the CPU";
+
+const EXIT_FAULT: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_DESYNC: i32 = 3;
+
+/// Grey level of each vram value, matching the SDL frontend's palette.
+const PALETTE: [u8; 4] = [0, 255, 170, 85];
+
+/// How long the ROM runs for.
+enum Limit {
+    Frames(u64),
+    Cycles(u64),
+}
+
+/// A key held down for `frames` frames starting at `frame`.
+struct Press {
+    key: usize,
+    frame: u64,
+    frames: u64,
+}
+
+struct Options {
+    rom: String,
+    limit: Limit,
+    cycles_per_frame: u32,
+    quirks: Option<String>,
+    seed: u64,
+    vip_rng: bool,
+    presses: Vec<Press>,
+    screen: Option<String>,
+    scale: u32,
+    record: Option<String>,
+    play: Option<String>,
+    trace: Option<String>,
+    trace_format: Format,
+    gdb: Option<u16>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("error: {}\n\n{}", e, USAGE);
+        process::exit(EXIT_USAGE);
+    });
+
+    let mut cpu = CPU::new();
+    if let Some(name) = &options.quirks {
+        if !headless::apply_quirks(&mut cpu, name) {
+            eprintln!("error: unknown quirks preset: {}", name);
+            process::exit(EXIT_USAGE);
+        }
+    }
+    cpu.rng = if options.vip_rng {
+        Rng::cosmac_vip(options.seed)
+    } else {
+        Rng::new(options.seed)
+    };
+    if let Err(e) = cpu.load_rom(&options.rom) {
+        eprintln!("error: {}", e);
+        process::exit(EXIT_USAGE);
+    }
+
+    let playback = options.play.as_ref().map(|path| {
+        load_movie(path, &mut cpu).unwrap_or_else(|e| {
+            eprintln!("error: cannot play movie {}: {}", path, e);
+            process::exit(EXIT_USAGE);
+        })
+    });
+    if let Some(movie) = &playback {
+        options.cycles_per_frame = movie.cycles_per_frame;
+        options.limit = Limit::Frames(movie.len() as u64);
+    }
+    let mut recording = options
+        .record
+        .as_ref()
+        .map(|_| Movie::new(&cpu, options.cycles_per_frame));
+    let mut trace = options.trace.as_ref().map(|path| {
+        open_output(path)
+            .map(|out| Trace::with_format(out, options.trace_format))
+            .unwrap_or_else(|e| {
+                eprintln!("error: cannot write trace to {}: {}", path, e);
+                process::exit(EXIT_USAGE);
+            })
+    });
+
+    let (cycles, result) = match options.gdb {
+        Some(port) => serve_gdb(&mut cpu, port, options.cycles_per_frame).unwrap_or_else(|e| {
+            eprintln!("error: gdb connection failed: {}", e);
+            process::exit(EXIT_USAGE);
+        }),
+        None => run(
+            &mut cpu,
+            &options,
+            playback.as_ref(),
+            recording.as_mut(),
+            trace.as_mut(),
+        ),
+    };
+    if let (Some(path), Some(trace)) = (&options.trace, trace) {
+        if let Err(e) = trace.finish() {
+            eprintln!("error: cannot write trace to {}: {}", path, e);
+            process::exit(EXIT_USAGE);
+        }
+    }
+    print_report(&cpu, cycles, options.cycles_per_frame, result);
+
+    if let (Some(path), Some(movie)) = (&options.record, &mut recording) {
+        movie.finish(&cpu);
+        if let Err(e) = fs::write(path, movie.to_bytes()) {
+            eprintln!("error: cannot write movie to {}: {}", path, e);
+            process::exit(EXIT_USAGE);
+        }
+    }
+
+    if let Some(path) = &options.screen {
+        if let Err(e) = write_screen(&cpu, path, options.scale) {
+            eprintln!("error: cannot write screen to {}: {}", path, e);
+            process::exit(EXIT_USAGE);
+        }
+    }
+
+    if let Err(e) = result {
+        eprintln!("CPU fault: {}", e);
+        process::exit(EXIT_FAULT);
+    }
+    if let Some(movie) = &playback {
+        if let Err(e) = movie.verify(&cpu) {
+            println!("movie: {}", e);
+            process::exit(EXIT_DESYNC);
+        }
+        println!("movie: in sync");
+    }
+}
+
+/// Runs the CPU until the limit is reached, it exits or it faults. Keys come
+/// from `playback` if given, otherwise from the scripted presses, and are
+/// appended to `recording` at the start of every frame. Every instruction is
+/// written to `trace` before it executes. Returns the number of instructions
+/// executed.
+fn run(
+    cpu: &mut CPU,
+    options: &Options,
+    playback: Option<&Movie>,
+    mut recording: Option<&mut Movie>,
+    mut trace: Option<&mut Trace<Box<dyn Write>>>,
+) -> (u64, Result<(), CpuError>) {
+    let total = match options.limit {
+        Limit::Frames(frames) => frames.saturating_mul(options.cycles_per_frame as u64),
+        Limit::Cycles(cycles) => cycles,
+    };
+    let set_keys = |cpu: &mut CPU, frame: u64| {
+        match playback {
+            Some(movie) => cpu.key = movie.keys(frame as usize).unwrap_or([0; 16]),
+            None => {
+                for (key, state) in cpu.key.iter_mut().enumerate() {
+                    *state = options.presses.iter().any(|p| {
+                        p.key == key && (p.frame..p.frame.saturating_add(p.frames)).contains(&frame)
+                    }) as u8;
+                }
+            }
+        }
+        if let Some(movie) = recording.as_deref_mut() {
+            movie.record(&cpu.key);
+        }
+    };
+    let record = |cpu: &CPU| {
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.record(cpu);
+        }
+    };
+    headless::run(cpu, total, options.cycles_per_frame, set_keys, record)
+}
+
+/// Waits for one GDB client on localhost:`port` and serves it until it
+/// detaches. Faults are reported to the client, so the run itself succeeds.
+fn serve_gdb(
+    cpu: &mut CPU,
+    port: u16,
+    cycles_per_frame: u32,
+) -> io::Result<(u64, Result<(), CpuError>)> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("gdb: listening on {}", listener.local_addr()?);
+    let (stream, client) = listener.accept()?;
+    println!("gdb: {} connected", client);
+    GdbStub::new(stream, cycles_per_frame).serve(cpu)?;
+    Ok((cpu.steps(), Ok(())))
+}
+
+/// Reads the movie at `path` and prepares `cpu` to play it back.
+fn load_movie(path: &str, cpu: &mut CPU) -> Result<Movie, Box<dyn std::error::Error>> {
+    let movie = Movie::from_bytes(&fs::read(path)?)?;
+    movie.start(cpu)?;
+    Ok(movie)
+}
+
+fn print_report(cpu: &CPU, cycles: u64, cycles_per_frame: u32, result: Result<(), CpuError>) {
+    let status = match result {
+        Err(e) => format!("fault: {}", e),
+        Ok(()) if cpu.exited => "exited".to_owned(),
+        Ok(()) => "ok".to_owned(),
+    };
+    println!("status: {}", status);
+    println!("cycles: {}", cycles);
+    println!("frames: {}", cycles / cycles_per_frame as u64);
+    println!("pc: {:#06X}", cpu.pc);
+    println!("i: {:#06X}", cpu.index_register);
+    println!("sp: {}", cpu.sp);
+    println!("dt: {}", cpu.delay_timer);
+    println!("st: {}", cpu.audio_timer);
+    let registers: Vec<String> = cpu.registers.iter().map(|r| format!("{:02X}", r)).collect();
+    println!("v: {}", registers.join(" "));
+    let stack: Vec<String> = cpu.stack[..cpu.sp as usize]
+        .iter()
+        .map(|s| format!("{:04X}", s))
+        .collect();
+    println!("stack: {}", stack.join(" "));
+    println!("memory: {:016x}", cpu.memory_hash());
+}
+
+/// A buffered writer for `path`, or for stdout if it is `-`.
+fn open_output(path: &str) -> io::Result<Box<dyn Write>> {
+    if path == "-" {
+        Ok(Box::new(BufWriter::new(io::stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(fs::File::create(path)?)))
+    }
+}
+
+fn write_screen(cpu: &CPU, path: &str, scale: u32) -> Result<(), Box<dyn std::error::Error>> {
+    if path == "-" {
+        io::stdout().write_all(render_text(cpu).as_bytes())?;
+    } else if path.to_ascii_lowercase().ends_with(".png") {
+        write_png(cpu, path, scale)?;
+    } else {
+        fs::write(path, render_text(cpu))?;
+    }
+    Ok(())
+}
+
+fn write_png(cpu: &CPU, path: &str, scale: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let scale = scale as usize;
+    let (width, height) = (cpu.width * scale, cpu.height * scale);
+    let mut data = Vec::with_capacity(width * height);
+    for row in cpu.vram.chunks(cpu.width) {
+        let line: Vec<u8> = row
+            .iter()
+            .flat_map(|&p| std::iter::repeat_n(PALETTE[p as usize & 0x3], scale))
+            .collect();
+        for _ in 0..scale {
+            data.extend_from_slice(&line);
+        }
+    }
+
+    let file = BufWriter::new(fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: String::new(),
+        limit: Limit::Frames(600),
+        cycles_per_frame: 10,
+        quirks: None,
+        seed: 0,
+        vip_rng: false,
+        presses: Vec::new(),
+        screen: None,
+        scale: 8,
+        record: None,
+        play: None,
+        trace: None,
+        trace_format: Format::Native,
+        gdb: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--frames" => options.limit = Limit::Frames(parse_number(arg, value()?)?),
+            "--cycles" => options.limit = Limit::Cycles(parse_number(arg, value()?)?),
+            "--cycles-per-frame" => {
+                options.cycles_per_frame = parse_number(arg, value()?)?;
+                if options.cycles_per_frame == 0 {
+                    return Err("--cycles-per-frame must be at least 1".to_owned());
+                }
+            }
+            // --profile is the option's old name
+            "--quirks" | "--profile" => options.quirks = Some(value()?.clone()),
+            "--seed" => options.seed = parse_number(arg, value()?)?,
+            "--vip-rng" => options.vip_rng = true,
+            "--press" => options.presses.push(parse_press(value()?)?),
+            "--keys" => {
+                let path = value()?;
+                let script = fs::read_to_string(path)
+                    .map_err(|e| format!("cannot read key script {}: {}", path, e))?;
+                for entry in script
+                    .lines()
+                    .map(|line| line.split('#').next().unwrap_or(""))
+                    .flat_map(str::split_whitespace)
+                {
+                    options.presses.push(parse_press(entry)?);
+                }
+            }
+            "--screen" => options.screen = Some(value()?.clone()),
+            "--record" => options.record = Some(value()?.clone()),
+            "--play" => options.play = Some(value()?.clone()),
+            "--trace" => options.trace = Some(value()?.clone()),
+            "--trace-format" => {
+                let name = value()?;
+                options.trace_format = Format::from_name(name).ok_or_else(|| {
+                    format!("unknown trace format {}, expected native or cadmium", name)
+                })?;
+            }
+            "--gdb" => options.gdb = Some(parse_number(arg, value()?)?),
+            "--scale" => {
+                options.scale = parse_number(arg, value()?)?;
+                if options.scale == 0 {
+                    return Err("--scale must be at least 1".to_owned());
+                }
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if options.rom.is_empty() => options.rom = arg.clone(),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    if options.rom.is_empty() {
+        return Err("no ROM given".to_owned());
+    }
+    if options.record.is_some() && matches!(options.limit, Limit::Cycles(_)) {
+        return Err("--record needs whole frames, use --frames instead of --cycles".to_owned());
+    }
+    if options.record.is_some() && options.play.is_some() {
+        return Err("--record and --play cannot be combined".to_owned());
+    }
+    if options.gdb.is_some()
+        && (options.record.is_some() || options.play.is_some() || options.trace.is_some())
+    {
+        return Err("--gdb cannot be combined with --record, --play or --trace".to_owned());
+    }
+    Ok(options)
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {}", option, value))
+}
+
+/// Parses `KEY@FRAME[:N]`, e.g. `5@30:10` holds key 5 for frames 30 to 39.
+fn parse_press(entry: &str) -> Result<Press, String> {
+    let invalid = || format!("invalid key press {}, expected KEY@FRAME[:N]", entry);
+    let (key, rest) = entry.split_once('@').ok_or_else(invalid)?;
+    let (frame, frames) = match rest.split_once(':') {
+        Some((frame, frames)) => (frame, frames),
+        None => (rest, "1"),
+    };
+    let key = usize::from_str_radix(key, 16).map_err(|_| invalid())?;
+    if key > 0xF {
+        return Err(invalid());
+    }
+    Ok(Press {
+        key,
+        frame: frame.parse().map_err(|_| invalid())?,
+        frames: frames.parse().map_err(|_| invalid())?,
+    })
+}
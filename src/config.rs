@@ -0,0 +1,281 @@
+//! Frontend settings read from a TOML file such as the repository's
+//! `config.toml`.
+//!
+//! Top-level keys apply to every ROM; a `[roms."NAME"]` table overrides them
+//! for the ROM whose file name is `NAME`:
+//!
+//! ```toml
+//! clock_speed = 600          # instructions per second
+//! mode = "normal"            # or "debug" to print every instruction
+//! quirks = "chip8"           # platform or quirks preset, as on the command line
+//! scale = 10                 # window pixels per CHIP-8 pixel in low resolution
+//! mute = false
+//! palette = ["#000000", "#FFFFFF", "#AAAAAA", "#555555"]
+//!
+//! [keys]                     # keypad key = keyboard key name
+//! A = "Z"
+//!
+//! [roms."PONG"]
+//! clock_speed = 400
+//! ```
+//!
+//! Every key is optional. Settings are layered with `Settings::merge`, so the
+//! frontend can put its command-line flags on top.
+
+use crate::{Mode, Platform, Quirks};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Keyboard keys for keypad keys 0-F, using SDL's key names.
+pub const DEFAULT_KEYS: [&str; 16] = [
+    "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+];
+
+/// Background, plane 1, plane 2 and both planes (XO-CHIP).
+pub const DEFAULT_PALETTE: [(u8, u8, u8); 4] =
+    [(0, 0, 0), (255, 255, 255), (170, 170, 170), (85, 85, 85)];
+
+/// Why a configuration file could not be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+fn invalid(section: &str, message: String) -> ConfigError {
+    let message = if section.is_empty() {
+        message
+    } else {
+        format!("[{}]: {}", section, message)
+    };
+    ConfigError { message }
+}
+
+/// One layer of settings; unset keys fall through to the layer below and
+/// finally to the defaults of the accessor methods.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub clock_speed: Option<u32>,
+    pub mode: Option<String>,
+    pub quirks: Option<String>,
+    pub scale: Option<u32>,
+    pub mute: Option<bool>,
+    pub palette: Option<[String; 4]>,
+    /// Keyboard key names by hex keypad key, e.g. `"A" => "Z"`.
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Settings {
+    /// These settings with every key set in `over` replaced by its value.
+    pub fn merge(&self, over: &Settings) -> Settings {
+        let mut keys = self.keys.clone();
+        keys.extend(over.keys.clone());
+        Settings {
+            clock_speed: over.clock_speed.or(self.clock_speed),
+            mode: over.mode.clone().or_else(|| self.mode.clone()),
+            quirks: over.quirks.clone().or_else(|| self.quirks.clone()),
+            scale: over.scale.or(self.scale),
+            mute: over.mute.or(self.mute),
+            palette: over.palette.clone().or_else(|| self.palette.clone()),
+            keys,
+        }
+    }
+
+    /// Instructions per second, 600 by default.
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed.unwrap_or(600)
+    }
+
+    pub fn mode(&self) -> Mode {
+        match self.mode.as_deref() {
+            Some("debug") => Mode::Debug,
+            _ => Mode::Normal,
+        }
+    }
+
+    /// Window pixels per low-resolution pixel, 10 by default.
+    pub fn scale(&self) -> u32 {
+        self.scale.unwrap_or(10)
+    }
+
+    pub fn mute(&self) -> bool {
+        self.mute.unwrap_or(false)
+    }
+
+    pub fn palette(&self) -> [(u8, u8, u8); 4] {
+        match &self.palette {
+            Some(colors) => {
+                let mut palette = DEFAULT_PALETTE;
+                for (slot, color) in palette.iter_mut().zip(colors) {
+                    *slot = parse_color(color).unwrap_or(*slot);
+                }
+                palette
+            }
+            None => DEFAULT_PALETTE,
+        }
+    }
+
+    /// The keyboard key name for each keypad key 0-F.
+    pub fn keys(&self) -> [String; 16] {
+        let mut keys = DEFAULT_KEYS.map(str::to_owned);
+        for (key, name) in &self.keys {
+            if let Ok(k) = usize::from_str_radix(key, 16) {
+                keys[k] = name.clone();
+            }
+        }
+        keys
+    }
+
+    /// Checks values that the file format alone cannot, naming `section` in
+    /// errors.
+    fn validate(&self, section: &str) -> Result<(), ConfigError> {
+        if self.clock_speed == Some(0) {
+            return Err(invalid(
+                section,
+                "clock_speed must be at least 1".to_owned(),
+            ));
+        }
+        if self.scale == Some(0) {
+            return Err(invalid(section, "scale must be at least 1".to_owned()));
+        }
+        if let Some(mode) = &self.mode {
+            if mode != "normal" && mode != "debug" {
+                return Err(invalid(
+                    section,
+                    format!("unknown mode `{}` (expected normal or debug)", mode),
+                ));
+            }
+        }
+        if let Some(name) = &self.quirks {
+            if Platform::from_name(name).is_none() && Quirks::from_name(name).is_none() {
+                return Err(invalid(
+                    section,
+                    format!(
+                        "unknown quirks `{}` (expected chip8, schip, xochip, vip or chip48)",
+                        name
+                    ),
+                ));
+            }
+        }
+        for color in self.palette.iter().flatten() {
+            if parse_color(color).is_none() {
+                return Err(invalid(
+                    section,
+                    format!("invalid color `{}` (expected #RRGGBB)", color),
+                ));
+            }
+        }
+        for key in self.keys.keys() {
+            if key.len() != 1 || usize::from_str_radix(key, 16).is_err() {
+                return Err(invalid(
+                    section,
+                    format!("unknown keypad key `{}` (expected 0-F)", key),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A whole configuration file: global settings and per-ROM overrides.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub global: Settings,
+    /// Overrides by ROM file name.
+    pub roms: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+            let line = e
+                .span()
+                .map_or(1, |span| text[..span.start].matches('\n').count() + 1);
+            ConfigError {
+                message: format!("line {}: {}", line, e.message().replace('\n', ", ")),
+            }
+        })?;
+        let roms = match table.remove("roms") {
+            None => toml::Table::new(),
+            Some(toml::Value::Table(roms)) => roms,
+            Some(_) => return Err(invalid("", "`roms` must be a table".to_owned())),
+        };
+
+        let mut config = Config {
+            global: settings(table, "")?,
+            roms: BTreeMap::new(),
+        };
+        for (name, value) in roms {
+            let section = format!("roms.\"{}\"", name);
+            let table = match value {
+                toml::Value::Table(table) => table,
+                _ => return Err(invalid(&section, "must be a table".to_owned())),
+            };
+            config.roms.insert(name, settings(table, &section)?);
+        }
+        Ok(config)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError {
+            message: format!("cannot read {}: {}", path.display(), e),
+        })?;
+        Config::parse(&text).map_err(|e| ConfigError {
+            message: format!("{}: {}", path.display(), e),
+        })
+    }
+
+    /// The global settings with the overrides for the ROM at `rom` applied.
+    pub fn for_rom(&self, rom: &str) -> Settings {
+        let name = Path::new(rom)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match self.roms.get(&name) {
+            Some(over) => self.global.merge(over),
+            None => self.global.clone(),
+        }
+    }
+}
+
+fn settings(table: toml::Table, section: &str) -> Result<Settings, ConfigError> {
+    // One key at a time first, as errors from a whole table do not say which
+    // key is wrong
+    for (key, value) in &table {
+        let mut single = toml::Table::new();
+        single.insert(key.clone(), value.clone());
+        if let Err(e) = toml::Value::Table(single).try_into::<Settings>() {
+            let message = e.message();
+            return Err(if message.contains(&format!("`{}`", key)) {
+                invalid(section, message.to_owned())
+            } else {
+                invalid(section, format!("`{}`: {}", key, message))
+            });
+        }
+    }
+    let settings: Settings = toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| invalid(section, e.message().to_owned()))?;
+    settings.validate(section)?;
+    Ok(settings)
+}
+
+/// Parses `#RRGGBB`.
+fn parse_color(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
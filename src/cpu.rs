@@ -1,7 +1,22 @@
+mod error;
+mod platform;
+mod quirks;
 mod read;
+mod rng;
+mod state;
+mod watch;
+pub use error::CpuError;
+pub use platform::Platform;
+pub use quirks::{LoadStore, Quirks};
+pub use read::RomError;
+pub use rng::{Rng, RngKind};
+pub use state::{StateError, STATE_MAGIC, STATE_VERSION};
+pub use watch::{Access, WatchAction, WatchHit, Watchpoint};
+pub(crate) use state::{
+    platform_from_id, platform_id, quirks_bits, quirks_from_bits, rng_kind_from_id, rng_kind_id,
+    StateReader,
+};
 use read::Reader;
-use rand;
-use rand::Rng;
 
 
 pub const FONT_SET: [u8; 80] = [ 
@@ -23,397 +38,746 @@ pub const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+/// Where the SUPER-CHIP 8x10 font is stored, right after `FONT_SET`.
+pub const BIG_FONT_ADDR: usize = 0x50;
+
+pub const BIG_FONT_SET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+/// Display size in base CHIP-8 and SUPER-CHIP lores mode.
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+/// Display size in SUPER-CHIP hires mode.
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
 pub struct CPU {
     pub pc : u16,
     pub opcode : u16,
     pub index_register : u16,
     pub sp : u8,
     pub registers : [u8; 16],
-    pub memory : [u8; 4096],
+    /// 4 KB, or 64 KB on XO-CHIP.
+    pub memory : Vec<u8>,
     pub stack : [u16; 16],
-    pub vram : [u8; 64 * 32],
+    /// One byte per pixel, `width * height` long, row-major.
+    pub vram : Vec<u8>,
+    pub width : usize,
+    pub height : usize,
+    pub hires : bool,
     pub key : [u8; 16],
     pub audio_timer : u8,
     pub audio_play : bool,
     pub delay_timer : u8,
     pub draw : bool,
+    pub quirks : Quirks,
+    pub platform : Platform,
+    /// SUPER-CHIP RPL user flags, saved and restored by FX75/FX85.
+    pub rpl : [u8; 16],
+    /// Set once the program executes 00FD; the CPU then stays on that instruction.
+    pub exited : bool,
+    /// XO-CHIP bitplanes selected by FN01; bit 0 is plane 1, bit 1 is plane 2.
+    /// Each `vram` byte holds the planes set for that pixel.
+    pub planes : u8,
+    /// XO-CHIP 1-bit audio pattern loaded by F002.
+    pub audio_pattern : [u8; 16],
+    /// XO-CHIP pitch set by FX3A; 64 plays the pattern at 4000 Hz.
+    pub pitch : u8,
+    /// Size in bytes of the ROM loaded at 0x200.
+    pub rom_size : usize,
+    /// Source of CXNN's random bytes. Seed it for reproducible runs.
+    pub rng : Rng,
+    /// Memory ranges whose accesses are recorded, see `take_watch_hits`.
+    pub watchpoints : Vec<Watchpoint>,
+    watch_hits : Vec<WatchHit>,
     mode: Mode,
     step : u64
 }
+/// Whether the CPU prints every instruction it executes to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode{
     Debug,
     Normal
 }
-pub fn load() -> CPU{
-    let mut cpu = CPU { 
-        pc: 0x200,
-        opcode: 0x0,
-        index_register: 0x0,
-        sp: 0x0,
-        registers : [0x0; 16],
-        memory: [0x0; 4096],
-        stack: [0x0; 16],
-        vram: [0x0; 64 * 32],
-        key: [0x0; 16],
-        audio_timer: 0x0,
-        audio_play: false,
-        delay_timer: 0x0,
-        draw: true,
-        mode: Mode::Normal,
-        step : 0,
-    };
-    for i in 0..80{
-        cpu.memory[i + 0x0] = FONT_SET[i];
+impl CPU{
+    /// Creates a CPU in its power-on state with the font loaded at 0x000.
+    pub fn new() -> CPU{
+        let mut cpu = CPU { 
+            pc: 0x200,
+            opcode: 0x0,
+            index_register: 0x0,
+            sp: 0x0,
+            registers : [0x0; 16],
+            memory: vec![0x0; Platform::Chip8.memory_size()],
+            stack: [0x0; 16],
+            vram: vec![0x0; LORES_WIDTH * LORES_HEIGHT],
+            width: LORES_WIDTH,
+            height: LORES_HEIGHT,
+            hires: false,
+            key: [0x0; 16],
+            audio_timer: 0x0,
+            audio_play: false,
+            delay_timer: 0x0,
+            draw: true,
+            quirks: Quirks::default(),
+            platform: Platform::Chip8,
+            rpl: [0x0; 16],
+            exited: false,
+            planes: 0x1,
+            audio_pattern: [0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00],
+            pitch: 64,
+            rom_size: 0,
+            rng: Rng::default(),
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            mode: Mode::Normal,
+            step : 0,
+        };
+        cpu.memory[..FONT_SET.len()].copy_from_slice(&FONT_SET);
+        cpu.memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SET.len()].copy_from_slice(&BIG_FONT_SET);
+        cpu
     }
-    cpu
-}
-pub fn load_rom(mut cpu: CPU, file: String) -> CPU{
-    let mut reader = Reader::new(file).unwrap();
-    reader.open().unwrap();
-    let mut current_mem = 0x200;
-    for line in reader.ROM.iter(){
-        cpu.memory[current_mem] = *line;
-        current_mem += 1;
+
+    /// Switches to `platform`, enabling its opcodes and adopting its quirks.
+    pub fn set_platform(&mut self, platform: Platform){
+        self.platform = platform;
+        self.quirks = platform.quirks();
+        self.memory.resize(platform.memory_size(), 0x0);
+    }
+
+    /// Selects `Mode::Debug` to print every instruction as it executes.
+    pub fn set_mode(&mut self, mode: Mode){
+        self.mode = mode;
     }
-    cpu
-}
 
+    pub fn mode(&self) -> Mode{
+        self.mode
+    }
 
-pub fn emulate_cycle(mut cpu : CPU) -> CPU{
-    
-    let opcode : u16 = (cpu.memory[cpu.pc as usize] as u16) << 8 | cpu.memory[(cpu.pc + 1) as usize]  as u16;
-    let X = ((opcode & 0x0F00) >> 8) as usize;
-    let Y = ((opcode & 0x00F0) >> 4) as usize;
-    //println!("{:#x?}", opcode);
-    match &cpu.mode{
-        Mode::Debug => println!("DEBUG - Step {} - Current Opcode: {:#x?}\nVX: {}\nVY: {}\nPC: {}\nI: {}\nSP: {}",cpu.step, &opcode,&cpu.registers[((&opcode & 0x0F00) >> 8) as usize], &cpu.registers[((&opcode & 0x00F0) >> 4) as usize], &cpu.pc, &cpu.index_register, &cpu.sp),
-        _ => {}
-    };
-    match opcode & 0xF000{
-        0x0000 => {
-            match opcode & 0x000F{
-                0x0000 => { //0x00E0 Clear screen
-                    for i in 0..2048{
-                        cpu.vram[i] = 0x0;
-                    }
-                    cpu.draw = true;
-                    cpu.pc += 2;    
-                    //panic!("CLS: {:#x?}", opcode & 0x000F)
-                }
-                0x000E => { //0x00EE Return from subroutine
-                    
-                    cpu.sp -= 1;
+    /// Number of instructions executed since power-on.
+    pub fn steps(&self) -> u64{
+        self.step
+    }
 
-                    cpu.pc = cpu.stack[cpu.sp as usize] as u16;
-                    cpu.pc += 2;
-                    //panic!("Leaving stack: {:#x?}", opcode & 0x000F);
-                }              
-                _ => {panic!("Unknown opcode @ 0000: {:#x?}", opcode & 0x000F)}
-            }
+    /// Returns the watchpoint hits recorded since the last call, oldest first.
+    /// Whoever adds watchpoints should drain these regularly, they pile up otherwise.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit>{
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    // Records the accesses to [start, start + len) by the current instruction that fall inside a watchpoint
+    fn watch(&mut self, pc: u16, opcode: u16, access: Access, start: usize, len: usize){
+        if self.watchpoints.is_empty(){
+            return;
         }
-        0x1000 => {
-            cpu.pc = opcode & 0x0FFF;
-            
+        for address in start..start + len{
+            for watchpoint in self.watchpoints.iter().filter(|w| w.covers(address, access)){
+                self.watch_hits.push(WatchHit { pc, opcode, address: address as u16, access, value: self.memory[address], action: watchpoint.action });
+            }
         }
-        0x2000 => {
-            cpu.stack[cpu.sp as usize] = cpu.pc;
-            cpu.sp += 1;
-            cpu.pc = opcode & 0x0FFF;
-            
+    }
+
+    /// Switches between 64x32 and 128x64, clearing the screen.
+    pub fn set_hires(&mut self, hires: bool){
+        self.hires = hires;
+        if hires{
+            self.width = HIRES_WIDTH;
+            self.height = HIRES_HEIGHT;
+        }else{
+            self.width = LORES_WIDTH;
+            self.height = LORES_HEIGHT;
         }
-        0x3000 => {
-            //if vX == NN, skip next instruction
-            if (cpu.registers[X] as u16) == (opcode & 0x00FF){
-                cpu.pc += 4;
-                        
-            }else{
-                cpu.pc += 2
+        self.vram = vec![0x0; self.width * self.height];
+        self.draw = true;
+    }
+
+    /// Shifts the selected planes of the screen by (`dx`, `dy`) pixels, filling with blank pixels.
+    fn scroll(&mut self, dx: isize, dy: isize){
+        let mask = self.planes;
+        let old = self.vram.clone();
+        let (width, height) = (self.width as isize, self.height as isize);
+        for y in 0..height{
+            for x in 0..width{
+                let (sx, sy) = (x - dx, y - dy);
+                let src = if sx >= 0 && sx < width && sy >= 0 && sy < height { old[(sy * width + sx) as usize] } else { 0 };
+                let idx = (y * width + x) as usize;
+                self.vram[idx] = (old[idx] & !mask) | (src & mask);
             }
         }
-        0x4000 => {
-            //if vX != NN, skip next instruction
-            if (cpu.registers[X] as u16) != (opcode & 0x00FF){
-                cpu.pc += 4;
-                
-            }else{
-                cpu.pc += 2
-            }
+    }
+
+    /// Skips the next instruction. On XO-CHIP this steps over all four bytes of F000 NNNN.
+    fn skip_next(&mut self){
+        let next = self.pc as usize + 2;
+        let long = self.platform == Platform::XoChip
+            && next + 1 < self.memory.len()
+            && self.memory[next] == 0xF0
+            && self.memory[next + 1] == 0x00;
+        self.pc = self.pc.wrapping_add(if long { 6 } else { 4 });
+    }
+
+    /// Playback rate in Hz of the XO-CHIP audio pattern at the current pitch.
+    pub fn audio_playback_rate(&self) -> f64{
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    /// Renders `samples` samples of the XO-CHIP audio pattern at `sample_rate`,
+    /// looping the 128-bit pattern at the current pitch.
+    pub fn audio_samples(&self, sample_rate: u32, samples: usize) -> Vec<i16>{
+        let step = self.audio_playback_rate() / sample_rate as f64;
+        (0..samples).map(|i|{
+            let bit = (i as f64 * step) as usize % 128;
+            if self.audio_pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { i16::MAX / 4 } else { -(i16::MAX / 4) }
+        }).collect()
+    }
+
+    /// Copies the ROM at `file` into memory starting at 0x200, returning its size in bytes.
+    ///
+    /// Select the platform first: XO-CHIP's larger memory also allows larger ROMs.
+    pub fn load_rom(&mut self, file: &str) -> Result<usize, RomError>{
+        let reader = Reader::open(file, self.memory.len() - 0x200)?;
+        Ok(self.copy_rom(&reader))
+    }
+
+    /// Copies an in-memory ROM image into memory starting at 0x200, returning its size in bytes.
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<usize, RomError>{
+        let reader = Reader::from_bytes(rom, self.memory.len() - 0x200)?;
+        Ok(self.copy_rom(&reader))
+    }
+
+    fn copy_rom(&mut self, reader: &Reader) -> usize{
+        self.memory[0x200..0x200 + reader.len()].copy_from_slice(&reader.rom);
+        self.rom_size = reader.len();
+        self.rom_size
+    }
+
+    /// Fetches, decodes and executes a single instruction.
+    ///
+    /// If the instruction faults, a `CpuError` describing it is returned and the
+    /// CPU is left untouched, still pointing at the faulting instruction.
+    pub fn step(&mut self) -> Result<(), CpuError>{
+        let pc = self.pc;
+        if self.exited{
+            return Ok(());
         }
-        0x5000 => {
-            if cpu.registers[X] == cpu.registers[Y]{
-                cpu.pc += 4;
-                
+        if pc as usize + 1 >= self.memory.len(){
+            return Err(CpuError::PcOutOfBounds { pc });
+        }
+        let opcode : u16 = (self.memory[pc as usize] as u16) << 8 | self.memory[pc as usize + 1]  as u16;
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        // Bounds-checks the memory range [start, start + len) touched by the current instruction
+        let check_mem = |memory: &[u8], start: usize, len: usize| {
+            if start + len > memory.len(){
+                Err(CpuError::MemoryOutOfBounds { pc, opcode, address: start.max(memory.len()) })
             }else{
-                cpu.pc += 2;
+                Ok(())
             }
+        };
+        //println!("{:#x?}", opcode);
+        if let Mode::Debug = self.mode{
+            //same line format as --trace and the REPL's `trace on`
+            println!("{}", crate::trace::line(self));
         }
-        0x6000 => {
-            cpu.registers[X] = (opcode & 0x00FF) as u8;
-            cpu.pc += 2;
-            
-        }
-        0x7000 => {
-            cpu.registers[X] += (opcode & 0x00FF) as u8;
-            cpu.pc += 2;
-            
-        }
-        0x8000 => {
-           
-            match opcode & 0x000F{
-                    0x0000 =>{ // 0x8X Y0: Sets VX to the value of VY
-                        cpu.registers[X] = cpu.registers[Y];
+        match opcode & 0xF000{
+            0x0000 => {
+                match opcode{
+                    0x00E0 => { //0x00E0 Clear screen
+                        let planes = self.planes;
+                        self.vram.iter_mut().for_each(|pixel| *pixel &= !planes);
+                        self.draw = true;
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x00EE => { //0x00EE Return from subroutine
+                        if self.sp == 0{
+                            return Err(CpuError::StackUnderflow { pc, opcode });
+                        }
+                        self.sp -= 1;
 
-                        cpu.pc += 2; 
+                        self.pc = self.stack[self.sp as usize];
+                        self.pc = self.pc.wrapping_add(2);
+                        //panic!("Leaving stack: {:#x?}", opcode & 0x000F);
+                    }              
+                    _ if self.platform == Platform::Chip8 => return Err(CpuError::UnknownOpcode { pc, opcode }),
+                    0x00C0..=0x00CF => { //0x00CN Scroll down N rows
+                        self.scroll(0, (opcode & 0x000F) as isize);
+                        self.draw = true;
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    0x0001 =>{
-                        cpu.registers[X] |= cpu.registers[Y];                      
-                        cpu.pc += 2;
+                    0x00D0..=0x00DF if self.platform == Platform::XoChip => { //0x00DN Scroll up N rows
+                        self.scroll(0, -((opcode & 0x000F) as isize));
+                        self.draw = true;
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    0x0002 =>{      
-                        cpu.registers[X] &= cpu.registers[Y];
-                        cpu.pc += 2;
-                        
+                    0x00FB => { //0x00FB Scroll right 4 pixels
+                        self.scroll(4, 0);
+                        self.draw = true;
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    0x0003 =>{
-                        cpu.registers[X] ^= cpu.registers[Y];
-                        cpu.pc += 2;
+                    0x00FC => { //0x00FC Scroll left 4 pixels
+                        self.scroll(-4, 0);
+                        self.draw = true;
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    0x0004 =>{
-                        if cpu.registers[Y] > (0xFF - cpu.registers[X]){
-                            cpu.registers[0xF] = 1;
-                        }else{
-                            cpu.registers[0xF] = 0;
-                        }
-                        cpu.registers[X] += cpu.registers[Y];
-                        cpu.pc += 2;                        
+                    0x00FD => { //0x00FD Exit interpreter
+                        self.exited = true;
+                    }
+                    0x00FE => { //0x00FE Lores
+                        self.set_hires(false);
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x00FF => { //0x00FF Hires
+                        self.set_hires(true);
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    0x0005 =>{
-                        //set register to be subtracted values of VX and VY. Use VF as a carry, as each register can only hold 8 bits (max 255). If VF = 1, carry. else, do not.
-                        if cpu.registers[Y] > cpu.registers[X]{
-                            cpu.registers[0xF] = 0;
+                    _ => return Err(CpuError::UnknownOpcode { pc, opcode }),
+                }
+            }
+            0x1000 => {
+                self.pc = opcode & 0x0FFF;
+            
+            }
+            0x2000 => {
+                if self.sp as usize >= self.stack.len(){
+                    return Err(CpuError::StackOverflow { pc, opcode });
+                }
+                self.stack[self.sp as usize] = self.pc;
+                self.sp += 1;
+                self.pc = opcode & 0x0FFF;
+            
+            }
+            0x3000 => {
+                //if vX == NN, skip next instruction
+                if (self.registers[x] as u16) == (opcode & 0x00FF){
+                    self.skip_next();
+                        
+                }else{
+                    self.pc = self.pc.wrapping_add(2)
+                }
+            }
+            0x4000 => {
+                //if vX != NN, skip next instruction
+                if (self.registers[x] as u16) != (opcode & 0x00FF){
+                    self.skip_next();
+                
+                }else{
+                    self.pc = self.pc.wrapping_add(2)
+                }
+            }
+            0x5000 => {
+                match opcode & 0x000F{
+                    0x0000 => {
+                        if self.registers[x] == self.registers[y]{
+                            self.skip_next();
                         }else{
-                            cpu.registers[0xF] = 1;
+                            self.pc = self.pc.wrapping_add(2);
                         }
-                        
-                        cpu.registers[X] -= cpu.registers[Y];
-                       
-                        cpu.pc += 2;
-                        
                     }
-                    0x0006 =>{
-                        
-                        cpu.registers[0xF] = &cpu.registers[X] & 0x1;
-                        cpu.registers[X] >>= 1;
-                        
-                        cpu.pc += 2;
+                    0x0002 if self.platform == Platform::XoChip => {
+                        let range = register_range(x, y);
+                        check_mem(&self.memory, self.index_register as usize, range.len())?;
+                        for (i, r) in range.iter().enumerate(){
+                            self.memory[self.index_register as usize + i] = self.registers[*r];
+                        }
+                        self.watch(pc, opcode, Access::Write, self.index_register as usize, range.len());
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    0x0007 =>{
-                        //set register to be subtracted values of VX and VY. Use VF as a carry, as each register can only hold 8 bits (max 255). If VF = 1, carry. else, do not.
-                        if cpu.registers[X] > cpu.registers[Y]{
-                            cpu.registers[0xF] = 0;
-                        }else{
-                            cpu.registers[0xF] = 1;
+                    0x0003 if self.platform == Platform::XoChip => {
+                        let range = register_range(x, y);
+                        check_mem(&self.memory, self.index_register as usize, range.len())?;
+                        self.watch(pc, opcode, Access::Read, self.index_register as usize, range.len());
+                        for (i, r) in range.iter().enumerate(){
+                            self.registers[*r] = self.memory[self.index_register as usize + i];
                         }
-                        
-                        cpu.registers[X] = cpu.registers[Y] - cpu.registers[X];
-                        cpu.pc += 2;
-                        
-                       
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    0x000E =>{
-                        cpu.registers[0xF] = cpu.registers[X] >> 7;
-                        cpu.registers[X] <<= 1;
-                        cpu.pc += 2;
-                    },
-    
-                    _ => {panic!("Unknown opcode @ 8000: {:#x?}", opcode & 0x00F)}
+                    _ => return Err(CpuError::UnknownOpcode { pc, opcode }),
                 }
             }
-        0x9000 => {
-            if cpu.registers[X] != cpu.registers[Y]{
-				cpu.pc += 4;
-            }else{
-                cpu.pc += 2;
+            0x6000 => {
+                self.registers[x] = (opcode & 0x00FF) as u8;
+                self.pc = self.pc.wrapping_add(2);
+            
             }
-        }
-        0xA000 => {
-            cpu.index_register = (opcode & 0x0FFF) as u16;
-            cpu.pc += 2;
-        }
-        0xB000 => {
-            cpu.pc = (opcode & 0x0FFF) as u16 + cpu.registers[0] as u16;
-        }
-        0xC000 => {
-            cpu.registers[X] = ((rand::thread_rng().gen_range(0, 255) % 0xFF) & (opcode & 0x00FF)) as u8;
-            cpu.pc += 2;
-        }
-        0xD000 => {
-            let sprite_x = cpu.registers[X];
-            let sprite_y = cpu.registers[Y];
-            let sprite_height = opcode & 0x000F;
-
-            cpu.registers[0xF] = 0;// Sets to 1 if there's a collision
-       
-            for y_line in 0..sprite_height{
-                let line = cpu.memory[(cpu.index_register + y_line as u16) as usize];
-                for x_line in 0..8{
-                    let pixel = line & (0x80 >> x_line);
-                    if pixel != 0{
-
-                        
-
-                       
-                        
-                        let idx = ((sprite_x as u16 + x_line as u16 + ((sprite_y as u16 + y_line) * 64)) % 2048) as usize;
+            0x7000 => {
+                self.registers[x] = self.registers[x].wrapping_add((opcode & 0x00FF) as u8);
+                self.pc = self.pc.wrapping_add(2);
+            
+            }
+            0x8000 => {
+           
+                match opcode & 0x000F{
+                        0x0000 =>{ // 0x8X Y0: Sets VX to the value of VY
+                            self.registers[x] = self.registers[y];
 
-                        
-                        if cpu.vram[idx] != 0{
-                            cpu.registers[0xF] = 1;
+                            self.pc = self.pc.wrapping_add(2); 
                         }
-                        
-                        cpu.vram[idx] ^= 1;
-                        
-
-                        
-                        //println!("old: {}\ncol: {}", current_pixel, cpu.V[0xF]);
-                        //let pos_pixel = ((y as u16 + y_line) * 64 + (x as u16 + x_line as u16)) as usize;
-                        /*if (y as u16 + y_line < 32) && (x as u16 + (x_line as u16) < 64){
-                            cpu.vram[((y as u16 + y_line) * 64 + (x as u16 + x_line as u16)) as usize] ^= 1;
+                        0x0001 =>{
+                            self.registers[x] |= self.registers[y];
+                            if self.quirks.vf_reset{
+                                self.registers[0xF] = 0;
+                            }
+                            self.pc = self.pc.wrapping_add(2);
                         }
-                        
+                        0x0002 =>{
+                            self.registers[x] &= self.registers[y];
+                            if self.quirks.vf_reset{
+                                self.registers[0xF] = 0;
+                            }
+                            self.pc = self.pc.wrapping_add(2);
+                        }
+                        0x0003 =>{
+                            self.registers[x] ^= self.registers[y];
+                            if self.quirks.vf_reset{
+                                self.registers[0xF] = 0;
+                            }
+                            self.pc = self.pc.wrapping_add(2);
+                        }
+                        0x0004 =>{
+                            //VF is written after the result, so it holds the carry even when X is F
+                            let (sum, carry) = self.registers[x].overflowing_add(self.registers[y]);
+                            self.registers[x] = sum;
+                            self.registers[0xF] = carry as u8;
+                            self.pc = self.pc.wrapping_add(2);
+                        }
+                        0x0005 =>{
+                            //set register to be subtracted values of VX and VY. VF is 0 on a borrow and 1 otherwise, written after the result
+                            let (diff, borrow) = self.registers[x].overflowing_sub(self.registers[y]);
+                            self.registers[x] = diff;
+                            self.registers[0xF] = !borrow as u8;
+                            self.pc = self.pc.wrapping_add(2);
+                        }
+                        0x0006 =>{
+                            let value = if self.quirks.shift { self.registers[x] } else { self.registers[y] };
+                            self.registers[x] = value >> 1;
+                            self.registers[0xF] = value & 0x1;
+                            self.pc = self.pc.wrapping_add(2);
+                        }
+                        0x0007 =>{
+                            //set register to be VY minus VX. VF is 0 on a borrow and 1 otherwise, written after the result
+                            let (diff, borrow) = self.registers[y].overflowing_sub(self.registers[x]);
+                            self.registers[x] = diff;
+                            self.registers[0xF] = !borrow as u8;
+                            self.pc = self.pc.wrapping_add(2);
+                        }
+                        0x000E =>{
+                            let value = if self.quirks.shift { self.registers[x] } else { self.registers[y] };
+                            self.registers[x] = value << 1;
+                            self.registers[0xF] = value >> 7;
+                            self.pc = self.pc.wrapping_add(2);
+                        },
+    
+                        _ => return Err(CpuError::UnknownOpcode { pc, opcode }),
+                    }
+                }
+            0x9000 => {
+                if opcode & 0x000F != 0{
+                    return Err(CpuError::UnknownOpcode { pc, opcode });
+                }
+                if self.registers[x] != self.registers[y]{
+    				self.skip_next();
+                }else{
+                    self.pc = self.pc.wrapping_add(2);
+                }
+            }
+            0xA000 => {
+                self.index_register = opcode & 0x0FFF;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            0xB000 => {
+                let offset = if self.quirks.jump { self.registers[x] } else { self.registers[0] };
+                self.pc = (opcode & 0x0FFF) + offset as u16;
+            }
+            0xC000 => {
+                self.registers[x] = self.rng.next_byte(&self.memory) & (opcode & 0x00FF) as u8;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            0xD000 => {
+                let n = (opcode & 0x000F) as usize;
+                // SUPER-CHIP draws a 16x16 sprite, two bytes per row, for DXY0
+                let (sprite_width, sprite_height) = if n == 0 && self.platform != Platform::Chip8 { (16, 16) } else { (8, n) };
+                let row_bytes = sprite_width / 8;
+                let sprite_bytes = sprite_height * row_bytes * self.planes.count_ones() as usize;
+                check_mem(&self.memory, self.index_register as usize, sprite_bytes)?;
+                self.watch(pc, opcode, Access::Read, self.index_register as usize, sprite_bytes);
 
-                        let old_pixel_value = cpu.vram[pos_pixel % 2048];
-                        
+                // the starting position always wraps; pixels past the edge are clipped or wrapped per quirks
+                let sprite_x = self.registers[x] as usize % self.width;
+                let sprite_y = self.registers[y] as usize % self.height;
 
+                self.registers[0xF] = 0;// Sets to 1 if there's a collision
 
-                        if cpu.vram[pos_pixel % 2048] == 1 && old_pixel_value != cpu.vram[pos_pixel % 2048]{
-                            cpu.V[0xF] = 1;
-                            
-                        }*/
-                        
-                        
-                    }
-                    
+                // XO-CHIP reads one copy of the sprite per selected plane, back to back
+                let planes = self.planes;
+                let mut data = self.index_register as usize;
+                for plane in [0x1u8, 0x2].iter().copied().filter(|&p| planes & p != 0){
+                    for y_line in 0..sprite_height{
+                        let addr = data + y_line * row_bytes;
+                        let line = if row_bytes == 2{
+                            (self.memory[addr] as u16) << 8 | self.memory[addr + 1] as u16
+                        }else{
+                            (self.memory[addr] as u16) << 8
+                        };
+                        for x_line in 0..sprite_width{
+                            let pixel = line & (0x8000 >> x_line);
+                            if pixel != 0{
+                                let mut px = sprite_x + x_line;
+                                let mut py = sprite_y + y_line;
+                                if px >= self.width || py >= self.height{
+                                    if self.quirks.clipping{
+                                        continue;
+                                    }
+                                    px %= self.width;
+                                    py %= self.height;
+                                }
+                                let idx = py * self.width + px;
+
+                                if self.vram[idx] & plane != 0{
+                                    self.registers[0xF] = 1;
+                                }
 
+                                self.vram[idx] ^= plane;
+                            }
+                        }
+                    }
+                    data += sprite_height * row_bytes;
                 }
+                self.draw = true;
+                self.pc = self.pc.wrapping_add(2);
             }
-            cpu.draw = true;
-            cpu.pc += 2;
-        }
-        0xE000 => {
-            match opcode & 0x00FF{
-                0x009E => {
-                    if cpu.key[cpu.registers[X] as usize] != 0{
-                        cpu.pc += 4;
-                    }else{
-                        cpu.pc += 2;
+            0xE000 => {
+                match opcode & 0x00FF{
+                    0x009E => {
+                        if self.key[(self.registers[x] & 0xF) as usize] != 0{
+                            self.skip_next();
+                        }else{
+                            self.pc = self.pc.wrapping_add(2);
+                        }
                     }
-                }
-                0x00A1 => {
-                    if cpu.key[cpu.registers[X] as usize] == 0{
-                        cpu.pc += 4;
-                    }else{
-                        cpu.pc += 2;
+                    0x00A1 => {
+                        if self.key[(self.registers[x] & 0xF) as usize] == 0{
+                            self.skip_next();
+                        }else{
+                            self.pc = self.pc.wrapping_add(2);
+                        }
                     }
+                    _ => return Err(CpuError::UnknownOpcode { pc, opcode }),
                 }
-                _ => {panic!("Unknown opcode @ E000: {:#x?}", opcode & 0x00FF)}
             }
-        }
-        0xF000 => {
-            match opcode & 0x00FF{
-                0x0007 => {
-                    cpu.registers[X] =  cpu.delay_timer;
-                    cpu.pc += 2;
-                }
-                0x000A => {
-                    match &cpu.mode{
-                        Mode::Debug => println!("Waiting for keypress..."),
-                        _ => {}
-                    };
-                    let mut key_pressed = false;
+            0xF000 => {
+                match opcode & 0x00FF{
+                    0x0000 if self.platform == Platform::XoChip && x == 0 => { //0xF000 NNNN Load 16-bit I
+                        check_mem(&self.memory, pc as usize + 2, 2)?;
+                        self.index_register = (self.memory[pc as usize + 2] as u16) << 8 | self.memory[pc as usize + 3] as u16;
+                        self.pc = self.pc.wrapping_add(4);
+                    }
+                    0x0001 if self.platform == Platform::XoChip => { //0xFN01 Select bitplanes
+                        self.planes = x as u8 & 0x3;
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x0002 if self.platform == Platform::XoChip && x == 0 => { //0xF002 Load audio pattern
+                        check_mem(&self.memory, self.index_register as usize, 16)?;
+                        let start = self.index_register as usize;
+                        self.watch(pc, opcode, Access::Read, start, 16);
+                        self.audio_pattern.copy_from_slice(&self.memory[start..start + 16]);
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x003A if self.platform == Platform::XoChip => { //0xFX3A Set audio pitch
+                        self.pitch = self.registers[x];
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x0007 => {
+                        self.registers[x] =  self.delay_timer;
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x000A => {
+                        if let Mode::Debug = self.mode{
+                            println!("Waiting for keypress...");
+                        }
+                        let mut key_pressed = false;
                     
-                    for i in 0..16{
-                        if cpu.key[i] != 0{
-                            key_pressed = true;
-                            cpu.registers[X] =  i as u8;
+                        for i in 0..16{
+                            if self.key[i] != 0{
+                                key_pressed = true;
+                                self.registers[x] =  i as u8;
+                            }
+                        }
+                        if key_pressed{
+                            self.pc = self.pc.wrapping_add(2);
                         }
+                    
+                    }    
+                    0x0015 => {
+                        self.delay_timer = self.registers[x];
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    if key_pressed{
-                        cpu.pc += 2;
+                    0x0018 => {
+                        self.audio_timer = self.registers[x];
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    
-                }    
-                0x0015 => {
-                    cpu.delay_timer = cpu.registers[X];
-                    cpu.pc += 2;
-                }
-                0x0018 => {
-                    cpu.audio_timer = cpu.registers[X];
-                    cpu.pc += 2;
-                }
-                0x001E => {
-                    if cpu.index_register + cpu.registers[X] as u16 > 0x0FFF{
-                        cpu.registers[0xF] = 1;
-                    }else{
-                        cpu.registers[0xF] = 0;
+                    0x001E => {
+                        let sum = self.index_register.wrapping_add(self.registers[x] as u16);
+                        self.index_register = sum;
+                        self.registers[0xF] = (sum > 0x0FFF) as u8;
+                        self.pc = self.pc.wrapping_add(2);
                     }
-                    cpu.index_register += cpu.registers[X] as u16;
-                    cpu.pc += 2;
-                }
-                0x0029 => {
-                    cpu.index_register = 0x0 + (cpu.registers[X] * 0x5) as u16;
-                    cpu.pc += 2;
-                }
-                0x0033 => {
-                    cpu.memory[cpu.index_register as usize]     =  cpu.registers[X] / 100;
-					cpu.memory[cpu.index_register as usize + 1] = (cpu.registers[X] / 10) % 10;
-					cpu.memory[cpu.index_register as usize + 2] = (cpu.registers[X] % 100) % 10;
-                    cpu.pc += 2;
+                    0x0029 => {
+                        self.index_register = (self.registers[x] & 0xF) as u16 * 5;
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x0030 if self.platform != Platform::Chip8 => {
+                        self.index_register = (BIG_FONT_ADDR + (self.registers[x] & 0xF) as usize * 10) as u16;
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x0033 => {
+                        check_mem(&self.memory, self.index_register as usize, 3)?;
+                        self.memory[self.index_register as usize]     =  self.registers[x] / 100;
+    					self.memory[self.index_register as usize + 1] = (self.registers[x] / 10) % 10;
+    					self.memory[self.index_register as usize + 2] = (self.registers[x] % 100) % 10;
+                        self.watch(pc, opcode, Access::Write, self.index_register as usize, 3);
+                        self.pc = self.pc.wrapping_add(2);
                  
-                }
-                0x0055 => {
-                    for i in 0..X + 1{
-                        cpu.memory[cpu.index_register as usize + i] = cpu.registers[i];
                     }
+                    0x0055 => {
+                        check_mem(&self.memory, self.index_register as usize, x + 1)?;
+                        for i in 0..x + 1{
+                            self.memory[self.index_register as usize + i] = self.registers[i];
+                        }
+                        self.watch(pc, opcode, Access::Write, self.index_register as usize, x + 1);
 
-                    cpu.index_register += X as u16 + 1;
-                    cpu.pc += 2;
-                }
-                0x0065 => {
-                    for i in 0..X + 1{
-                        cpu.registers[i] = cpu.memory[cpu.index_register as usize + i];
+                        self.advance_index_after_load_store(x);
+                        self.pc = self.pc.wrapping_add(2);
                     }
+                    0x0065 => {
+                        check_mem(&self.memory, self.index_register as usize, x + 1)?;
+                        self.watch(pc, opcode, Access::Read, self.index_register as usize, x + 1);
+                        for i in 0..x + 1{
+                            self.registers[i] = self.memory[self.index_register as usize + i];
+                        }
 
-                    cpu.index_register += X as u16 + 1;
-                    cpu.pc += 2;
+                        self.advance_index_after_load_store(x);
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x0075 if self.platform != Platform::Chip8 => {
+                        self.rpl[..=x].copy_from_slice(&self.registers[..=x]);
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    0x0085 if self.platform != Platform::Chip8 => {
+                        self.registers[..=x].copy_from_slice(&self.rpl[..=x]);
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                    _ => return Err(CpuError::UnknownOpcode { pc, opcode }),
                 }
-                _ => {panic!("Unknown opcode @ 0xF000: {:#x?}", opcode & 0x00FF)}
-            }
     
 
+            }
+            _ => return Err(CpuError::UnknownOpcode { pc, opcode }),
+
+
+        }
+
+        self.step += 1;
+        Ok(())
+    }
+
+    fn advance_index_after_load_store(&mut self, x: usize){
+        match self.quirks.load_store{
+            LoadStore::Increment => self.index_register = self.index_register.wrapping_add(x as u16 + 1),
+            LoadStore::IncrementByX => self.index_register = self.index_register.wrapping_add(x as u16),
+            LoadStore::Unchanged => {}
         }
-        _ => {panic!("Unknown opcode @ main: {:#x?}", opcode & 0xF0FF)}
+    }
 
+    /// Decrements the delay and sound timers. Call this at 60 Hz, independently
+    /// of how many instructions are executed per second.
+    pub fn tick_timers(&mut self){
+        if self.delay_timer > 0{
+            self.delay_timer -= 1;
+        }
+        if self.audio_timer > 0{
+            if self.audio_timer == 1{
+                self.audio_play = true;
+            }
+            self.audio_timer -= 1;
+        }
+    }
 
+    /// Runs one 60 Hz frame: `cycles_per_frame` instructions followed by a
+    /// single timer tick. Stops at the first fault, without ticking the timers.
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> Result<(), CpuError>{
+        for _ in 0..cycles_per_frame{
+            self.step()?;
+        }
+        self.tick_timers();
+        Ok(())
     }
 
-    cpu.step += 1;
+    /// 64-bit FNV-1a hash of the whole of memory, for comparing runs without
+    /// dumping 4 KB (or 64 KB) of bytes.
+    pub fn memory_hash(&self) -> u64{
+        fnv1a(&self.memory)
+    }
 
-    if cpu.step % 2 == 0{
-        if cpu.delay_timer > 0{
-            cpu.delay_timer -= 1;
-        } 
+    /// 64-bit FNV-1a hash of `save_state`, covering everything that affects
+    /// how the program continues. Two runs that end with the same hash are
+    /// indistinguishable.
+    pub fn state_hash(&self) -> u64{
+        fnv1a(&self.save_state())
     }
+}
 
-    
+/// 64-bit FNV-1a, a fast hash that is stable across platforms and releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64{
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
 
-    if cpu.audio_timer > 0{
-        
-        if cpu.audio_timer == 1{
-            cpu.audio_play = true;
-        }
-        cpu.audio_timer -= 1;
+impl Default for CPU{
+    fn default() -> Self{
+        CPU::new()
+    }
+}
 
+/// Registers VX..VY as used by XO-CHIP 5XY2/5XY3, which may run in either direction.
+fn register_range(x: usize, y: usize) -> Vec<usize>{
+    if x <= y { (x..=y).collect() } else { (y..=x).rev().collect() }
+}
+
+/// Creates a CPU in its power-on state. Equivalent to `CPU::new()`.
+pub fn load() -> CPU{
+    CPU::new()
+}
+
+/// Loads a ROM into `cpu`, taking and returning it by value. Prefer `CPU::load_rom`.
+///
+/// # Panics
+///
+/// Panics if the ROM cannot be loaded; `CPU::load_rom` returns the error instead.
+pub fn load_rom(mut cpu: CPU, file: String) -> CPU{
+    if let Err(e) = cpu.load_rom(&file){
+        panic!("Error loading ROM: {}", e);
     }
     cpu
 }
+
+/// Executes a single instruction. Equivalent to `CPU::step`.
+pub fn emulate_cycle(cpu : &mut CPU) -> Result<(), CpuError>{
+    cpu.step()
+}
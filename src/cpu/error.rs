@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fmt;
+
+/// A fault raised by `emulate_cycle` when the program does something the
+/// interpreter cannot execute. The CPU is left exactly as it was before the
+/// faulting instruction, so frontends can halt and inspect it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// The fetched opcode does not decode to any instruction.
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// 2NNN was executed with all 16 stack slots in use.
+    StackOverflow { pc: u16, opcode: u16 },
+    /// 00EE was executed with an empty stack.
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// The instruction tried to read or write memory past the end of RAM.
+    MemoryOutOfBounds { pc: u16, opcode: u16, address: usize },
+    /// The program counter points outside of RAM, so no opcode could be fetched.
+    PcOutOfBounds { pc: u16 },
+}
+
+impl CpuError {
+    /// Address of the instruction that faulted.
+    pub fn pc(&self) -> u16 {
+        match *self {
+            CpuError::UnknownOpcode { pc, .. }
+            | CpuError::StackOverflow { pc, .. }
+            | CpuError::StackUnderflow { pc, .. }
+            | CpuError::MemoryOutOfBounds { pc, .. }
+            | CpuError::PcOutOfBounds { pc } => pc,
+        }
+    }
+
+    /// The faulting opcode, if one could be fetched.
+    pub fn opcode(&self) -> Option<u16> {
+        match *self {
+            CpuError::UnknownOpcode { opcode, .. }
+            | CpuError::StackOverflow { opcode, .. }
+            | CpuError::StackUnderflow { opcode, .. }
+            | CpuError::MemoryOutOfBounds { opcode, .. } => Some(opcode),
+            CpuError::PcOutOfBounds { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:#05X}", opcode, pc)
+            }
+            CpuError::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow executing {:04X} at {:#05X}", opcode, pc)
+            }
+            CpuError::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow executing {:04X} at {:#05X}", opcode, pc)
+            }
+            CpuError::MemoryOutOfBounds { pc, opcode, address } => write!(
+                f,
+                "memory access at {:#X} out of bounds executing {:04X} at {:#05X}",
+                address, opcode, pc
+            ),
+            CpuError::PcOutOfBounds { pc } => write!(f, "program counter {:#X} out of bounds", pc),
+        }
+    }
+}
+
+impl Error for CpuError {}
@@ -0,0 +1,43 @@
+use super::Quirks;
+
+/// The CHIP-8 dialect the interpreter runs. Each platform enables its own
+/// extra opcodes on top of the base instruction set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// The original 64x32 CHIP-8.
+    Chip8,
+    /// SUPER-CHIP 1.1: 128x64 hires mode, scrolling, 16x16 sprites, big font and RPL flags.
+    SuperChip,
+    /// XO-CHIP: 64 KB of memory, two bitplanes, long I loads, register ranges and audio patterns.
+    /// Includes the SUPER-CHIP extensions.
+    XoChip,
+}
+
+impl Platform {
+    /// The quirks the platform's reference interpreter had.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::super_chip(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    /// Bytes of addressable memory.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    /// Looks up a platform by name: `chip8`, `schip` or `xochip`.
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Some(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Platform::SuperChip),
+            "xochip" | "xo-chip" | "octo" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+}
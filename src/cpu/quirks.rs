@@ -0,0 +1,101 @@
+/// How FX55/FX65 leave the index register after storing or loading registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStore {
+    /// I is advanced past the last register, to I + X + 1 (COSMAC VIP).
+    Increment,
+    /// I is advanced to I + X (CHIP-48).
+    IncrementByX,
+    /// I is left unchanged (SUPER-CHIP).
+    Unchanged,
+}
+
+/// Behaviour of the opcodes that CHIP-8 interpreters historically disagree on.
+///
+/// Different ROMs were written against different interpreters, so pick the
+/// preset matching the platform a game was made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VX in place instead of shifting VY into VX.
+    pub shift: bool,
+    /// What FX55/FX65 do to I.
+    pub load_store: LoadStore,
+    /// BNNN jumps to XNN + VX instead of NNN + V0.
+    pub jump: bool,
+    /// DXYN clips sprites at the screen edges instead of wrapping them around.
+    pub clipping: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: LoadStore::Increment,
+            jump: false,
+            clipping: true,
+            vf_reset: true,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift: true,
+            load_store: LoadStore::IncrementByX,
+            jump: true,
+            clipping: true,
+            vf_reset: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1.
+    pub fn super_chip() -> Quirks {
+        Quirks {
+            shift: true,
+            load_store: LoadStore::Unchanged,
+            jump: true,
+            clipping: true,
+            vf_reset: false,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xo_chip() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: LoadStore::Increment,
+            jump: false,
+            clipping: false,
+            vf_reset: false,
+        }
+    }
+
+    /// Looks up a preset by name: `vip`, `chip48`, `schip` or `xochip`. `default` selects
+    /// this interpreter's original behaviour.
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac" | "cosmac-vip" => Some(Quirks::cosmac_vip()),
+            "chip48" | "chip-48" => Some(Quirks::chip48()),
+            "schip" | "superchip" | "super-chip" => Some(Quirks::super_chip()),
+            "xochip" | "xo-chip" => Some(Quirks::xo_chip()),
+            "default" => Some(Quirks::default()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    /// The behaviour this interpreter has always had: shifts act on VX, FX55/FX65
+    /// increment I, BNNN uses V0, sprites wrap and VF is left alone by 8XY1-3.
+    fn default() -> Quirks {
+        Quirks {
+            shift: true,
+            load_store: LoadStore::Increment,
+            jump: false,
+            clipping: false,
+            vf_reset: false,
+        }
+    }
+}
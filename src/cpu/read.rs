@@ -1,41 +1,71 @@
-use std::fs::File;
-use std::io::Read;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
 
-pub struct Reader{
-    file : String,
-    pub ROM : [u8; 3584]
+/// Why a ROM could not be loaded.
+#[derive(Debug)]
+pub enum RomError {
+    /// No file exists at the given path.
+    NotFound(String),
+    /// The file exists but could not be read.
+    Io(String, io::Error),
+    /// The ROM contains no bytes.
+    Empty,
+    /// The ROM does not fit in memory above 0x200.
+    TooLarge { size: usize, max: usize },
 }
 
-impl Reader{
-    pub fn new(path : String) -> Result<Self, &'static str>{
-        
-        let reader = Reader { file : path.to_owned(), ROM: [0x0; 3584]};    
-        Ok(reader)
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::NotFound(path) => write!(f, "ROM file not found: {}", path),
+            RomError::Io(path, e) => write!(f, "error reading ROM {}: {}", path, e),
+            RomError::Empty => write!(f, "ROM is empty"),
+            RomError::TooLarge { size, max } => {
+                write!(f, "max ROM size is {} bytes, but ROM loaded was {} bytes", max, size)
+            }
+        }
+    }
+}
+
+impl Error for RomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RomError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
+/// Reads a ROM image and checks that it fits in the memory it will be loaded into.
+pub struct Reader {
+    pub rom: Vec<u8>,
+}
 
+impl Reader {
+    /// Reads the whole file at `path`, rejecting it if it is empty or longer than `max_size` bytes.
+    pub fn open(path: &str, max_size: usize) -> Result<Self, RomError> {
+        let bytes = fs::read(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => RomError::NotFound(path.to_owned()),
+            _ => RomError::Io(path.to_owned(), e),
+        })?;
+        Reader::from_bytes(&bytes, max_size)
     }
-    pub fn open(&mut self) -> Result<(), &'static str>{
-        let mut file = File::open(&self.file).expect("Error opening file");
-        let mut buffer = [0u8; 3584];
-        let mut len = 0;
-        let _bytes_read = if let Ok(bytes_read) = file.read(&mut buffer) {
-            len += 1;
-            bytes_read
-        } else {
-            0
-        };
-        let mut mem = 0;
-        for _ in buffer.iter(){
-            mem += 1;
+
+    /// Wraps an in-memory ROM image, applying the same checks as `open`.
+    pub fn from_bytes(bytes: &[u8], max_size: usize) -> Result<Self, RomError> {
+        if bytes.is_empty() {
+            return Err(RomError::Empty);
         }
-        if mem > 4096 - 512{
-            panic!("Error! Max file size is {} bytes, but ROM loaded was {} bytes", 4096 - 512, len)
+        if bytes.len() > max_size {
+            return Err(RomError::TooLarge { size: bytes.len(), max: max_size });
         }
-        self.ROM = buffer;
-        
-        println!("• Loaded ROM: {}", self.file);
-       
-        Ok(())
+        Ok(Reader { rom: bytes.to_vec() })
     }
-    
-}
\ No newline at end of file
+
+    /// The actual size of the ROM in bytes.
+    pub fn len(&self) -> usize {
+        self.rom.len()
+    }
+}
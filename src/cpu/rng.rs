@@ -0,0 +1,80 @@
+/// Which algorithm CXNN draws its random bytes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngKind {
+    /// SplitMix64: uniform over 0..=255 with no short cycles.
+    SplitMix,
+    /// Modelled on the COSMAC VIP interpreter, which kept a 16-bit seed in R9:
+    /// each call increments the low byte, reads the interpreter byte it points
+    /// at, adds the high byte and keeps the sum as the new high byte. This
+    /// interpreter has no VIP ROM, so the byte comes from page 0 where the fonts
+    /// live; the sequence has the VIP's character rather than its exact values.
+    CosmacVip,
+}
+
+/// The random number generator owned by the CPU. It is fully determined by its
+/// seed, so runs with the same seed and input are reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    kind: RngKind,
+    state: u64,
+}
+
+impl Rng {
+    /// A SplitMix64 generator starting from `seed`.
+    pub fn new(seed: u64) -> Rng {
+        Rng {
+            kind: RngKind::SplitMix,
+            state: seed,
+        }
+    }
+
+    /// A COSMAC VIP style generator; only the low 16 bits of `seed` are used.
+    pub fn cosmac_vip(seed: u64) -> Rng {
+        Rng {
+            kind: RngKind::CosmacVip,
+            state: seed & 0xFFFF,
+        }
+    }
+
+    /// Restores a generator from `kind` and the value returned by `state`.
+    pub fn from_state(kind: RngKind, state: u64) -> Rng {
+        Rng { kind, state }
+    }
+
+    pub fn kind(&self) -> RngKind {
+        self.kind
+    }
+
+    /// The generator's internal state, which together with `kind` determines
+    /// every byte it will produce.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Produces the next random byte. `memory` is only read by `RngKind::CosmacVip`.
+    pub fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        match self.kind {
+            RngKind::SplitMix => {
+                self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                let mut z = self.state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                (z ^ (z >> 31)) as u8
+            }
+            RngKind::CosmacVip => {
+                let low = (self.state as u8).wrapping_add(1);
+                let high = (self.state >> 8) as u8;
+                let value = memory[low as usize].wrapping_add(high);
+                self.state = (value as u64) << 8 | low as u64;
+                value
+            }
+        }
+    }
+}
+
+impl Default for Rng {
+    /// SplitMix64 seeded with 0, so a fresh CPU is deterministic.
+    fn default() -> Rng {
+        Rng::new(0)
+    }
+}
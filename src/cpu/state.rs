@@ -0,0 +1,258 @@
+use super::{LoadStore, Mode, Platform, Quirks, Rng, RngKind, CPU};
+use std::error::Error;
+use std::fmt;
+
+/// First bytes of every save state.
+pub const STATE_MAGIC: [u8; 4] = *b"CH8S";
+/// Version written by `CPU::save_state`. Bump it whenever the layout changes.
+///
+/// Version 2 added the RNG after `rom_size`; version 1 states still load, with
+/// the RNG reset to its default.
+pub const STATE_VERSION: u16 = 2;
+
+/// Why a save state could not be restored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with `STATE_MAGIC`, so it is not a save state.
+    BadMagic,
+    /// The state was written by a newer or unknown version of the format.
+    UnsupportedVersion(u16),
+    /// The data ends before the state does.
+    Truncated,
+    /// A field holds a value no CPU could be in.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+impl CPU {
+    /// Serializes the complete machine state.
+    ///
+    /// The format is `STATE_MAGIC`, a little-endian `u16` version and then every
+    /// field of the CPU in a fixed order, with memory and vram prefixed by their size.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.memory.len() + self.vram.len() + 256);
+        out.extend_from_slice(&STATE_MAGIC);
+        out.extend_from_slice(&STATE_VERSION.to_le_bytes());
+
+        out.push(platform_id(self.platform));
+        out.extend_from_slice(&quirks_bits(self.quirks).to_le_bytes());
+        out.push(matches!(self.mode, Mode::Debug) as u8);
+        out.extend_from_slice(&self.step.to_le_bytes());
+
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.opcode.to_le_bytes());
+        out.extend_from_slice(&self.index_register.to_le_bytes());
+        out.push(self.sp);
+        out.extend_from_slice(&self.registers);
+        for slot in &self.stack {
+            out.extend_from_slice(&slot.to_le_bytes());
+        }
+        out.extend_from_slice(&self.key);
+        out.push(self.delay_timer);
+        out.push(self.audio_timer);
+        out.push(self.audio_play as u8);
+        out.push(self.draw as u8);
+        out.push(self.exited as u8);
+        out.extend_from_slice(&self.rpl);
+        out.push(self.planes);
+        out.extend_from_slice(&self.audio_pattern);
+        out.push(self.pitch);
+        out.extend_from_slice(&(self.rom_size as u32).to_le_bytes());
+        out.push(rng_kind_id(self.rng.kind()));
+        out.extend_from_slice(&self.rng.state().to_le_bytes());
+
+        out.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.memory);
+        out.push(self.hires as u8);
+        out.extend_from_slice(&self.vram);
+        out
+    }
+
+    /// Restores a state written by `save_state`. Watchpoints are not part of the
+    /// state and stay as they are. On error the CPU is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data);
+        if r.bytes(4)? != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version == 0 || version > STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let mut cpu = CPU::new();
+        cpu.platform = platform_from_id(r.u8()?)?;
+        cpu.quirks = quirks_from_bits(r.u16()?)?;
+        cpu.mode = if r.flag()? { Mode::Debug } else { Mode::Normal };
+        cpu.step = r.u64()?;
+
+        cpu.pc = r.u16()?;
+        cpu.opcode = r.u16()?;
+        cpu.index_register = r.u16()?;
+        cpu.sp = r.u8()?;
+        if cpu.sp as usize > cpu.stack.len() {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        cpu.registers.copy_from_slice(r.bytes(16)?);
+        for slot in cpu.stack.iter_mut() {
+            *slot = r.u16()?;
+        }
+        cpu.key.copy_from_slice(r.bytes(16)?);
+        cpu.delay_timer = r.u8()?;
+        cpu.audio_timer = r.u8()?;
+        cpu.audio_play = r.flag()?;
+        cpu.draw = r.flag()?;
+        cpu.exited = r.flag()?;
+        cpu.rpl.copy_from_slice(r.bytes(16)?);
+        cpu.planes = r.u8()?;
+        cpu.audio_pattern.copy_from_slice(r.bytes(16)?);
+        cpu.pitch = r.u8()?;
+        cpu.rom_size = r.u32()? as usize;
+        if version >= 2 {
+            let kind = rng_kind_from_id(r.u8()?)?;
+            cpu.rng = Rng::from_state(kind, r.u64()?);
+        }
+
+        let memory_size = r.u32()? as usize;
+        if memory_size != cpu.platform.memory_size() {
+            return Err(StateError::Invalid("memory size"));
+        }
+        cpu.memory = r.bytes(memory_size)?.to_vec();
+        cpu.set_hires(r.flag()?);
+        cpu.vram = r.bytes(cpu.width * cpu.height)?.to_vec();
+        if !r.is_empty() {
+            return Err(StateError::Invalid("length"));
+        }
+
+        cpu.watchpoints = std::mem::take(&mut self.watchpoints);
+        *self = cpu;
+        Ok(())
+    }
+}
+
+pub(crate) fn platform_id(platform: Platform) -> u8 {
+    match platform {
+        Platform::Chip8 => 0,
+        Platform::SuperChip => 1,
+        Platform::XoChip => 2,
+    }
+}
+
+pub(crate) fn platform_from_id(id: u8) -> Result<Platform, StateError> {
+    match id {
+        0 => Ok(Platform::Chip8),
+        1 => Ok(Platform::SuperChip),
+        2 => Ok(Platform::XoChip),
+        _ => Err(StateError::Invalid("platform")),
+    }
+}
+
+pub(crate) fn rng_kind_id(kind: RngKind) -> u8 {
+    match kind {
+        RngKind::SplitMix => 0,
+        RngKind::CosmacVip => 1,
+    }
+}
+
+pub(crate) fn rng_kind_from_id(id: u8) -> Result<RngKind, StateError> {
+    match id {
+        0 => Ok(RngKind::SplitMix),
+        1 => Ok(RngKind::CosmacVip),
+        _ => Err(StateError::Invalid("rng")),
+    }
+}
+
+/// Packs the boolean quirks into bits 0-3 and `load_store` into bits 4-5.
+pub(crate) fn quirks_bits(quirks: Quirks) -> u16 {
+    let load_store = match quirks.load_store {
+        LoadStore::Increment => 0,
+        LoadStore::IncrementByX => 1,
+        LoadStore::Unchanged => 2,
+    };
+    quirks.shift as u16
+        | (quirks.jump as u16) << 1
+        | (quirks.clipping as u16) << 2
+        | (quirks.vf_reset as u16) << 3
+        | load_store << 4
+}
+
+pub(crate) fn quirks_from_bits(bits: u16) -> Result<Quirks, StateError> {
+    let load_store = match bits >> 4 {
+        0 => LoadStore::Increment,
+        1 => LoadStore::IncrementByX,
+        2 => LoadStore::Unchanged,
+        _ => return Err(StateError::Invalid("quirks")),
+    };
+    Ok(Quirks {
+        shift: bits & 0x1 != 0,
+        load_store,
+        jump: bits & 0x2 != 0,
+        clipping: bits & 0x4 != 0,
+        vf_reset: bits & 0x8 != 0,
+    })
+}
+
+/// Reads little-endian fields off the front of a save state.
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        StateReader { data }
+    }
+
+    /// Whether every byte has been read.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn flag(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag")),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
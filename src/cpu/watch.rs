@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// Whether an instruction read or wrote a byte of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// What should happen when a watchpoint is hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAction {
+    /// Pause execution after the instruction.
+    Break,
+    /// Keep running; the hit is only reported.
+    Log,
+}
+
+/// A range of memory whose accesses by DXYN, FX33, FX55, FX65, the XO-CHIP
+/// 5XY2/5XY3 register range instructions and F002 are recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// First watched address.
+    pub start: u16,
+    /// Last watched address, inclusive.
+    pub end: u16,
+    pub reads: bool,
+    pub writes: bool,
+    pub action: WatchAction,
+}
+
+impl Watchpoint {
+    /// Breaks on writes to `start..=end`, the usual way to catch a stray store.
+    pub fn writes(start: u16, end: u16) -> Watchpoint {
+        Watchpoint {
+            start,
+            end,
+            reads: false,
+            writes: true,
+            action: WatchAction::Break,
+        }
+    }
+
+    /// Whether `access` to `address` triggers this watchpoint.
+    pub fn covers(&self, address: usize, access: Access) -> bool {
+        let wanted = match access {
+            Access::Read => self.reads,
+            Access::Write => self.writes,
+        };
+        wanted && (self.start as usize..=self.end as usize).contains(&address)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match (self.reads, self.writes) {
+            (true, true) => "reads and writes",
+            (true, false) => "reads",
+            (false, true) => "writes",
+            (false, false) => "nothing",
+        };
+        let action = match self.action {
+            WatchAction::Break => "break",
+            WatchAction::Log => "log",
+        };
+        if self.start == self.end {
+            write!(f, "watch {:#06X} {}, {}", self.start, access, action)
+        } else {
+            write!(
+                f,
+                "watch {:#06X}-{:#06X} {}, {}",
+                self.start, self.end, access, action
+            )
+        }
+    }
+}
+
+/// One byte read or written inside a watchpoint, with the instruction that
+/// did it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub pc: u16,
+    pub opcode: u16,
+    pub address: u16,
+    pub access: Access,
+    /// The byte read, or the byte written.
+    pub value: u8,
+    pub action: WatchAction,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (verb, preposition) = match self.access {
+            Access::Read => ("read", "from"),
+            Access::Write => ("write", "to"),
+        };
+        write!(
+            f,
+            "{} {:#04X} {} {:#06X} by {:04X} at {:#06X}",
+            verb, self.value, preposition, self.address, self.opcode, self.pc
+        )
+    }
+}
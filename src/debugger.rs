@@ -0,0 +1,593 @@
+//! An interactive debugger that drives the CPU in place of `CPU::run_frame`.
+//!
+//! The debugger owns the decision of when to execute instructions: it can be
+//! paused and resumed, single-stepped, told to step over a 2NNN call or to run
+//! to an address, and it stops at PC breakpoints, optionally only when a
+//! condition on the registers holds. It also acts on the CPU's memory
+//! watchpoints, pausing after an instruction that hits a breaking one and
+//! collecting the hits of logging ones. `Debugger::execute` accepts the same
+//! operations as text commands so frontends can offer a REPL.
+
+use crate::trace::Trace;
+use crate::{CpuError, WatchAction, WatchHit, Watchpoint, CPU};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// A CPU value that a breakpoint condition can test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// Register V0-VF.
+    V(usize),
+    I,
+    Sp,
+    /// Delay timer.
+    Dt,
+    /// Sound timer.
+    St,
+}
+
+impl Operand {
+    fn read(self, cpu: &CPU) -> u16 {
+        match self {
+            Operand::V(x) => cpu.registers[x] as u16,
+            Operand::I => cpu.index_register,
+            Operand::Sp => cpu.sp as u16,
+            Operand::Dt => cpu.delay_timer as u16,
+            Operand::St => cpu.audio_timer as u16,
+        }
+    }
+
+    fn parse(s: &str) -> Option<Operand> {
+        match s.to_ascii_uppercase().as_str() {
+            "I" => Some(Operand::I),
+            "SP" => Some(Operand::Sp),
+            "DT" => Some(Operand::Dt),
+            "ST" => Some(Operand::St),
+            reg if reg.len() == 2 && reg.starts_with('V') => {
+                usize::from_str_radix(&reg[1..], 16).ok().map(Operand::V)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::V(x) => write!(f, "V{:X}", x),
+            Operand::I => write!(f, "I"),
+            Operand::Sp => write!(f, "SP"),
+            Operand::Dt => write!(f, "DT"),
+            Operand::St => write!(f, "ST"),
+        }
+    }
+}
+
+/// The comparisons a condition can make, in the order they are matched when parsing.
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    ("<=", Comparison::Le),
+    (">=", Comparison::Ge),
+    ("<", Comparison::Lt),
+    (">", Comparison::Gt),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A test such as `V3 == 0x10` that makes a breakpoint conditional.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    /// Parses `OPERAND OP VALUE`, where the operand is V0-VF, I, SP, DT or ST,
+    /// OP is one of `== != < <= > >=` and the value is decimal or `0x` hex.
+    pub fn parse(s: &str) -> Result<Condition, String> {
+        let (pos, symbol, comparison) = COMPARISONS
+            .iter()
+            .find_map(|&(symbol, comparison)| s.find(symbol).map(|pos| (pos, symbol, comparison)))
+            .ok_or_else(|| format!("no comparison in condition `{}`", s))?;
+        let operand = s[..pos].trim();
+        let value = s[pos + symbol.len()..].trim();
+        Ok(Condition {
+            operand: Operand::parse(operand)
+                .ok_or_else(|| format!("unknown register `{}`", operand))?,
+            comparison,
+            value: parse_value(value)?,
+        })
+    }
+
+    /// Whether the condition is true for the CPU's current state.
+    pub fn holds(&self, cpu: &CPU) -> bool {
+        let actual = self.operand.read(cpu);
+        match self.comparison {
+            Comparison::Eq => actual == self.value,
+            Comparison::Ne => actual != self.value,
+            Comparison::Lt => actual < self.value,
+            Comparison::Le => actual <= self.value,
+            Comparison::Gt => actual > self.value,
+            Comparison::Ge => actual >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = COMPARISONS
+            .iter()
+            .find(|(_, c)| *c == self.comparison)
+            .map(|(s, _)| *s)
+            .unwrap();
+        write!(f, "{} {} {:#X}", self.operand, symbol, self.value)
+    }
+}
+
+/// Why the debugger paused execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// A breakpoint at this address was hit (and its condition held).
+    Breakpoint(u16),
+    /// A step-over or run-to reached its destination.
+    Reached(u16),
+    /// The instruction just executed hit a breaking watchpoint.
+    Watchpoint(WatchHit),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(pc) => write!(f, "breakpoint at {:#06X}", pc),
+            Stop::Reached(pc) => write!(f, "stopped at {:#06X}", pc),
+            Stop::Watchpoint(hit) => write!(f, "watchpoint: {}", hit),
+        }
+    }
+}
+
+/// Where a step-over or run-to should stop. Step-over also requires the stack
+/// to be back at the depth of the call, so recursion does not stop it early.
+struct Target {
+    pc: u16,
+    sp: Option<u8>,
+}
+
+const HELP: &str = "\
+commands:
+  c, continue            resume execution
+  p, pause               pause and show the registers
+  s, step [N]            execute N instructions (default 1)
+  n, next                step, running 2NNN calls until they return
+  u, until ADDR          run until PC reaches ADDR
+  b, break ADDR [if C]   break at ADDR, optionally only when C holds, e.g. `V3 == 0x10`
+  d, delete [ADDR]       delete the breakpoint at ADDR, or all of them
+  bl, breakpoints        list breakpoints
+  r, regs                show V0-VF, I, SP, the timers and the stack
+  m, mem ADDR [LEN]      dump LEN bytes of memory (default 16)
+  w, watch ADDR[-END] [r|w|rw] [log]
+                         watch memory for writes (default) or reads, breaking
+                         after the instruction unless `log` is given
+  uw, unwatch [ADDR]     delete the watchpoints starting at ADDR, or all of them
+  wl, watchpoints        list watchpoints
+  trace on [FILE]|off    write a trace line per instruction to FILE or stdout
+addresses are hex, values are decimal or 0x hex";
+
+/// Decides when the CPU runs. Call `run_frame` once per 60 Hz frame instead
+/// of `CPU::run_frame`; it does nothing while paused.
+pub struct Debugger {
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    paused: bool,
+    target: Option<Target>,
+    /// The PC execution resumed at. Its breakpoint is skipped once, so that
+    /// continuing from a breakpoint does not stop on it again straight away.
+    resume_pc: Option<u16>,
+    /// Instructions already executed in the current frame.
+    frame_cycle: u32,
+    /// Hits of logging watchpoints not yet taken by `take_log`.
+    log: Vec<WatchHit>,
+    /// Gets a line for every instruction executed, see `set_trace`.
+    trace: Option<Trace<Box<dyn Write>>>,
+}
+
+impl Debugger {
+    /// Creates a debugger that is running and has no breakpoints.
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeMap::new(),
+            paused: false,
+            target: None,
+            resume_pc: None,
+            frame_cycle: 0,
+            log: Vec::new(),
+            trace: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.target = None;
+    }
+
+    /// Resumes execution from the CPU's current PC.
+    pub fn resume(&mut self, cpu: &CPU) {
+        self.paused = false;
+        self.resume_pc = Some(cpu.pc);
+    }
+
+    /// Stops at `pc` whenever `condition` holds, or always if there is none.
+    pub fn add_breakpoint(&mut self, pc: u16, condition: Option<Condition>) {
+        self.breakpoints.insert(pc, condition);
+    }
+
+    /// Returns whether there was a breakpoint at `pc`.
+    pub fn remove_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.remove(&pc).is_some()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (u16, Option<Condition>)> + '_ {
+        self.breakpoints.iter().map(|(&pc, &c)| (pc, c))
+    }
+
+    /// Stops tracing and flushes the trace.
+    fn end_trace(&mut self) -> Result<(), String> {
+        match self.set_trace(None) {
+            Some(trace) => trace
+                .finish()
+                .map(drop)
+                .map_err(|e| format!("trace failed: {}", e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes a trace line for every instruction executed from now on, or
+    /// stops tracing with `None`, returning the previous trace to `finish`.
+    pub fn set_trace(
+        &mut self,
+        trace: Option<Trace<Box<dyn Write>>>,
+    ) -> Option<Trace<Box<dyn Write>>> {
+        std::mem::replace(&mut self.trace, trace)
+    }
+
+    /// Executes one instruction, tracing it first.
+    fn execute_one(&mut self, cpu: &mut CPU) -> Result<(), CpuError> {
+        if let Some(trace) = &mut self.trace {
+            trace.record(cpu);
+        }
+        cpu.step()
+    }
+
+    /// Runs the rest of the current frame, then ticks the timers. Returns the
+    /// reason if a breakpoint or step target paused execution part way through;
+    /// the next call after resuming finishes that frame.
+    pub fn run_frame(
+        &mut self,
+        cpu: &mut CPU,
+        cycles_per_frame: u32,
+    ) -> Result<Option<Stop>, CpuError> {
+        if self.paused {
+            return Ok(None);
+        }
+        while self.frame_cycle < cycles_per_frame {
+            if let Some(stop) = self.check_stop(cpu) {
+                self.pause();
+                return Ok(Some(stop));
+            }
+            self.execute_one(cpu)?;
+            self.frame_cycle += 1;
+            if let Some(stop) = self.collect_hits(cpu) {
+                self.pause();
+                return Ok(Some(stop));
+            }
+        }
+        self.frame_cycle = 0;
+        cpu.tick_timers();
+        Ok(None)
+    }
+
+    /// Executes a single instruction and pauses, returning the stop if it hit
+    /// a breaking watchpoint. Timers do not tick while single-stepping.
+    pub fn step(&mut self, cpu: &mut CPU) -> Result<Option<Stop>, CpuError> {
+        self.pause();
+        self.execute_one(cpu)?;
+        Ok(self.collect_hits(cpu))
+    }
+
+    /// Returns the hits of logging watchpoints since the last call, oldest first.
+    pub fn take_log(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.log)
+    }
+
+    /// Moves the CPU's watchpoint hits into the log, returning a stop for the
+    /// first breaking one.
+    fn collect_hits(&mut self, cpu: &mut CPU) -> Option<Stop> {
+        let mut stop = None;
+        for hit in cpu.take_watch_hits() {
+            match hit.action {
+                WatchAction::Break if stop.is_none() => stop = Some(Stop::Watchpoint(hit)),
+                _ => self.log.push(hit),
+            }
+        }
+        stop
+    }
+
+    /// Like `step`, except that a 2NNN call is run until it returns, with
+    /// breakpoints inside the subroutine still honoured. Returns whether
+    /// execution was resumed to run a call.
+    pub fn step_over(&mut self, cpu: &mut CPU) -> Result<bool, CpuError> {
+        if !is_call(cpu) {
+            self.step(cpu)?;
+            return Ok(false);
+        }
+        self.target = Some(Target {
+            pc: cpu.pc.wrapping_add(2),
+            sp: Some(cpu.sp),
+        });
+        self.resume(cpu);
+        Ok(true)
+    }
+
+    /// Resumes execution until the PC reaches `pc`.
+    pub fn run_to(&mut self, cpu: &CPU, pc: u16) {
+        self.target = Some(Target { pc, sp: None });
+        self.resume(cpu);
+    }
+
+    fn check_stop(&mut self, cpu: &CPU) -> Option<Stop> {
+        let pc = cpu.pc;
+        if self.resume_pc.take() == Some(pc) {
+            return None;
+        }
+        if let Some(target) = &self.target {
+            if target.pc == pc && target.sp.is_none_or(|sp| sp == cpu.sp) {
+                return Some(Stop::Reached(pc));
+            }
+        }
+        match self.breakpoints.get(&pc) {
+            Some(None) => Some(Stop::Breakpoint(pc)),
+            Some(Some(condition)) if condition.holds(cpu) => Some(Stop::Breakpoint(pc)),
+            _ => None,
+        }
+    }
+
+    /// Runs one REPL command and returns the text to show for it. See `help`
+    /// for the commands.
+    pub fn execute(&mut self, cpu: &mut CPU, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(String::new()),
+        };
+        let args: Vec<&str> = words.collect();
+        match command {
+            "h" | "help" => Ok(HELP.to_owned()),
+            "c" | "continue" => {
+                self.resume(cpu);
+                Ok("continuing".to_owned())
+            }
+            "p" | "pause" => {
+                self.pause();
+                Ok(registers(cpu))
+            }
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(n) => n
+                        .parse()
+                        .map_err(|_| format!("invalid step count `{}`", n))?,
+                    None => 1,
+                };
+                for _ in 0..count {
+                    if let Some(stop) = self.step(cpu).map_err(|e| e.to_string())? {
+                        return Ok(format!("{}\n{}", stop, registers(cpu)));
+                    }
+                }
+                Ok(registers(cpu))
+            }
+            "n" | "next" if !is_call(cpu) => self.execute(cpu, "step"),
+            "n" | "next" => {
+                self.step_over(cpu).map_err(|e| e.to_string())?;
+                Ok(format!("running call at {:#06X}", cpu.pc))
+            }
+            "u" | "until" => {
+                let pc = parse_address(args.first().ok_or("until needs an address")?)?;
+                self.run_to(cpu, pc);
+                Ok(format!("running to {:#06X}", pc))
+            }
+            "b" | "break" => {
+                let pc = parse_address(args.first().ok_or("break needs an address")?)?;
+                let condition = match args.get(1) {
+                    Some(&"if") => Some(Condition::parse(&args[2..].join(" "))?),
+                    Some(other) => return Err(format!("expected `if`, got `{}`", other)),
+                    None => None,
+                };
+                self.add_breakpoint(pc, condition);
+                Ok(describe_breakpoint(pc, condition))
+            }
+            "d" | "delete" => match args.first() {
+                Some(addr) => {
+                    let pc = parse_address(addr)?;
+                    if self.remove_breakpoint(pc) {
+                        Ok(format!("deleted breakpoint at {:#06X}", pc))
+                    } else {
+                        Err(format!("no breakpoint at {:#06X}", pc))
+                    }
+                }
+                None => {
+                    self.breakpoints.clear();
+                    Ok("deleted all breakpoints".to_owned())
+                }
+            },
+            "bl" | "breakpoints" => Ok(self
+                .breakpoints()
+                .map(|(pc, condition)| describe_breakpoint(pc, condition))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "r" | "regs" => Ok(registers(cpu)),
+            "m" | "mem" => {
+                let start = parse_address(args.first().ok_or("mem needs an address")?)? as usize;
+                let len = match args.get(1) {
+                    Some(len) => parse_value(len)? as usize,
+                    None => 16,
+                };
+                Ok(memory(cpu, start, len))
+            }
+            "w" | "watch" => {
+                let range = args.first().ok_or("watch needs an address")?;
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (parse_address(start)?, parse_address(end)?),
+                    None => (parse_address(range)?, parse_address(range)?),
+                };
+                if end < start {
+                    return Err(format!("watch range `{}` ends before it starts", range));
+                }
+                let mut watchpoint = Watchpoint::writes(start, end);
+                for &option in &args[1..] {
+                    match option {
+                        "r" => (watchpoint.reads, watchpoint.writes) = (true, false),
+                        "w" => (watchpoint.reads, watchpoint.writes) = (false, true),
+                        "rw" => (watchpoint.reads, watchpoint.writes) = (true, true),
+                        "log" => watchpoint.action = WatchAction::Log,
+                        _ => return Err(format!("unknown watch option `{}`", option)),
+                    }
+                }
+                cpu.watchpoints.push(watchpoint);
+                Ok(watchpoint.to_string())
+            }
+            "uw" | "unwatch" => match args.first() {
+                Some(addr) => {
+                    let start = parse_address(addr)?;
+                    let before = cpu.watchpoints.len();
+                    cpu.watchpoints.retain(|w| w.start != start);
+                    if cpu.watchpoints.len() < before {
+                        Ok(format!("deleted watchpoints at {:#06X}", start))
+                    } else {
+                        Err(format!("no watchpoint at {:#06X}", start))
+                    }
+                }
+                None => {
+                    cpu.watchpoints.clear();
+                    Ok("deleted all watchpoints".to_owned())
+                }
+            },
+            "wl" | "watchpoints" => Ok(cpu
+                .watchpoints
+                .iter()
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "trace" => {
+                let out: Box<dyn Write> = match args[..] {
+                    ["on"] => Box::new(io::stdout()),
+                    ["on", path] => Box::new(BufWriter::new(
+                        File::create(path)
+                            .map_err(|e| format!("cannot write trace to {}: {}", path, e))?,
+                    )),
+                    ["off"] => {
+                        self.end_trace()?;
+                        return Ok("tracing off".to_owned());
+                    }
+                    _ => return Err("usage: trace on [FILE]|off".to_owned()),
+                };
+                // a trace already running, e.g. from --trace, is flushed first
+                self.end_trace()?;
+                self.set_trace(Some(Trace::new(out)));
+                Ok("tracing on".to_owned())
+            }
+            _ => Err(format!("unknown command `{}`, try `help`", command)),
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+fn describe_breakpoint(pc: u16, condition: Option<Condition>) -> String {
+    match condition {
+        Some(condition) => format!("breakpoint at {:#06X} if {}", pc, condition),
+        None => format!("breakpoint at {:#06X}", pc),
+    }
+}
+
+/// The register view shown by `regs`: PC, the next opcode, I, SP, the timers,
+/// V0-VF and the stack.
+pub fn registers(cpu: &CPU) -> String {
+    let pc = cpu.pc as usize;
+    let opcode = match cpu.memory.get(pc..pc + 2) {
+        Some(bytes) => format!("{:02X}{:02X}", bytes[0], bytes[1]),
+        None => "----".to_owned(),
+    };
+    let mut out = format!(
+        "PC {:#06X}  opcode {}  I {:#06X}  SP {}  DT {}  ST {}  steps {}",
+        cpu.pc,
+        opcode,
+        cpu.index_register,
+        cpu.sp,
+        cpu.delay_timer,
+        cpu.audio_timer,
+        cpu.steps()
+    );
+    for (i, value) in cpu.registers.iter().enumerate() {
+        out.push_str(if i % 8 == 0 { "\n" } else { "  " });
+        out.push_str(&format!("V{:X} {:02X}", i, value));
+    }
+    out.push_str("\nstack:");
+    for frame in &cpu.stack[..cpu.sp as usize] {
+        out.push_str(&format!(" {:#06X}", frame));
+    }
+    out
+}
+
+/// Hex dump of `len` bytes from `start`, 16 per line, clipped to the end of memory.
+fn memory(cpu: &CPU, start: usize, len: usize) -> String {
+    let end = (start + len).min(cpu.memory.len());
+    (start..end)
+        .step_by(16)
+        .map(|line| {
+            let bytes: Vec<String> = cpu.memory[line..(line + 16).min(end)]
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect();
+            format!("{:04X}: {}", line, bytes.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether the instruction at the PC is a 2NNN call.
+fn is_call(cpu: &CPU) -> bool {
+    let pc = cpu.pc as usize;
+    pc + 1 < cpu.memory.len() && cpu.memory[pc] & 0xF0 == 0x20
+}
+
+/// Parses a hex address, with or without a `0x` prefix.
+fn parse_address(s: &str) -> Result<u16, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address `{}`", s))
+}
+
+/// Parses a decimal value, or hex with a `0x` prefix.
+fn parse_value(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("invalid value `{}`", s))
+}
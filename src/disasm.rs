@@ -0,0 +1,643 @@
+//! Turns ROMs back into readable source, in Cowgod's mnemonics or in Octo.
+//!
+//! Code is told apart from data by following every path the program can take
+//! from 0x200: jumps, calls, both outcomes of skips and the base of BNNN jump
+//! tables. Bytes that no path reaches are listed as data. Jump, call and I
+//! targets inside the ROM get labels, named `L` and the hex address.
+
+use crate::Platform;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// Where ROMs are loaded and execution starts.
+pub const START: u16 = 0x200;
+
+/// Most data bytes put on one line.
+const DATA_PER_LINE: usize = 8;
+
+/// The assembly language of a listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// The mnemonics of Cowgod's Chip-8 Technical Reference (`LD V0, #2A`),
+    /// extended for SUPER-CHIP and XO-CHIP.
+    Cowgod,
+    /// The Octo assembly language (`v0 := 0x2A`).
+    Octo,
+}
+
+impl Syntax {
+    /// Looks up a syntax by name: `cowgod` or `octo`.
+    pub fn from_name(name: &str) -> Option<Syntax> {
+        match name.to_ascii_lowercase().as_str() {
+            "cowgod" => Some(Syntax::Cowgod),
+            "octo" => Some(Syntax::Octo),
+            _ => None,
+        }
+    }
+
+    fn comment(self) -> char {
+        match self {
+            Syntax::Cowgod => ';',
+            Syntax::Octo => '#',
+        }
+    }
+
+    fn hex(self, value: u16) -> String {
+        match self {
+            Syntax::Cowgod => format!("#{:02X}", value),
+            Syntax::Octo => format!("0x{:02X}", value),
+        }
+    }
+
+    fn reg(self, r: u8) -> String {
+        match self {
+            Syntax::Cowgod => format!("V{:X}", r),
+            Syntax::Octo => format!("v{:x}", r),
+        }
+    }
+}
+
+/// The 8XYN register operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Mov,
+    Or,
+    And,
+    Xor,
+    Add,
+    Sub,
+    Shr,
+    SubN,
+    Shl,
+}
+
+impl AluOp {
+    /// The low nibble of the opcode.
+    pub fn nibble(self) -> u16 {
+        match self {
+            AluOp::Mov => 0x0,
+            AluOp::Or => 0x1,
+            AluOp::And => 0x2,
+            AluOp::Xor => 0x3,
+            AluOp::Add => 0x4,
+            AluOp::Sub => 0x5,
+            AluOp::Shr => 0x6,
+            AluOp::SubN => 0x7,
+            AluOp::Shl => 0xE,
+        }
+    }
+
+    fn from_nibble(n: u16) -> Option<AluOp> {
+        [
+            AluOp::Mov,
+            AluOp::Or,
+            AluOp::And,
+            AluOp::Xor,
+            AluOp::Add,
+            AluOp::Sub,
+            AluOp::Shr,
+            AluOp::SubN,
+            AluOp::Shl,
+        ]
+        .iter()
+        .copied()
+        .find(|op| op.nibble() == n)
+    }
+}
+
+/// A decoded instruction. Registers are numbered 0-15; addresses are those
+/// the instruction jumps to, calls or loads into I.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00CN
+    ScrollDown(u8),
+    /// 00DN, XO-CHIP
+    ScrollUp(u8),
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    Lores,
+    /// 00FF
+    Hires,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN, skip if VX == NN
+    SkipEq(u8, u8),
+    /// 4XNN, skip if VX != NN
+    SkipNe(u8, u8),
+    /// 5XY0, skip if VX == VY
+    SkipEqReg(u8, u8),
+    /// 5XY2, XO-CHIP: store VX to VY at I
+    SaveRange(u8, u8),
+    /// 5XY3, XO-CHIP: load VX to VY from I
+    LoadRange(u8, u8),
+    /// 6XNN
+    LoadByte(u8, u8),
+    /// 7XNN
+    AddByte(u8, u8),
+    /// 8XYN
+    Alu(AluOp, u8, u8),
+    /// 9XY0, skip if VX != VY
+    SkipNeReg(u8, u8),
+    /// ANNN
+    LoadI(u16),
+    /// BNNN
+    JumpV0(u16),
+    /// CXNN
+    Random(u8, u8),
+    /// DXYN
+    Draw(u8, u8, u8),
+    /// EX9E
+    SkipKey(u8),
+    /// EXA1
+    SkipNotKey(u8),
+    /// F000 NNNN, XO-CHIP
+    LoadILong(u16),
+    /// FN01, XO-CHIP
+    Plane(u8),
+    /// F002, XO-CHIP
+    Audio,
+    /// FX07
+    GetDelay(u8),
+    /// FX0A
+    WaitKey(u8),
+    /// FX15
+    SetDelay(u8),
+    /// FX18
+    SetSound(u8),
+    /// FX1E
+    AddI(u8),
+    /// FX29
+    Font(u8),
+    /// FX30
+    BigFont(u8),
+    /// FX33
+    Bcd(u8),
+    /// FX3A, XO-CHIP
+    Pitch(u8),
+    /// FX55
+    Store(u8),
+    /// FX65
+    Fill(u8),
+    /// FX75
+    SaveFlags(u8),
+    /// FX85
+    LoadFlags(u8),
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `bytes`, or returns `None` if
+    /// they do not hold one that runs on `platform`.
+    pub fn decode(bytes: &[u8], platform: Platform) -> Option<Instruction> {
+        use Instruction::*;
+        if bytes.len() < 2 {
+            return None;
+        }
+        let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        let schip = platform != Platform::Chip8;
+        let xo = platform == Platform::XoChip;
+
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00E0 => Cls,
+                0x00EE => Ret,
+                0x00C0..=0x00CF if schip => ScrollDown(n),
+                0x00D0..=0x00DF if xo => ScrollUp(n),
+                0x00FB if schip => ScrollRight,
+                0x00FC if schip => ScrollLeft,
+                0x00FD if schip => Exit,
+                0x00FE if schip => Lores,
+                0x00FF if schip => Hires,
+                _ => return None,
+            },
+            0x1000 => Jump(nnn),
+            0x2000 => Call(nnn),
+            0x3000 => SkipEq(x, nn),
+            0x4000 => SkipNe(x, nn),
+            0x5000 => match n {
+                0x0 => SkipEqReg(x, y),
+                0x2 if xo => SaveRange(x, y),
+                0x3 if xo => LoadRange(x, y),
+                _ => return None,
+            },
+            0x6000 => LoadByte(x, nn),
+            0x7000 => AddByte(x, nn),
+            0x8000 => Alu(AluOp::from_nibble(n as u16)?, x, y),
+            0x9000 if n == 0 => SkipNeReg(x, y),
+            0xA000 => LoadI(nnn),
+            0xB000 => JumpV0(nnn),
+            0xC000 => Random(x, nn),
+            0xD000 => Draw(x, y, n),
+            0xE000 => match nn {
+                0x9E => SkipKey(x),
+                0xA1 => SkipNotKey(x),
+                _ => return None,
+            },
+            0xF000 => match nn {
+                0x00 if xo && x == 0 && bytes.len() >= 4 => {
+                    LoadILong(u16::from_be_bytes([bytes[2], bytes[3]]))
+                }
+                0x01 if xo => Plane(x),
+                0x02 if xo && x == 0 => Audio,
+                0x07 => GetDelay(x),
+                0x0A => WaitKey(x),
+                0x15 => SetDelay(x),
+                0x18 => SetSound(x),
+                0x1E => AddI(x),
+                0x29 => Font(x),
+                0x30 if schip => BigFont(x),
+                0x33 => Bcd(x),
+                0x3A if xo => Pitch(x),
+                0x55 => Store(x),
+                0x65 => Fill(x),
+                0x75 if schip => SaveFlags(x),
+                0x85 if schip => LoadFlags(x),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// The machine code for the instruction, big-endian. Operands are masked
+    /// to the width of their field.
+    pub fn encode(&self) -> Vec<u8> {
+        use Instruction::*;
+        let xy = |op: u16, x: u8, y: u8| op | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4;
+        let xnn = |op: u16, x: u8, nn: u8| op | (x as u16 & 0xF) << 8 | nn as u16;
+        let fx = |x: u8, low: u16| 0xF000 | (x as u16 & 0xF) << 8 | low;
+        let opcode = match *self {
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            Jump(a) => 0x1000 | (a & 0xFFF),
+            Call(a) => 0x2000 | (a & 0xFFF),
+            SkipEq(x, nn) => xnn(0x3000, x, nn),
+            SkipNe(x, nn) => xnn(0x4000, x, nn),
+            SkipEqReg(x, y) => xy(0x5000, x, y),
+            SaveRange(x, y) => xy(0x5002, x, y),
+            LoadRange(x, y) => xy(0x5003, x, y),
+            LoadByte(x, nn) => xnn(0x6000, x, nn),
+            AddByte(x, nn) => xnn(0x7000, x, nn),
+            Alu(op, x, y) => xy(0x8000 | op.nibble(), x, y),
+            SkipNeReg(x, y) => xy(0x9000, x, y),
+            LoadI(a) => 0xA000 | (a & 0xFFF),
+            JumpV0(a) => 0xB000 | (a & 0xFFF),
+            Random(x, nn) => xnn(0xC000, x, nn),
+            Draw(x, y, n) => xy(0xD000, x, y) | (n as u16 & 0xF),
+            SkipKey(x) => xnn(0xE000, x, 0x9E),
+            SkipNotKey(x) => xnn(0xE000, x, 0xA1),
+            LoadILong(a) => return [0xF0, 0x00, (a >> 8) as u8, a as u8].to_vec(),
+            Plane(n) => fx(n, 0x01),
+            Audio => 0xF002,
+            GetDelay(x) => fx(x, 0x07),
+            WaitKey(x) => fx(x, 0x0A),
+            SetDelay(x) => fx(x, 0x15),
+            SetSound(x) => fx(x, 0x18),
+            AddI(x) => fx(x, 0x1E),
+            Font(x) => fx(x, 0x29),
+            BigFont(x) => fx(x, 0x30),
+            Bcd(x) => fx(x, 0x33),
+            Pitch(x) => fx(x, 0x3A),
+            Store(x) => fx(x, 0x55),
+            Fill(x) => fx(x, 0x65),
+            SaveFlags(x) => fx(x, 0x75),
+            LoadFlags(x) => fx(x, 0x85),
+        };
+        opcode.to_be_bytes().to_vec()
+    }
+
+    /// Bytes the instruction takes up: 4 for F000 NNNN, otherwise 2.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// The address the instruction jumps to, calls or points I at.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jump(addr)
+            | Instruction::Call(addr)
+            | Instruction::JumpV0(addr)
+            | Instruction::LoadI(addr)
+            | Instruction::LoadILong(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Whether the next instruction is skipped when some condition holds.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipEq(..)
+                | Instruction::SkipNe(..)
+                | Instruction::SkipEqReg(..)
+                | Instruction::SkipNeReg(..)
+                | Instruction::SkipKey(_)
+                | Instruction::SkipNotKey(_)
+        )
+    }
+
+    /// Formats the instruction in `syntax`, writing addresses with `address`.
+    pub fn format(&self, syntax: Syntax, address: &dyn Fn(u16) -> String) -> String {
+        match syntax {
+            Syntax::Cowgod => self.cowgod(address),
+            Syntax::Octo => self.octo(address),
+        }
+    }
+
+    fn cowgod(&self, address: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+        let s = Syntax::Cowgod;
+        let r = |r: u8| s.reg(r);
+        let h = |v: u8| s.hex(v as u16);
+        match *self {
+            Cls => "CLS".to_owned(),
+            Ret => "RET".to_owned(),
+            ScrollDown(n) => format!("SCD {}", n),
+            ScrollUp(n) => format!("SCU {}", n),
+            ScrollRight => "SCR".to_owned(),
+            ScrollLeft => "SCL".to_owned(),
+            Exit => "EXIT".to_owned(),
+            Lores => "LOW".to_owned(),
+            Hires => "HIGH".to_owned(),
+            Jump(a) => format!("JP {}", address(a)),
+            Call(a) => format!("CALL {}", address(a)),
+            SkipEq(x, nn) => format!("SE {}, {}", r(x), h(nn)),
+            SkipNe(x, nn) => format!("SNE {}, {}", r(x), h(nn)),
+            SkipEqReg(x, y) => format!("SE {}, {}", r(x), r(y)),
+            SaveRange(x, y) => format!("SAVE {}, {}", r(x), r(y)),
+            LoadRange(x, y) => format!("LOAD {}, {}", r(x), r(y)),
+            LoadByte(x, nn) => format!("LD {}, {}", r(x), h(nn)),
+            AddByte(x, nn) => format!("ADD {}, {}", r(x), h(nn)),
+            Alu(op, x, y) => {
+                let mnemonic = match op {
+                    AluOp::Mov => "LD",
+                    AluOp::Or => "OR",
+                    AluOp::And => "AND",
+                    AluOp::Xor => "XOR",
+                    AluOp::Add => "ADD",
+                    AluOp::Sub => "SUB",
+                    AluOp::Shr => "SHR",
+                    AluOp::SubN => "SUBN",
+                    AluOp::Shl => "SHL",
+                };
+                format!("{} {}, {}", mnemonic, r(x), r(y))
+            }
+            SkipNeReg(x, y) => format!("SNE {}, {}", r(x), r(y)),
+            LoadI(a) => format!("LD I, {}", address(a)),
+            JumpV0(a) => format!("JP V0, {}", address(a)),
+            Random(x, nn) => format!("RND {}, {}", r(x), h(nn)),
+            Draw(x, y, n) => format!("DRW {}, {}, {}", r(x), r(y), n),
+            SkipKey(x) => format!("SKP {}", r(x)),
+            SkipNotKey(x) => format!("SKNP {}", r(x)),
+            LoadILong(a) => format!("LD I, LONG {}", address(a)),
+            Plane(n) => format!("PLANE {}", n),
+            Audio => "AUDIO".to_owned(),
+            GetDelay(x) => format!("LD {}, DT", r(x)),
+            WaitKey(x) => format!("LD {}, K", r(x)),
+            SetDelay(x) => format!("LD DT, {}", r(x)),
+            SetSound(x) => format!("LD ST, {}", r(x)),
+            AddI(x) => format!("ADD I, {}", r(x)),
+            Font(x) => format!("LD F, {}", r(x)),
+            BigFont(x) => format!("LD HF, {}", r(x)),
+            Bcd(x) => format!("LD B, {}", r(x)),
+            Pitch(x) => format!("PITCH {}", r(x)),
+            Store(x) => format!("LD [I], {}", r(x)),
+            Fill(x) => format!("LD {}, [I]", r(x)),
+            SaveFlags(x) => format!("LD R, {}", r(x)),
+            LoadFlags(x) => format!("LD {}, R", r(x)),
+        }
+    }
+
+    fn octo(&self, address: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+        let s = Syntax::Octo;
+        let r = |r: u8| s.reg(r);
+        let h = |v: u8| s.hex(v as u16);
+        // Octo's `if ... then` runs the next instruction when the condition
+        // holds, so each skip is written with the opposite comparison.
+        match *self {
+            Cls => "clear".to_owned(),
+            Ret => "return".to_owned(),
+            ScrollDown(n) => format!("scroll-down {}", n),
+            ScrollUp(n) => format!("scroll-up {}", n),
+            ScrollRight => "scroll-right".to_owned(),
+            ScrollLeft => "scroll-left".to_owned(),
+            Exit => "exit".to_owned(),
+            Lores => "lores".to_owned(),
+            Hires => "hires".to_owned(),
+            Jump(a) => format!("jump {}", address(a)),
+            Call(a) => format!(":call {}", address(a)),
+            SkipEq(x, nn) => format!("if {} != {} then", r(x), h(nn)),
+            SkipNe(x, nn) => format!("if {} == {} then", r(x), h(nn)),
+            SkipEqReg(x, y) => format!("if {} != {} then", r(x), r(y)),
+            SaveRange(x, y) => format!("save {} - {}", r(x), r(y)),
+            LoadRange(x, y) => format!("load {} - {}", r(x), r(y)),
+            LoadByte(x, nn) => format!("{} := {}", r(x), h(nn)),
+            AddByte(x, nn) => format!("{} += {}", r(x), h(nn)),
+            Alu(op, x, y) => {
+                let operator = match op {
+                    AluOp::Mov => ":=",
+                    AluOp::Or => "|=",
+                    AluOp::And => "&=",
+                    AluOp::Xor => "^=",
+                    AluOp::Add => "+=",
+                    AluOp::Sub => "-=",
+                    AluOp::Shr => ">>=",
+                    AluOp::SubN => "=-",
+                    AluOp::Shl => "<<=",
+                };
+                format!("{} {} {}", r(x), operator, r(y))
+            }
+            SkipNeReg(x, y) => format!("if {} == {} then", r(x), r(y)),
+            LoadI(a) => format!("i := {}", address(a)),
+            JumpV0(a) => format!("jump0 {}", address(a)),
+            Random(x, nn) => format!("{} := random {}", r(x), h(nn)),
+            Draw(x, y, n) => format!("sprite {} {} {}", r(x), r(y), n),
+            SkipKey(x) => format!("if {} -key then", r(x)),
+            SkipNotKey(x) => format!("if {} key then", r(x)),
+            LoadILong(a) => format!("i := long {}", address(a)),
+            Plane(n) => format!("plane {}", n),
+            Audio => "audio".to_owned(),
+            GetDelay(x) => format!("{} := delay", r(x)),
+            WaitKey(x) => format!("{} := key", r(x)),
+            SetDelay(x) => format!("delay := {}", r(x)),
+            SetSound(x) => format!("buzzer := {}", r(x)),
+            AddI(x) => format!("i += {}", r(x)),
+            Font(x) => format!("i := hex {}", r(x)),
+            BigFont(x) => format!("i := bighex {}", r(x)),
+            Bcd(x) => format!("bcd {}", r(x)),
+            Pitch(x) => format!("pitch := {}", r(x)),
+            Store(x) => format!("save {}", r(x)),
+            Fill(x) => format!("load {}", r(x)),
+            SaveFlags(x) => format!("saveflags {}", r(x)),
+            LoadFlags(x) => format!("loadflags {}", r(x)),
+        }
+    }
+}
+
+/// Finds the instructions reachable from `START`, keyed by address.
+pub fn trace(rom: &[u8], platform: Platform) -> BTreeMap<u16, Instruction> {
+    let end = START as usize + rom.len();
+    let decode_at = |addr: u16| {
+        let addr = addr as usize;
+        if addr < START as usize || addr >= end {
+            return None;
+        }
+        Instruction::decode(&rom[addr - START as usize..], platform)
+    };
+
+    let mut code = BTreeMap::new();
+    let mut pending = vec![START];
+    while let Some(addr) = pending.pop() {
+        if code.contains_key(&addr) {
+            continue;
+        }
+        let instruction = match decode_at(addr) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        code.insert(addr, instruction);
+        let next = addr.wrapping_add(instruction.size());
+        match instruction {
+            Instruction::Jump(target) | Instruction::JumpV0(target) => pending.push(target),
+            Instruction::Call(target) => pending.extend([target, next]),
+            Instruction::Ret | Instruction::Exit => {}
+            _ if instruction.is_skip() => {
+                let skipped = decode_at(next).map_or(2, |i| i.size());
+                pending.extend([next, next.wrapping_add(skipped)]);
+            }
+            _ => pending.push(next),
+        }
+    }
+    code
+}
+
+/// One line of the listing body.
+enum Item {
+    Code(Instruction),
+    Data(Vec<u8>),
+}
+
+/// Disassembles `rom`, loaded at `START`, into a listing in `syntax`. Each
+/// line ends in a comment with its address and bytes, so the listing can be
+/// assembled again as it is.
+pub fn disassemble(rom: &[u8], platform: Platform, syntax: Syntax) -> String {
+    let code = trace(rom, platform);
+    let targets: BTreeSet<u16> = code.values().filter_map(|i| i.target()).collect();
+
+    // Lay out the ROM: code where a traced instruction starts, data elsewhere.
+    // Data lines also break at targets, so I loads into data get labels too.
+    let mut items = Vec::new();
+    let mut addr = START as usize;
+    let end = START as usize + rom.len();
+    while addr < end {
+        if let Some(&instruction) = code.get(&(addr as u16)) {
+            items.push((addr as u16, Item::Code(instruction)));
+            addr += instruction.size() as usize;
+            continue;
+        }
+        let start = addr;
+        let mut bytes = Vec::new();
+        while addr < end
+            && bytes.len() < DATA_PER_LINE
+            && !code.contains_key(&(addr as u16))
+            && (addr == start || !targets.contains(&(addr as u16)))
+        {
+            bytes.push(rom[addr - START as usize]);
+            addr += 1;
+        }
+        items.push((start as u16, Item::Data(bytes)));
+    }
+
+    // Only targets at the start of a line can be labelled; the rest are
+    // written as plain addresses.
+    let labels: BTreeSet<u16> = items
+        .iter()
+        .map(|&(addr, _)| addr)
+        .filter(|addr| targets.contains(addr) || (*addr == START && syntax == Syntax::Octo))
+        .collect();
+    let label = |addr: u16| {
+        if addr == START && syntax == Syntax::Octo {
+            "main".to_owned()
+        } else {
+            format!("L{:03X}", addr)
+        }
+    };
+    let address = |addr: u16| {
+        if labels.contains(&addr) {
+            label(addr)
+        } else {
+            syntax.hex(addr)
+        }
+    };
+
+    let mut out = String::new();
+    for (addr, item) in &items {
+        if labels.contains(addr) {
+            match syntax {
+                Syntax::Cowgod => writeln!(out, "{}:", label(*addr)).unwrap(),
+                Syntax::Octo => writeln!(out, ": {}", label(*addr)).unwrap(),
+            }
+        }
+        let offset = (addr - START) as usize;
+        let (text, size) = match item {
+            Item::Code(instruction) => (
+                match (syntax, instruction) {
+                    // A call is just the label's name in Octo.
+                    (Syntax::Octo, Instruction::Call(target)) if labels.contains(target) => {
+                        label(*target)
+                    }
+                    _ => instruction.format(syntax, &address),
+                },
+                instruction.size() as usize,
+            ),
+            Item::Data(bytes) => {
+                let values: Vec<String> = bytes.iter().map(|&b| syntax.hex(b as u16)).collect();
+                let text = match syntax {
+                    Syntax::Cowgod => format!("DB {}", values.join(", ")),
+                    Syntax::Octo => values.join(" "),
+                };
+                (text, bytes.len())
+            }
+        };
+        let hex: String = rom[offset..offset + size]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        writeln!(
+            out,
+            "    {:<27} {} {:04X}  {}",
+            text,
+            syntax.comment(),
+            addr,
+            hex
+        )
+        .unwrap();
+    }
+    out
+}
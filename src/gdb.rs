@@ -0,0 +1,392 @@
+//! A GDB remote serial protocol server, so ROMs can be debugged with `gdb`,
+//! `lldb` or any other RSP client (`target remote localhost:PORT`).
+//!
+//! The target describes its registers in `target.xml`: V0-VF are registers
+//! 0-15 (8 bits), then I and PC (16 bits) and SP (8 bits), all little-endian
+//! in `g`/`p` replies. Memory is the CPU's whole address space. Supported
+//! are `?`, `g`, `G`, `p`, `P`, `m`, `M`, `c`, `s`, `Z0`/`z0` (and `Z1`/`z1`,
+//! treated the same), `D`, `k` and Ctrl-C while running; anything else gets
+//! the empty "unsupported" reply.
+//!
+//! Execution goes through a `Debugger`, so continuing runs whole 60 Hz frames
+//! with the timers ticking, as in the SDL frontend, only without waiting for
+//! real time to pass.
+
+use crate::{CpuError, Debugger, CPU};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+/// Registers described by `target.xml`, in protocol order.
+const REGISTER_COUNT: usize = 19;
+const I: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+/// Signal numbers reported in stop replies.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// One debugging session with a connected client.
+pub struct GdbStub {
+    stream: TcpStream,
+    /// Bytes received but not yet parsed.
+    input: VecDeque<u8>,
+    debugger: Debugger,
+    cycles_per_frame: u32,
+}
+
+impl GdbStub {
+    /// Serves the client on `stream`, running `cycles_per_frame` instructions
+    /// per frame while continuing.
+    pub fn new(stream: TcpStream, cycles_per_frame: u32) -> GdbStub {
+        // replies are small and each one is waited for, so don't batch them
+        stream.set_nodelay(true).ok();
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        GdbStub {
+            stream,
+            input: VecDeque::new(),
+            debugger,
+            cycles_per_frame,
+        }
+    }
+
+    /// Answers packets until the client detaches, kills the program or
+    /// disconnects, or the program exits through 00FD.
+    pub fn serve(&mut self, cpu: &mut CPU) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let reply = match packet.first() {
+                Some(b'D') => {
+                    self.send(b"OK")?;
+                    return Ok(());
+                }
+                Some(b'k') => return Ok(()),
+                Some(b'c') | Some(b's') => {
+                    if packet.len() > 1 {
+                        match parse_hex(&packet[1..]) {
+                            Some(pc) => cpu.pc = pc as u16,
+                            None => {
+                                self.send(b"E01")?;
+                                continue;
+                            }
+                        }
+                    }
+                    let reply = self.resume(cpu, packet[0] == b's')?;
+                    if cpu.exited {
+                        self.send(b"W00")?;
+                        return Ok(());
+                    }
+                    reply
+                }
+                _ => self.handle(cpu, &packet),
+            };
+            self.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// The reply to a packet that does not run the CPU.
+    fn handle(&mut self, cpu: &mut CPU, packet: &[u8]) -> Vec<u8> {
+        let command = packet[0];
+        let args = &*String::from_utf8_lossy(&packet[1..]);
+        let reply = match command {
+            b'?' => Some(stop_reply(SIGTRAP)),
+            b'g' => Some((0..REGISTER_COUNT).map(|r| register(cpu, r)).collect()),
+            b'G' => write_registers(cpu, args),
+            b'p' => parse_hex(args.as_bytes())
+                .filter(|&r| r < REGISTER_COUNT)
+                .map(|r| register(cpu, r)),
+            b'P' => args.split_once('=').and_then(|(r, value)| {
+                let r = parse_hex(r.as_bytes())?;
+                set_register(cpu, r, &decode_hex(value)?)?;
+                Some("OK".to_owned())
+            }),
+            b'm' => memory_range(cpu, args).map(|range| encode_hex(&cpu.memory[range])),
+            b'M' => args.split_once(':').and_then(|(range, data)| {
+                let range = memory_range(cpu, range)?;
+                let data = decode_hex(data).filter(|d| d.len() == range.len())?;
+                cpu.memory[range].copy_from_slice(&data);
+                Some("OK".to_owned())
+            }),
+            b'Z' | b'z' => {
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                let address = fields.next().and_then(|a| parse_hex(a.as_bytes()));
+                match (kind, address) {
+                    (Some("0"), Some(address)) | (Some("1"), Some(address)) => {
+                        if command == b'Z' {
+                            self.debugger.add_breakpoint(address as u16, None);
+                        } else {
+                            self.debugger.remove_breakpoint(address as u16);
+                        }
+                        Some("OK".to_owned())
+                    }
+                    // watchpoints are not supported
+                    (Some(_), Some(_)) => return Vec::new(),
+                    _ => None,
+                }
+            }
+            b'H' => Some("OK".to_owned()),
+            b'q' => return query(args).into_bytes(),
+            _ => return Vec::new(),
+        };
+        reply.unwrap_or_else(|| "E01".to_owned()).into_bytes()
+    }
+
+    /// Steps one instruction or continues until a breakpoint, a fault, the end
+    /// of the program or Ctrl-C, returning the stop reply.
+    fn resume(&mut self, cpu: &mut CPU, step: bool) -> io::Result<Vec<u8>> {
+        if step {
+            let signal = match self.debugger.step(cpu) {
+                Ok(_) => SIGTRAP,
+                Err(e) => fault_signal(&e),
+            };
+            return Ok(stop_reply(signal).into_bytes());
+        }
+        self.debugger.resume(cpu);
+        loop {
+            match self.debugger.run_frame(cpu, self.cycles_per_frame) {
+                Ok(Some(_)) => return Ok(stop_reply(SIGTRAP).into_bytes()),
+                Ok(None) => {}
+                Err(e) => {
+                    self.debugger.pause();
+                    return Ok(stop_reply(fault_signal(&e)).into_bytes());
+                }
+            }
+            if cpu.exited {
+                return Ok(Vec::new());
+            }
+            if self.interrupted()? {
+                self.debugger.pause();
+                return Ok(stop_reply(SIGINT).into_bytes());
+            }
+        }
+    }
+
+    /// Checks, without blocking, whether the client sent Ctrl-C (0x03) or
+    /// hung up. Anything else received is kept for `read_packet`.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0; 256];
+        let result = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Ok(true),
+            Ok(n) => {
+                let interrupt = buffer[..n].contains(&0x03);
+                self.input
+                    .extend(buffer[..n].iter().filter(|&&b| b != 0x03));
+                Ok(interrupt)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.input.is_empty() {
+            let mut buffer = [0; 4096];
+            let n = self.stream.read(&mut buffer)?;
+            self.input.extend(&buffer[..n]);
+        }
+        Ok(self.input.pop_front())
+    }
+
+    /// Reads the next packet's data, acknowledging it, or `None` once the
+    /// client disconnects. Acks from the client and stray Ctrl-Cs while
+    /// already stopped are skipped; packets with a bad checksum are nacked.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b'}') => match self.read_byte()? {
+                        None => return Ok(None),
+                        Some(b) => data.push(b ^ 0x20),
+                    },
+                    Some(b) => data.push(b),
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in checksum.iter_mut() {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b) => *digit = b,
+                }
+            }
+            if parse_hex(&checksum) == Some(sum(&data) as usize) {
+                self.stream.write_all(b"+")?;
+                if !data.is_empty() {
+                    return Ok(Some(data));
+                }
+            } else {
+                self.stream.write_all(b"-")?;
+            }
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        for &b in data {
+            // these would end the packet early, so they are escaped
+            if matches!(b, b'$' | b'#' | b'}' | b'*') {
+                packet.extend([b'}', b ^ 0x20]);
+            } else {
+                packet.push(b);
+            }
+        }
+        packet.extend(format!("#{:02x}", sum(&packet[1..])).bytes());
+        self.stream.write_all(&packet)?;
+        self.stream.flush()
+    }
+}
+
+/// The reply to a `q` packet; `args` is everything after the `q`.
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        "PacketSize=1000;qXfer:features:read+".to_owned()
+    } else if args == "Attached" {
+        "1".to_owned()
+    } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        let (offset, length) = match range.split_once(',') {
+            Some((offset, length)) => (parse_hex(offset.as_bytes()), parse_hex(length.as_bytes())),
+            None => (None, None),
+        };
+        match (offset, length) {
+            (Some(offset), Some(length)) => {
+                let xml = TARGET_XML.as_bytes();
+                let start = offset.min(xml.len());
+                let end = start.saturating_add(length).min(xml.len());
+                let more = if end < xml.len() { 'm' } else { 'l' };
+                format!("{}{}", more, &TARGET_XML[start..end])
+            }
+            _ => "E01".to_owned(),
+        }
+    } else {
+        String::new()
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn fault_signal(e: &CpuError) -> u8 {
+    match e {
+        CpuError::UnknownOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+/// Register `r` as little-endian hex.
+fn register(cpu: &CPU, r: usize) -> String {
+    match r {
+        I => encode_hex(&cpu.index_register.to_le_bytes()),
+        PC => encode_hex(&cpu.pc.to_le_bytes()),
+        SP => encode_hex(&[cpu.sp]),
+        _ => encode_hex(&[cpu.registers[r]]),
+    }
+}
+
+/// Sets register `r` from its little-endian bytes. SP must stay within the
+/// stack, as `CPU::load_state` also requires.
+fn set_register(cpu: &mut CPU, r: usize, bytes: &[u8]) -> Option<()> {
+    let word = || match *bytes {
+        [low, high] => Some(u16::from_le_bytes([low, high])),
+        _ => None,
+    };
+    let byte = || match *bytes {
+        [b] => Some(b),
+        _ => None,
+    };
+    match r {
+        I => cpu.index_register = word()?,
+        PC => cpu.pc = word()?,
+        SP => cpu.sp = byte().filter(|&sp| sp as usize <= cpu.stack.len())?,
+        r if r < 16 => cpu.registers[r] = byte()?,
+        _ => return None,
+    }
+    Some(())
+}
+
+fn write_registers(cpu: &mut CPU, args: &str) -> Option<String> {
+    let bytes = decode_hex(args)?;
+    if bytes.len() != 16 + 2 + 2 + 1 {
+        return None;
+    }
+    // SP first, so a rejected value leaves every register as it was
+    set_register(cpu, SP, &bytes[20..])?;
+    cpu.registers.copy_from_slice(&bytes[..16]);
+    set_register(cpu, I, &bytes[16..18])?;
+    set_register(cpu, PC, &bytes[18..20])?;
+    Some("OK".to_owned())
+}
+
+/// Parses `ADDR,LENGTH` into a range of memory, if it is all inside RAM.
+fn memory_range(cpu: &CPU, args: &str) -> Option<std::ops::Range<usize>> {
+    let (address, length) = args.split_once(',')?;
+    let start = parse_hex(address.as_bytes())?;
+    let end = start.checked_add(parse_hex(length.as_bytes())?)?;
+    if end > cpu.memory.len() {
+        return None;
+    }
+    Some(start..end)
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+fn parse_hex(digits: &[u8]) -> Option<usize> {
+    usize::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    // an odd digit at the end has no pair, making the whole string invalid
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
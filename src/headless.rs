@@ -0,0 +1,61 @@
+//! Running a ROM without a window or audio device, shared by `chip8-headless`
+//! and the `chip-8` command line's `trace`, `bench` and `test` subcommands.
+//!
+//! Timers tick once every `cycles_per_frame` instructions, standing in for
+//! the 60 Hz frame a windowed frontend would wait for.
+
+use crate::{CpuError, Platform, Quirks, CPU};
+
+/// Selects the platform or quirks preset `name`, e.g. `schip` or `vip`, as
+/// accepted by `--quirks`. Returns `false`, leaving the CPU alone, for an
+/// unknown name.
+pub fn apply_quirks(cpu: &mut CPU, name: &str) -> bool {
+    if let Some(platform) = Platform::from_name(name) {
+        cpu.set_platform(platform);
+    } else if let Some(quirks) = Quirks::from_name(name) {
+        cpu.quirks = quirks;
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Runs up to `cycles` instructions, ticking the timers at the end of every
+/// frame. `frame` is called with the frame number at the start of each frame,
+/// to set the keypad, and `before` ahead of every instruction. Stops early when
+/// the program exits or faults; returns the number of instructions executed.
+pub fn run(
+    cpu: &mut CPU,
+    cycles: u64,
+    cycles_per_frame: u32,
+    mut frame: impl FnMut(&mut CPU, u64),
+    mut before: impl FnMut(&CPU),
+) -> (u64, Result<(), CpuError>) {
+    let per_frame = cycles_per_frame.max(1) as u64;
+    for cycle in 0..cycles {
+        if cpu.exited {
+            return (cycle, Ok(()));
+        }
+        if cycle % per_frame == 0 {
+            frame(cpu, cycle / per_frame);
+        }
+        before(cpu);
+        if let Err(e) = cpu.step() {
+            return (cycle, Err(e));
+        }
+        if (cycle + 1) % per_frame == 0 {
+            cpu.tick_timers();
+        }
+    }
+    (cycles, Ok(()))
+}
+
+/// The framebuffer as one line of `#` (on) and `.` (off) per row.
+pub fn render_text(cpu: &CPU) -> String {
+    let mut out = String::with_capacity((cpu.width + 1) * cpu.height);
+    for row in cpu.vram.chunks(cpu.width) {
+        out.extend(row.iter().map(|&p| if p != 0 { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
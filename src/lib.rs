@@ -0,0 +1,25 @@
+//! Core CHIP-8 interpreter, usable without any frontend.
+//!
+//! The SDL frontend lives in the `chip-8` binary behind the `sdl` feature and
+//! `chip8-headless` behind the `headless` feature; build with
+//! `--no-default-features` to embed the interpreter on its own. The `config`
+//! feature adds the TOML settings file reader the SDL frontend uses.
+
+pub mod asm;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod gdb;
+pub mod headless;
+pub mod movie;
+pub mod octo;
+pub mod rewind;
+pub mod trace;
+
+pub use cpu::{emulate_cycle, load, load_rom, Access, CpuError, LoadStore, Mode, Platform, Quirks, RomError, Rng, RngKind, StateError, WatchAction, WatchHit, Watchpoint, CPU, FONT_SET};
+pub use debugger::Debugger;
+pub use movie::{Movie, MovieError};
+pub use rewind::Rewind;
+pub use trace::Trace;
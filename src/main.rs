@@ -1,8 +1,18 @@
-pub mod cpu;
-
-use cpu::{emulate_cycle, load_rom, load};
+use chip8_core::debugger;
+use chip8_core::{asm, headless, octo};
+use chip8_core::disasm::{self, Syntax};
+use chip8_core::trace::Format;
+use chip8_core::config::{Config, Settings};
+use chip8_core::{CpuError, Debugger, Movie, Platform, Quirks, Rewind, Rng, Trace, CPU};
+use clap::builder::NonEmptyStringValueParser;
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use std::thread;
 use std::env;
+use std::fs;
+use std::process;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::sync::mpsc;
+use std::collections::HashMap;
 
 extern crate sdl2; 
 
@@ -12,46 +22,268 @@ use sdl2::pixels::Color;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::rect::Rect;
-use sdl2::mixer::{InitFlag, DEFAULT_CHANNELS, AUDIO_S16LSB};
-use std::time::Duration;
-
-
-
-const WIDTH : u32 = 640;
-const HEIGHT : u32 = 320;
+use sdl2::mixer::{Channel, Chunk, InitFlag, DEFAULT_CHANNELS, AUDIO_S16LSB};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+
+
+// delay and sound timers always run at 60 Hz, so the loop runs one frame per tick
+const FRAME_RATE : u32 = 60;
+
+/// A CHIP-8, SUPER-CHIP and XO-CHIP emulator.
+#[derive(Parser)]
+#[command(name = "chip-8", version, arg_required_else_help = true,
+    after_help = "`chip-8 ROM [OPTIONS]` is short for `chip-8 run ROM [OPTIONS]`.")]
+struct Cli{
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command{
+    /// Play a ROM in a window
+    Run(RunArgs),
+    /// Print a listing of a ROM
+    Disasm(DisasmArgs),
+    /// Assemble a Cowgod-style or Octo (.8o) source into a ROM
+    Asm(AsmArgs),
+    /// Show the size of a ROM, the platform it needs and how much of it is code
+    Info(InfoArgs),
+    /// Run a ROM without a window, printing a line per instruction
+    Trace(TraceArgs),
+    /// Measure how many instructions per second the interpreter runs a ROM at
+    Bench(BenchArgs),
+    /// Run a test ROM without a window and print its final screen
+    Test(TestArgs),
+}
+
+/// How the machine is set up, shared by every subcommand that runs a ROM.
+#[derive(Args)]
+struct MachineArgs{
+    /// Platform or quirks preset: chip8, schip, xochip, vip or chip48
+    #[arg(long, value_name = "NAME", value_parser = parse_profile)]
+    quirks: Option<String>,
+    /// Instructions per second [default: 600, or the config file's clock_speed for run]
+    #[arg(long, value_name = "HZ", value_parser = clap::value_parser!(u32).range(1..))]
+    speed: Option<u32>,
+    /// Seed for the CXNN random number generator [default: the clock for run, otherwise 0]
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+}
+
+#[derive(Args)]
+struct RunArgs{
+    #[arg(value_name = "ROM", value_parser = NonEmptyStringValueParser::new())]
+    rom: String,
+    /// Same as --quirks, kept for `chip-8 ROM PRESET`
+    #[arg(value_name = "PRESET", value_parser = parse_profile, hide = true, conflicts_with = "quirks")]
+    preset: Option<String>,
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// Window pixels per CHIP-8 pixel in low resolution [default: 10]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    scale: Option<u32>,
+    /// Start paused, taking debugger commands from the terminal
+    #[arg(long, conflicts_with_all = ["record", "play"])]
+    debug: bool,
+    /// `debug` prints every instruction as it runs [default: the config file's mode, or normal]
+    #[arg(long, value_name = "MODE", value_parser = ["normal", "debug"])]
+    mode: Option<String>,
+    /// Turn the sound off
+    #[arg(long)]
+    mute: bool,
+    /// Record the keypad of every frame to a movie file
+    #[arg(long, value_name = "FILE", conflicts_with = "play")]
+    record: Option<String>,
+    /// Play back a movie file
+    #[arg(long, value_name = "FILE")]
+    play: Option<String>,
+    /// Write a line per executed instruction to FILE
+    #[arg(long, value_name = "FILE")]
+    trace: Option<String>,
+    /// Trace line layout: native, or cadmium to diff against Cadmium's trace
+    #[arg(long, value_name = "NAME", value_parser = parse_trace_format, default_value = "native")]
+    trace_format: Format,
+    /// Settings file to read instead of ./config.toml
+    #[arg(long, value_name = "FILE")]
+    config: Option<String>,
+}
+
+#[derive(Args)]
+struct DisasmArgs{
+    #[arg(value_name = "ROM", value_parser = NonEmptyStringValueParser::new())]
+    rom: String,
+    /// Same as --quirks, kept for `chip-8 disasm ROM PLATFORM`
+    #[arg(value_name = "PLATFORM", value_parser = parse_profile, hide = true, conflicts_with = "quirks")]
+    platform: Option<String>,
+    /// Platform whose instructions to decode: chip8, schip or xochip
+    #[arg(long, value_name = "NAME", value_parser = parse_profile)]
+    quirks: Option<String>,
+    /// Assembly language of the listing: cowgod or octo
+    #[arg(long, default_value = "cowgod", value_parser = parse_syntax)]
+    syntax: Syntax,
+}
+
+#[derive(Args)]
+struct AsmArgs{
+    #[arg(value_name = "SOURCE", value_parser = NonEmptyStringValueParser::new())]
+    source: String,
+    /// Where to write the ROM [default: the source with a .ch8 extension]
+    #[arg(short, long, value_name = "ROM")]
+    output: Option<String>,
+}
+
+#[derive(Args)]
+struct InfoArgs{
+    #[arg(value_name = "ROM", value_parser = NonEmptyStringValueParser::new())]
+    rom: String,
+}
+
+#[derive(Args)]
+struct TraceArgs{
+    #[arg(value_name = "ROM", value_parser = NonEmptyStringValueParser::new())]
+    rom: String,
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// Instructions to run
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    cycles: u64,
+    /// Where to write the trace, `-` for stdout
+    #[arg(short, long, value_name = "FILE", default_value = "-")]
+    output: String,
+    /// Trace line layout: native, or cadmium to diff against Cadmium's trace
+    #[arg(long, value_name = "NAME", value_parser = parse_trace_format, default_value = "native")]
+    format: Format,
+}
+
+#[derive(Args)]
+struct BenchArgs{
+    #[arg(value_name = "ROM", value_parser = NonEmptyStringValueParser::new())]
+    rom: String,
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// Instructions to run
+    #[arg(long, value_name = "N", default_value_t = 10_000_000)]
+    cycles: u64,
+}
+
+#[derive(Args)]
+struct TestArgs{
+    #[arg(value_name = "ROM", value_parser = NonEmptyStringValueParser::new())]
+    rom: String,
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// 60 Hz frames to run
+    #[arg(long, value_name = "N", default_value_t = 600)]
+    frames: u64,
+}
 
 fn main(){
-    //get args
-    let args: Vec<String> = env::args().collect();
-    let file = args[1].to_owned();
-
-    //calculate scale
-    let scale_x = (WIDTH / 64) as u32;
-    let scale_y = (HEIGHT / 32) as u32;
-    
-    
+    let mut args: Vec<String> = env::args().collect();
+    //a ROM where the subcommand should be runs it, as before subcommands existed
+    if args.get(1).is_some_and(|a| {
+        !a.starts_with('-') && a != "help" && Cli::command().find_subcommand(a).is_none()
+    }){
+        args.insert(1, "run".to_owned());
+    }
+    match Cli::parse_from(args).command{
+        Command::Run(args) => run(args),
+        Command::Disasm(args) => print_disassembly(args),
+        Command::Asm(args) => write_assembly(args),
+        Command::Info(args) => print_info(args),
+        Command::Trace(args) => write_trace(args),
+        Command::Bench(args) => bench(args),
+        Command::Test(args) => run_test(args),
+    }
+}
+
+/// `chip-8 run ROM`: plays a ROM in an SDL window.
+fn run(args: RunArgs){
+    //settings given on the command line win over the config file
+    let cli = Settings {
+        clock_speed: args.machine.speed,
+        mode: args.mode,
+        quirks: args.machine.quirks.or(args.preset),
+        scale: args.scale,
+        mute: if args.mute { Some(true) } else { None },
+        ..Settings::default()
+    };
+    let file = args.rom;
+    let settings = load_config(args.config.as_deref()).for_rom(&file).merge(&cli);
+    let profile = settings.quirks.clone();
+    let (seed, debug) = (args.machine.seed, args.debug);
+    let (record_path, play_path, trace_path) = (args.record, args.play, args.trace);
+    let trace_format = args.trace_format;
 
 
     //cpu
-    let mut cpu = load();
-    if file == String::from(""){
-     
-    }else{
-        cpu = load_rom(cpu, file.clone());
-    }
+    let mut cpu = CPU::new();
+    apply_profile(&mut cpu, profile.as_deref());
+    cpu.set_mode(settings.mode());
+    seed_rng(&mut cpu, seed);
+    load_or_exit(&mut cpu, &file);
     
 
     
 
-    let clock_speed = 600; //in hz
-
-    let mut delay = 1000000 / clock_speed; // delay in us
+    let clock_speed: u32 = settings.clock_speed(); //in hz
+
+    let mut cycles_per_frame = (clock_speed / FRAME_RATE).max(1); // instructions run per 60 Hz frame, at least one
+    let frame_time = Duration::from_secs(1) / FRAME_RATE;
+
+    //movie being played back and the next frame to play, or being recorded
+    let mut playback: Option<(Movie, usize)> = play_path.as_ref().map(|path| {
+        let movie = std::fs::read(path).map_err(|e| e.to_string())
+            .and_then(|data| Movie::from_bytes(&data).map_err(|e| e.to_string()))
+            .and_then(|movie| movie.start(&mut cpu).map(|()| movie).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Error playing movie {}: {}", path, e);
+                process::exit(1);
+            });
+        cycles_per_frame = movie.cycles_per_frame;
+        (movie, 0)
+    });
+    let mut recording = record_path.as_ref().map(|_| Movie::new(&cpu, cycles_per_frame));
+
+    //set once the cpu faults, halting emulation until reset
+    let mut fault: Option<CpuError> = None;
+
+    //quick-save slot used by F5/F9, changed with F6/F7
+    let mut slot: u32 = 0;
+
+    //the last 30 seconds of frames, played back while backspace is held
+    let mut rewind = Rewind::default();
+    let mut rewinding = false;
+    rewind.push(&cpu);
+
+    //runs the cpu each frame; with --debug it starts paused and takes commands from a terminal REPL
+    let mut debugger = Debugger::new();
+    let commands = if debug{
+        debugger.pause();
+        Some(spawn_repl())
+    }else{
+        None
+    };
+    //--trace FILE writes a line per executed instruction, for diffing against other emulators
+    if let Some(path) = &trace_path{
+        let file = std::fs::File::create(path).unwrap_or_else(|e| {
+            eprintln!("Error: cannot write trace to {}: {}", path, e);
+            process::exit(1);
+        });
+        debugger.set_trace(Some(Trace::with_format(Box::new(io::BufWriter::new(file)), trace_format)));
+    }
 
     //sdl and gfx
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let title = format!("Chip-8: {}", file);
-    let window = video_subsystem.window(&title, WIDTH, HEIGHT)
+    //window size follows the scale of a 64x32 screen; SUPER-CHIP hires halves it per pixel
+    let (width, height) = (64 * settings.scale(), 32 * settings.scale());
+    //colors for each vram value: background, plane 1, plane 2 and both planes (XO-CHIP)
+    let palette = settings.palette().map(|(r, g, b)| Color::RGB(r, g, b));
+    //keyboard key for each keypad key
+    let keymap = keymap_or_exit(&settings);
+    let window = video_subsystem.window(&title, width, height)
         .position_centered()
         .build()
         
@@ -70,6 +302,8 @@ fn main(){
         InitFlag::MP3 | InitFlag::FLAC | InitFlag::MOD | InitFlag::OGG
     ).unwrap();
     let music = sdl2::mixer::Music::from_file("./sfx/beep.wav").unwrap();
+    //the XO-CHIP audio pattern currently playing, kept alive until replaced
+    let mut pattern_chunk: Option<Chunk> = None;
     
  
     //events
@@ -80,8 +314,9 @@ fn main(){
     
     //emu loop
     'running: loop {
+            let frame_start = Instant::now();
             //clr screen
-            canvas.set_draw_color(Color::RGB(0,0,0));
+            canvas.set_draw_color(palette[0]);
             canvas.clear();
             //events
             for event in event_pump.poll_iter() {
@@ -89,43 +324,79 @@ fn main(){
                     Event::Quit {..} => {
                         break 'running;
                     },
+                    //handle user input. 1 is on, 0 is off
+                    Event::KeyDown { keycode: Some(keycode), .. } if keymap.contains_key(&keycode) => {
+                        cpu.key[keymap[&keycode]] = 1;
+                    },
+                    Event::KeyUp { keycode: Some(keycode), .. } if keymap.contains_key(&keycode) => {
+                        cpu.key[keymap[&keycode]] = 0;
+                    },
                     Event::KeyDown { keycode, .. } => {
                         match keycode  {
                             Some(Keycode::Escape) => break 'running,
-                                //handle user input. 1 is on, 0 is off. Change key addresses per key
-                            Some(Keycode::Num1) => cpu.key[0x1] =  1,
-                            Some(Keycode::Num2) => cpu.key[0x2] =  1,
-                            Some(Keycode::Num3) => cpu.key[0x3] =  1,
-                            Some(Keycode::Num4) => cpu.key[0xC] =  1,
-
-                            Some(Keycode::Q) => cpu.key[0x4] =  1,
-                            Some(Keycode::W) => cpu.key[0x5] =  1,
-                            Some(Keycode::E) => cpu.key[0x6] =  1,
-                            Some(Keycode::R) => cpu.key[0xD] =  1,
-
-                            Some(Keycode::A) => cpu.key[0x7] =  1,
-                            Some(Keycode::S) => cpu.key[0x8] =  1,
-                            Some(Keycode::D) => cpu.key[0x9] =  1,
-                            Some(Keycode::F) => cpu.key[0xE] =  1,
-
-                            Some(Keycode::Z) => cpu.key[0xA] =  1,
-                            Some(Keycode::X) => cpu.key[0x0] =  1,
-                            Some(Keycode::C) => cpu.key[0xB] =  1,
-                            Some(Keycode::V) => cpu.key[0xF] =  1,
+                            Some(Keycode::P) | Some(Keycode::F9) | Some(Keycode::Backspace) | Some(Keycode::M) | Some(Keycode::L)
+                                if recording.is_some() || playback.is_some() => {
+                                //anything that breaks the movie's frame-by-frame replay
+                                println!("• Not available while a movie is recording or playing");
+                            }
                             Some(Keycode::P) => {
-                                cpu = load();
-                                cpu = load_rom(cpu, file.clone());
+                                //watchpoints belong to the debugging session, like breakpoints
+                                let watchpoints = std::mem::take(&mut cpu.watchpoints);
+                                cpu = CPU::new();
+                                cpu.watchpoints = watchpoints;
+                                apply_profile(&mut cpu, profile.as_deref());
+                                cpu.set_mode(settings.mode());
+                                seed_rng(&mut cpu, seed);
+                                load_or_exit(&mut cpu, &file);
+                                fault = None;
+                                rewind.clear();
+                                rewind.push(&cpu);
                             },
-                            Some(Keycode::M) => {   //Decrease emulation speed
-                                delay += 1;
-                                
-
+                            Some(Keycode::F5) => {  //Quick-save
+                                let path = state_path(&file, slot);
+                                match std::fs::write(&path, cpu.save_state()){
+                                    Ok(()) => println!("• Saved state to {}", path),
+                                    Err(e) => eprintln!("Error saving state to {}: {}", path, e),
+                                }
                             }
-                            Some(Keycode::L) => {
-                                if delay != 0{
-                                    delay -= 1; //Increase emulation speed        
+                            Some(Keycode::F9) => {  //Quick-load
+                                let path = state_path(&file, slot);
+                                let result = std::fs::read(&path).map_err(|e| e.to_string())
+                                    .and_then(|data| cpu.load_state(&data).map_err(|e| e.to_string()));
+                                match result{
+                                    Ok(()) => {
+                                        println!("• Loaded state from {}", path);
+                                        canvas.window_mut().set_title(&title).unwrap();
+                                        fault = None;
+                                        rewind.clear();
+                                        rewind.push(&cpu);
+                                    }
+                                    Err(e) => eprintln!("Error loading state from {}: {}", path, e),
                                 }
-                                
+                            }
+                            Some(Keycode::F6) => {  //Previous quick-save slot
+                                slot = (slot + 9) % 10;
+                                println!("• State slot {}", slot);
+                            }
+                            Some(Keycode::F7) => {  //Next quick-save slot
+                                slot = (slot + 1) % 10;
+                                println!("• State slot {}", slot);
+                            }
+                            Some(Keycode::Backspace) => rewinding = true,
+                            Some(Keycode::F10) => {  //Pause or resume
+                                if debugger.is_paused(){
+                                    debugger.resume(&cpu);
+                                    println!("• Resumed");
+                                }else{
+                                    debugger.pause();
+                                    println!("• Paused\n{}", debugger::registers(&cpu));
+                                }
+                            }
+                            Some(Keycode::M) => {   //Decrease emulation speed
+                                cycles_per_frame = cycles_per_frame.saturating_sub(1).max(1);
+                            }
+                            Some(Keycode::L) => {   //Increase emulation speed
+                                cycles_per_frame += 1;
                             }
                             _ => {}
                                 
@@ -134,62 +405,110 @@ fn main(){
                         }
                             
                     },
-                    Event::KeyUp { keycode, .. } =>{
-                        match keycode{
-                            Some(Keycode::Num1) => cpu.key[0x1] =  0,
-                            Some(Keycode::Num2) => cpu.key[0x2] =  0,
-                            Some(Keycode::Num3) => cpu.key[0x3] =  0,
-                            Some(Keycode::Num4) => cpu.key[0xC] =  0,
-
-                            Some(Keycode::Q) => cpu.key[0x4] =  0,
-                            Some(Keycode::W) => cpu.key[0x5] =  0,
-                            Some(Keycode::E) => cpu.key[0x6] =  0,
-                            Some(Keycode::R) => cpu.key[0xD] =  0,
-
-                            Some(Keycode::A) => cpu.key[0x7] =  0,
-                            Some(Keycode::S) => cpu.key[0x8] =  0,
-                            Some(Keycode::D) => cpu.key[0x9] =  0,
-                            Some(Keycode::F) => cpu.key[0xE] =  0,
-
-                            Some(Keycode::Z) => cpu.key[0xA] =  0,
-                            Some(Keycode::X) => cpu.key[0x0] =  0,
-                            Some(Keycode::C) => cpu.key[0xB] =  0,
-                            Some(Keycode::V) => cpu.key[0xF] =  0,
-                            _ => {}
-                        }
-
+                    Event::KeyUp { keycode: Some(Keycode::Backspace), .. } =>{
+                        rewinding = false;
                     },
                         
                     _ => {}
                 }
             }
                 
-            //emulate cpu cycle
-            cpu = emulate_cycle(cpu);
+            //debugger commands typed into the terminal
+            if let Some(commands) = &commands{
+                for line in commands.try_iter(){
+                    match debugger.execute(&mut cpu, &line){
+                        Ok(out) if out.is_empty() => {}
+                        Ok(out) => println!("{}", out),
+                        Err(e) => println!("error: {}", e),
+                    }
+                    prompt();
+                }
+            }
+
+            if rewinding{
+                //step back one frame per tick, keeping the keys that are held right now
+                let keys = cpu.key;
+                if rewind.rewind(&mut cpu){
+                    cpu.key = keys;
+                    if fault.take().is_some(){
+                        canvas.window_mut().set_title(&title).unwrap();
+                    }
+                }
+            }else if fault.is_none() && !debugger.is_paused(){
+                if let Some((movie, frame)) = &mut playback{
+                    if let Some(keys) = movie.keys(*frame){
+                        cpu.key = keys;
+                        *frame += 1;
+                    }
+                }
+                if let Some(movie) = &mut recording{
+                    movie.record(&cpu.key);
+                }
+                //emulate one frame worth of cpu cycles, then tick the timers
+                match debugger.run_frame(&mut cpu, cycles_per_frame){
+                    Err(e) => {
+                        eprintln!("CPU fault: {}", e);
+                        eprintln!("{}", debugger::registers(&cpu));
+                        eprintln!("Emulation halted, press P to reset or hold backspace to rewind");
+                        canvas.window_mut().set_title(&format!("{} - halted: {}", title, e)).unwrap();
+                        fault = Some(e);
+                    }
+                    Ok(stop) => {
+                        if let Some(stop) = stop{
+                            println!("\n{}\n{}", stop, debugger::registers(&cpu));
+                            prompt();
+                        }
+                        rewind.push(&cpu);
+                    }
+                }
+                //hand control back to the keyboard once the movie is over
+                if playback.as_ref().is_some_and(|(movie, frame)| *frame == movie.len()){
+                    let (movie, _) = playback.take().unwrap();
+                    match movie.verify(&cpu){
+                        Ok(()) => println!("• Movie finished in sync"),
+                        Err(e) => eprintln!("Movie {}", e),
+                    }
+                }
+            }
+            for hit in debugger.take_log(){
+                println!("watch: {}", hit);
+            }
 
                 //draw
             
 
 
             //sound
-            if cpu.audio_play{
-                
+            if settings.mute(){
+                cpu.audio_play = false;
+            }else if cpu.platform == Platform::XoChip{
+                //XO-CHIP plays its audio pattern for as long as the sound timer runs
+                if cpu.audio_timer > 0 && !Channel(0).is_playing(){
+                    let duration_ms = cpu.audio_timer as u32 * 1000 / FRAME_RATE;
+                    let samples = cpu.audio_samples(frequency as u32, (frequency as u32 * duration_ms / 1000) as usize);
+                    let stereo: Vec<i16> = samples.iter().flat_map(|s| [*s, *s]).collect();
+                    let chunk = pattern_chunk.insert(Chunk::from_raw_buffer(stereo.into_boxed_slice()).unwrap());
+                    Channel(0).play(chunk, 0).unwrap();
+                }
+                cpu.audio_play = false;
+            }else if cpu.audio_play{
                 music.play(1).unwrap();
                 cpu.audio_play = false;
             }
 
             //gfx
             if cpu.draw{
-                for x in 0..64{
-                    for y in 0..32{
-                        
-                        canvas.set_draw_color(Color::WHITE);
-                        if cpu.vram[(y * 64) as usize + x as usize] != 0{
-                            
-                            canvas.fill_rect(Rect::new((x * scale_x) as i32, (y * scale_y) as i32, scale_x, scale_y)).unwrap();
+                //the framebuffer changes size when SUPER-CHIP switches resolution
+                let scale_x = width / cpu.width as u32;
+                let scale_y = height / cpu.height as u32;
+                for y in 0..cpu.height{
+                    for x in 0..cpu.width{
+                        let pixel = cpu.vram[y * cpu.width + x] as usize & 0x3;
+                        if pixel != 0{
+                            canvas.set_draw_color(palette[pixel]);
+                            canvas.fill_rect(Rect::new(x as i32 * scale_x as i32, y as i32 * scale_y as i32, scale_x, scale_y)).unwrap();
                         }
                     }
-                    
                 }
                 //cpu.draw = false;
             }
@@ -197,8 +516,263 @@ fn main(){
             canvas.present();
             
             //emulate speed
-            thread::sleep(Duration::from_micros(delay));
+            if let Some(remaining) = frame_time.checked_sub(frame_start.elapsed()){
+                thread::sleep(remaining);
+            }
         }  
+
+    if let (Some(path), Some(mut movie)) = (record_path, recording){
+        movie.finish(&cpu);
+        match std::fs::write(&path, movie.to_bytes()){
+            Ok(()) => println!("• Saved movie of {} frames to {}", movie.len(), path),
+            Err(e) => eprintln!("Error saving movie to {}: {}", path, e),
+        }
+    }
+    if let (Some(path), Some(trace)) = (trace_path, debugger.set_trace(None)){
+        if let Err(e) = trace.finish(){
+            eprintln!("Error writing trace to {}: {}", path, e);
+        }
+    }
  
     
-}
\ No newline at end of file
+}
+
+/// Reads the config file given with --config, or ./config.toml if there is one.
+fn load_config(path: Option<&str>) -> Config{
+    let path = match path{
+        Some(path) => path,
+        None if std::path::Path::new("config.toml").exists() => "config.toml",
+        None => return Config::default(),
+    };
+    Config::load(path).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    })
+}
+
+/// Maps each configured keyboard key to its keypad key.
+fn keymap_or_exit(settings: &Settings) -> HashMap<Keycode, usize>{
+    settings.keys().iter().enumerate().map(|(key, name)| {
+        let keycode = Keycode::from_name(name).unwrap_or_else(|| {
+            eprintln!("Error: unknown key name {:?} for keypad key {:X}", name, key);
+            process::exit(1);
+        });
+        (keycode, key)
+    }).collect()
+}
+
+/// Checks a platform or quirks preset name given on the command line.
+fn parse_profile(name: &str) -> Result<String, String>{
+    if Platform::from_name(name).is_some() || Quirks::from_name(name).is_some(){
+        Ok(name.to_owned())
+    }else{
+        Err("expected chip8, schip, xochip, vip or chip48".to_owned())
+    }
+}
+
+fn parse_syntax(name: &str) -> Result<Syntax, String>{
+    Syntax::from_name(name).ok_or_else(|| "expected cowgod or octo".to_owned())
+}
+
+fn parse_trace_format(name: &str) -> Result<Format, String>{
+    Format::from_name(name).ok_or_else(|| "expected native or cadmium".to_owned())
+}
+
+/// Selects the platform or quirks preset named on the command line or in the config, e.g.
+/// `schip` or `vip`. Both check the name first.
+fn apply_profile(cpu: &mut CPU, profile: Option<&str>){
+    if let Some(name) = profile{
+        headless::apply_quirks(cpu, name);
+    }
+}
+
+/// `chip-8 disasm ROM`: prints a listing of the ROM.
+fn print_disassembly(args: DisasmArgs){
+    //quirks presets such as vip run the CHIP-8 instruction set
+    let platform = args.quirks.or(args.platform)
+        .and_then(|name| Platform::from_name(&name))
+        .unwrap_or(Platform::Chip8);
+    let rom = read_or_exit(&args.rom);
+    print!("{}", disasm::disassemble(&rom, platform, args.syntax));
+}
+
+/// `chip-8 asm SOURCE`: assembles Cowgod-style or Octo source into a ROM, by default
+/// next to the source with a .ch8 extension.
+fn write_assembly(args: AsmArgs){
+    let source = &args.source;
+    let output = args.output.unwrap_or_else(|| {
+        std::path::Path::new(source).with_extension("ch8").to_string_lossy().into_owned()
+    });
+    if output == *source{
+        eprintln!("Error: refusing to overwrite the source {}, pass -o ROM", source);
+        process::exit(1);
+    }
+    //Octo sources get the Octo compiler, everything else the Cowgod assembler
+    let rom = if source.ends_with(".8o"){
+        std::fs::read_to_string(source)
+            .map_err(|e| format!("cannot read {}: {}", source, e))
+            .and_then(|text| octo::compile(&text).map_err(|e| format!("{}: {}", source, e)))
+    }else{
+        asm::assemble_file(source).map_err(|e| e.to_string())
+    };
+    let rom = rom.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+    if let Err(e) = std::fs::write(&output, &rom){
+        eprintln!("Error: cannot write {}: {}", output, e);
+        process::exit(1);
+    }
+    println!("• Assembled {} bytes to {}", rom.len(), output);
+}
+
+/// `chip-8 info ROM`: describes a ROM without running it.
+fn print_info(args: InfoArgs){
+    let rom = read_or_exit(&args.rom);
+    let code = disasm::trace(&rom, Platform::XoChip);
+    let code_bytes: usize = code.values().map(|i| i.size() as usize).sum();
+    //the oldest platform that decodes every reachable instruction the same way
+    let decodes_on = |platform| code.iter().all(|(&addr, instruction)| {
+        disasm::Instruction::decode(&rom[addr as usize - 0x200..], platform) == Some(*instruction)
+    });
+    let platform = if rom.len() > 0x1000 - 0x200{
+        "xochip"
+    }else if decodes_on(Platform::Chip8){
+        "chip8"
+    }else if decodes_on(Platform::SuperChip){
+        "schip"
+    }else{
+        "xochip"
+    };
+    println!("file: {}", args.rom);
+    println!("size: {} bytes", rom.len());
+    println!("platform: {}", platform);
+    println!("code: {} instructions ({} bytes)", code.len(), code_bytes);
+    println!("data: {} bytes", rom.len().saturating_sub(code_bytes));
+}
+
+/// `chip-8 trace ROM`: runs the ROM with no keys held, writing a trace line per instruction.
+fn write_trace(args: TraceArgs){
+    let mut cpu = headless_cpu(&args.rom, &args.machine);
+    let out: Box<dyn Write> = if args.output == "-"{
+        Box::new(BufWriter::new(io::stdout()))
+    }else{
+        match fs::File::create(&args.output){
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("Error: cannot write trace to {}: {}", args.output, e);
+                process::exit(1);
+            }
+        }
+    };
+    let mut trace = Trace::with_format(out, args.format);
+    let per_frame = cycles_per_frame(&args.machine);
+    let (_, result) = headless::run(&mut cpu, args.cycles, per_frame, |_, _| {}, |cpu| trace.record(cpu));
+    if let Err(e) = trace.finish(){
+        eprintln!("Error: cannot write trace to {}: {}", args.output, e);
+        process::exit(1);
+    }
+    exit_on_fault(&cpu, result);
+}
+
+/// `chip-8 bench ROM`: times the interpreter running the ROM with no keys held.
+fn bench(args: BenchArgs){
+    let mut cpu = headless_cpu(&args.rom, &args.machine);
+    let per_frame = cycles_per_frame(&args.machine);
+    let start = Instant::now();
+    let (cycles, result) = headless::run(&mut cpu, args.cycles, per_frame, |_, _| {}, |_| {});
+    let elapsed = start.elapsed().as_secs_f64();
+    exit_on_fault(&cpu, result);
+    let per_second = cycles as f64 / elapsed.max(1e-9);
+    let speed = args.machine.speed.unwrap_or(600);
+    println!("{} instructions in {:.3} s", cycles, elapsed);
+    println!("{:.0} instructions per second, {:.0}x real time at {} Hz", per_second, per_second / speed as f64, speed);
+}
+
+/// `chip-8 test ROM`: runs a test ROM with no keys held and prints its screen, since test
+/// ROMs report their results there.
+fn run_test(args: TestArgs){
+    let mut cpu = headless_cpu(&args.rom, &args.machine);
+    let per_frame = cycles_per_frame(&args.machine);
+    let cycles = args.frames.saturating_mul(per_frame as u64);
+    let (_, result) = headless::run(&mut cpu, cycles, per_frame, |_, _| {}, |_| {});
+    print!("{}", headless::render_text(&cpu));
+    exit_on_fault(&cpu, result);
+}
+
+/// A CPU set up by the machine options with `rom` loaded. The RNG is seeded with 0 unless
+/// --seed is given, so runs repeat exactly.
+fn headless_cpu(rom: &str, machine: &MachineArgs) -> CPU{
+    let mut cpu = CPU::new();
+    apply_profile(&mut cpu, machine.quirks.as_deref());
+    cpu.rng = Rng::new(machine.seed.unwrap_or(0));
+    if let Err(e) = cpu.load_rom(rom){
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+    cpu
+}
+
+fn cycles_per_frame(machine: &MachineArgs) -> u32{
+    (machine.speed.unwrap_or(600) / FRAME_RATE).max(1)
+}
+
+fn exit_on_fault(cpu: &CPU, result: Result<(), CpuError>){
+    if let Err(e) = result{
+        eprintln!("CPU fault at {:#05X}: {}", cpu.pc, e);
+        process::exit(1);
+    }
+}
+
+/// Reads the file at `path`, exiting with an error message if it cannot be read.
+fn read_or_exit(path: &str) -> Vec<u8>{
+    fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Error: cannot read {}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+/// Reads debugger commands from stdin on their own thread, so the window keeps
+/// running while the terminal waits for input.
+fn spawn_repl() -> mpsc::Receiver<String>{
+    let (tx, rx) = mpsc::channel();
+    println!("Debugger paused at start, type `help` for commands or `c` to run");
+    prompt();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok){
+            if tx.send(line).is_err(){
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn prompt(){
+    print!("(chip-8) ");
+    io::stdout().flush().unwrap();
+}
+
+/// Seeds the CXNN random number generator with `seed`, or from the clock if none was given.
+fn seed_rng(cpu: &mut CPU, seed: Option<u64>){
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+    });
+    cpu.rng = Rng::new(seed);
+}
+
+/// Where quick-save `slot` for `file` is stored, next to the ROM.
+fn state_path(file: &str, slot: u32) -> String{
+    format!("{}.state{}", file, slot)
+}
+
+/// Loads `file` into `cpu`, exiting with an error message if it cannot be loaded.
+fn load_or_exit(cpu: &mut CPU, file: &str){
+    match cpu.load_rom(file){
+        Ok(size) => println!("• Loaded ROM: {} ({} bytes)", file, size),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
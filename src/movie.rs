@@ -0,0 +1,241 @@
+//! Input movies: the keypad state of every frame of a run, recorded so the run
+//! can be replayed bit-exactly for bug reports and regression tests.
+//!
+//! A movie stores everything besides input that decides how a run goes: the
+//! platform, quirks, RNG state and instructions per frame, plus a hash of the
+//! ROM. Once the recording is finished it also stores a hash of the final CPU
+//! state, which playback compares against to detect a desync.
+
+use crate::cpu::{
+    fnv1a, platform_from_id, platform_id, quirks_bits, quirks_from_bits, rng_kind_from_id,
+    rng_kind_id, StateReader,
+};
+use crate::{CpuError, Platform, Quirks, Rng, StateError, CPU};
+use std::error::Error;
+use std::fmt;
+
+/// First bytes of every movie file.
+pub const MOVIE_MAGIC: [u8; 4] = *b"CH8M";
+/// Version written by `Movie::to_bytes`.
+pub const MOVIE_VERSION: u16 = 1;
+
+/// Why a movie could not be read or played back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data does not start with `MOVIE_MAGIC`.
+    BadMagic,
+    /// The movie was written by a newer or unknown version of the format.
+    UnsupportedVersion(u16),
+    /// The data ends before the movie does.
+    Truncated,
+    /// A field holds a value no movie could contain.
+    Invalid(&'static str),
+    /// The loaded ROM is not the one the movie was recorded with.
+    RomMismatch { expected: u64, actual: u64 },
+    /// Playback ended in a different state than the recording.
+    Desync { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "not a movie file"),
+            MovieError::UnsupportedVersion(v) => write!(f, "unsupported movie version {}", v),
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::Invalid(field) => write!(f, "movie has an invalid {}", field),
+            MovieError::RomMismatch { expected, actual } => write!(
+                f,
+                "movie was recorded with a different ROM (hash {:016x}, loaded {:016x})",
+                expected, actual
+            ),
+            MovieError::Desync { expected, actual } => write!(
+                f,
+                "playback desynced: final state hash {:016x}, recorded {:016x}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(e: StateError) -> MovieError {
+        match e {
+            StateError::BadMagic => MovieError::BadMagic,
+            StateError::UnsupportedVersion(v) => MovieError::UnsupportedVersion(v),
+            StateError::Truncated => MovieError::Truncated,
+            StateError::Invalid(field) => MovieError::Invalid(field),
+        }
+    }
+}
+
+/// A recorded run. Record one by creating it right after loading the ROM and
+/// calling `record` with the keypad before every frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// The RNG as it was when recording started.
+    pub rng: Rng,
+    pub cycles_per_frame: u32,
+    /// FNV-1a hash of the ROM bytes.
+    pub rom_hash: u64,
+    /// Keypad state of each frame; bit N is set while key N is held.
+    pub frames: Vec<u16>,
+    /// `CPU::state_hash` after the last frame, once `finish` has been called.
+    pub final_hash: Option<u64>,
+}
+
+impl Movie {
+    /// Starts recording a run of the ROM loaded in `cpu`, which should not have
+    /// executed any instructions yet.
+    pub fn new(cpu: &CPU, cycles_per_frame: u32) -> Movie {
+        Movie {
+            platform: cpu.platform,
+            quirks: cpu.quirks,
+            rng: cpu.rng,
+            cycles_per_frame,
+            rom_hash: rom_hash(cpu),
+            frames: Vec::new(),
+            final_hash: None,
+        }
+    }
+
+    /// Appends the keypad state for the next frame.
+    pub fn record(&mut self, keys: &[u8; 16]) {
+        let mask = keys
+            .iter()
+            .enumerate()
+            .filter(|(_, &k)| k != 0)
+            .fold(0, |mask, (i, _)| mask | 1 << i);
+        self.frames.push(mask);
+    }
+
+    /// Ends the recording, remembering the state `cpu` finished in.
+    pub fn finish(&mut self, cpu: &CPU) {
+        self.final_hash = Some(cpu.state_hash());
+    }
+
+    /// Number of recorded frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether no frames have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The keypad state to apply before `frame`, or `None` past the end.
+    pub fn keys(&self, frame: usize) -> Option<[u8; 16]> {
+        let mask = *self.frames.get(frame)?;
+        let mut keys = [0; 16];
+        for (i, key) in keys.iter_mut().enumerate() {
+            *key = (mask >> i & 1) as u8;
+        }
+        Some(keys)
+    }
+
+    /// Prepares `cpu`, with the ROM already loaded, for playback: checks it is
+    /// the recorded ROM and restores the recorded platform, quirks and RNG.
+    pub fn start(&self, cpu: &mut CPU) -> Result<(), MovieError> {
+        let actual = rom_hash(cpu);
+        if actual != self.rom_hash {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_hash,
+                actual,
+            });
+        }
+        cpu.set_platform(self.platform);
+        cpu.quirks = self.quirks;
+        cpu.rng = self.rng;
+        Ok(())
+    }
+
+    /// Plays every frame of the movie on a CPU prepared by `start`.
+    pub fn play(&self, cpu: &mut CPU) -> Result<(), CpuError> {
+        for frame in 0..self.len() {
+            cpu.key = self.keys(frame).unwrap();
+            cpu.run_frame(self.cycles_per_frame)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that `cpu` ended playback in the recorded state. Movies that were
+    /// never finished have nothing to check against and always pass.
+    pub fn verify(&self, cpu: &CPU) -> Result<(), MovieError> {
+        match self.final_hash {
+            Some(expected) if expected != cpu.state_hash() => Err(MovieError::Desync {
+                expected,
+                actual: cpu.state_hash(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Serializes the movie: `MOVIE_MAGIC`, a little-endian `u16` version, the
+    /// run settings and then one `u16` key mask per frame.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(48 + self.frames.len() * 2);
+        out.extend_from_slice(&MOVIE_MAGIC);
+        out.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        out.push(platform_id(self.platform));
+        out.extend_from_slice(&quirks_bits(self.quirks).to_le_bytes());
+        out.push(rng_kind_id(self.rng.kind()));
+        out.extend_from_slice(&self.rng.state().to_le_bytes());
+        out.extend_from_slice(&self.cycles_per_frame.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+        out.push(self.final_hash.is_some() as u8);
+        out.extend_from_slice(&self.final_hash.unwrap_or(0).to_le_bytes());
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for mask in &self.frames {
+            out.extend_from_slice(&mask.to_le_bytes());
+        }
+        out
+    }
+
+    /// Reads a movie written by `to_bytes`.
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        let mut r = StateReader::new(data);
+        if r.bytes(4)? != MOVIE_MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let platform = platform_from_id(r.u8()?)?;
+        let quirks = quirks_from_bits(r.u16()?)?;
+        let rng_kind = rng_kind_from_id(r.u8()?)?;
+        let rng = Rng::from_state(rng_kind, r.u64()?);
+        let cycles_per_frame = r.u32()?;
+        if cycles_per_frame == 0 {
+            return Err(MovieError::Invalid("cycles per frame"));
+        }
+        let rom_hash = r.u64()?;
+        let finished = r.flag()?;
+        let final_hash = Some(r.u64()?).filter(|_| finished);
+        let frames = (0..r.u32()?)
+            .map(|_| r.u16())
+            .collect::<Result<Vec<u16>, StateError>>()?;
+        if !r.is_empty() {
+            return Err(MovieError::Invalid("length"));
+        }
+
+        Ok(Movie {
+            platform,
+            quirks,
+            rng,
+            cycles_per_frame,
+            rom_hash,
+            frames,
+            final_hash,
+        })
+    }
+}
+
+fn rom_hash(cpu: &CPU) -> u64 {
+    fnv1a(&cpu.memory[0x200..0x200 + cpu.rom_size])
+}
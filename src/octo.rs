@@ -0,0 +1,881 @@
+//! Compiles Octo assembly language (`.8o` files) into ROMs for `CPU::load_rom`.
+//!
+//! Octo source is a stream of whitespace-separated tokens; `#` starts a
+//! comment. Supported are labels (`: name`), `:const`, `:alias`, `:calc`,
+//! `:macro`, `:byte`, `:call`, `:unpack`, `:next` and `:org`, structured
+//! control flow (`if ... then`, `if ... begin ... else ... end` and
+//! `loop ... while ... again`), the SUPER-CHIP instructions and the XO-CHIP
+//! extensions. `:breakpoint` and `:monitor` are accepted and ignored.
+//!
+//! As in Octo, execution starts at the `main` label: unless `: main` is the
+//! first thing in the program, 0x200 holds a jump to it. Labels may be used
+//! before they are defined wherever an address is expected; constants must be
+//! defined first. `:calc` expressions have no operator precedence and are
+//! evaluated right to left, so `{ 2 * 3 + 1 }` is 8.
+
+use crate::disasm::START;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+/// How many macro expansions a program may make before the compiler assumes
+/// a macro expands itself forever.
+const MAX_EXPANSIONS: usize = 0x10000;
+
+/// An error in the source, with the line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OctoError {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for OctoError {}
+
+/// Compiles Octo `source` into a ROM to be loaded at 0x200.
+pub fn compile(source: &str) -> Result<Vec<u8>, OctoError> {
+    let mut compiler = Compiler::new(tokenize(source));
+    compiler.run().map_err(|message| OctoError {
+        line: compiler.line,
+        message,
+    })?;
+    Ok(compiler.rom)
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+/// Splits source into tokens at whitespace, dropping comments. Braces and
+/// parentheses are tokens of their own even without spaces around them.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap();
+        for word in code.split_whitespace() {
+            let mut start = 0;
+            for (j, c) in word.char_indices() {
+                if "{}()".contains(c) {
+                    if start < j {
+                        tokens.push_back(Token {
+                            text: word[start..j].to_owned(),
+                            line: i + 1,
+                        });
+                    }
+                    tokens.push_back(Token {
+                        text: c.to_string(),
+                        line: i + 1,
+                    });
+                    start = j + 1;
+                }
+            }
+            if start < word.len() {
+                tokens.push_back(Token {
+                    text: word[start..].to_owned(),
+                    line: i + 1,
+                });
+            }
+        }
+    }
+    tokens
+}
+
+/// How a label's address is written into an instruction once it is known.
+#[derive(Debug, Clone, Copy)]
+enum Patch {
+    /// The low 12 bits of the opcode at the address.
+    Nnn,
+    /// A 16-bit big-endian word.
+    Word,
+    /// The byte `nibble << 4 | address >> 8`, for `:unpack`.
+    HighNibble(u8),
+    HighByte,
+    LowByte,
+}
+
+/// A label used before it was defined.
+struct Fixup {
+    at: usize,
+    patch: Patch,
+    label: String,
+    line: usize,
+}
+
+/// An open `if ... begin`, `else` or `loop`.
+enum Block {
+    /// The address of the jump to patch when the block ends.
+    Begin(usize),
+    Else(usize),
+    /// Where the loop starts and the `while` jumps out of it.
+    Loop(usize, Vec<usize>),
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    /// Line of the token being compiled, for errors.
+    line: usize,
+    /// The ROM from 0x200; `here` is the next address written.
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, usize)>,
+    expansions: usize,
+}
+
+impl Compiler {
+    fn new(tokens: VecDeque<Token>) -> Compiler {
+        Compiler {
+            tokens,
+            line: 1,
+            rom: Vec::new(),
+            here: START as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        // Room for `jump main`, removed again if main turns out to be here.
+        self.emit(&[0, 0])?;
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement(&token.text)?;
+        }
+
+        if let Some((_, line)) = self.blocks.last() {
+            self.line = *line;
+            return Err("block is never closed".to_owned());
+        }
+        let main = *self
+            .labels
+            .get("main")
+            .ok_or("the program has no `main` label")?;
+        if main != START {
+            self.patch(START as usize, Patch::Nnn, main)?;
+            self.rom[0] |= 0x10;
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            let address = *self
+                .labels
+                .get(&fixup.label)
+                .ok_or_else(|| format!("undefined label `{}`", fixup.label))?;
+            self.patch(fixup.at, fixup.patch, address)?;
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.pop_front().ok_or("unexpected end of file")?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != text {
+            return Err(format!("expected `{}`, found `{}`", text, token));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let end = self.here + bytes.len();
+        if end > 0x10000 {
+            return Err("program does not fit in 64 KB of memory".to_owned());
+        }
+        let offset = self.here - START as usize;
+        if self.rom.len() < end - START as usize {
+            self.rom.resize(end - START as usize, 0);
+        }
+        self.rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.here = end;
+        Ok(())
+    }
+
+    fn op(&mut self, opcode: u16) -> Result<(), String> {
+        self.emit(&opcode.to_be_bytes())
+    }
+
+    fn patch(&mut self, at: usize, patch: Patch, address: u16) -> Result<(), String> {
+        let i = at - START as usize;
+        match patch {
+            Patch::Nnn => {
+                if address > 0xFFF {
+                    return Err(format!("address {:#X} does not fit in 12 bits", address));
+                }
+                self.rom[i] = self.rom[i] & 0xF0 | (address >> 8) as u8;
+                self.rom[i + 1] = address as u8;
+            }
+            Patch::Word => self.rom[i..i + 2].copy_from_slice(&address.to_be_bytes()),
+            Patch::HighNibble(nibble) => {
+                if address > 0xFFF {
+                    return Err(format!("address {:#X} does not fit in 12 bits", address));
+                }
+                self.rom[i] = nibble << 4 | (address >> 8) as u8;
+            }
+            Patch::HighByte => self.rom[i] = (address >> 8) as u8,
+            Patch::LowByte => self.rom[i] = address as u8,
+        }
+        Ok(())
+    }
+
+    /// Reads an address operand and writes it into each of `patches`, now if
+    /// it is known or once the label is defined.
+    fn address(&mut self, patches: &[(usize, Patch)]) -> Result<(), String> {
+        let token = self.next()?;
+        let address = match self.labels.get(&token) {
+            Some(&address) => address,
+            None if token == "{" => self.calc()? as u16,
+            None => match self.number(&token) {
+                Some(value) if (0..=0xFFFF).contains(&value) => value as u16,
+                Some(value) => return Err(format!("address {} is out of range", value)),
+                None if is_identifier(&token) => {
+                    for &(at, patch) in patches {
+                        self.fixups.push(Fixup {
+                            at,
+                            patch,
+                            label: token.clone(),
+                            line: self.line,
+                        });
+                    }
+                    return Ok(());
+                }
+                None => return Err(format!("expected an address, found `{}`", token)),
+            },
+        };
+        for &(at, patch) in patches {
+            self.patch(at, patch, address)?;
+        }
+        Ok(())
+    }
+
+    /// Emits an instruction with a 12-bit address operand.
+    fn op_nnn(&mut self, opcode: u16) -> Result<(), String> {
+        let at = self.here;
+        self.op(opcode)?;
+        self.address(&[(at, Patch::Nnn)])
+    }
+
+    /// A number literal or constant.
+    fn number(&self, token: &str) -> Option<i64> {
+        if let Some(&value) = self.constants.get(token) {
+            return Some(value as i64);
+        }
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()?
+        } else if let Some(bin) = digits.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2).ok()?
+        } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+            digits.parse().ok()?
+        } else {
+            return None;
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    /// Reads a value in `min..=max`: a number, a constant or `{ calc }`.
+    fn value(&mut self, min: i64, max: i64) -> Result<i64, String> {
+        let token = self.next()?;
+        let value = if token == "{" {
+            self.calc()? as i64
+        } else {
+            self.number(&token)
+                .ok_or_else(|| format!("expected a number, found `{}`", token))?
+        };
+        if value < min || value > max {
+            return Err(format!("{} is out of range", value));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        self.value(-0x80, 0xFF).map(|v| v as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        self.value(0, 0xF).map(|v| v as u8)
+    }
+
+    fn register_named(&self, token: &str) -> Option<u8> {
+        if let Some(&r) = self.aliases.get(token) {
+            return Some(r);
+        }
+        let digit = token.strip_prefix(|c| c == 'v' || c == 'V')?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        self.register_named(&token)
+            .ok_or_else(|| format!("expected a register, found `{}`", token))
+    }
+
+    fn next_is_register(&self) -> bool {
+        self.peek().and_then(|t| self.register_named(t)).is_some()
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<(), String> {
+        if !is_identifier(&name) {
+            return Err(format!("`{}` is not a valid name", name));
+        }
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return Err(format!("`{}` is already defined", name));
+        }
+        self.labels.insert(name, address as u16);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: String, value: f64) -> Result<(), String> {
+        if !is_identifier(&name) {
+            return Err(format!("`{}` is not a valid name", name));
+        }
+        if self.labels.contains_key(&name) {
+            return Err(format!("`{}` is already defined", name));
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    fn statement(&mut self, token: &str) -> Result<(), String> {
+        if let Some(x) = self.register_named(token) {
+            return self.arithmetic(x);
+        }
+        if self.macros.contains_key(token) {
+            return self.expand(token);
+        }
+        match token {
+            ":" => {
+                let name = self.next()?;
+                // main straight after the reserved jump takes its place
+                if name == "main"
+                    && self.here == START as usize + 2
+                    && self.rom.len() == 2
+                    && self.labels.is_empty()
+                {
+                    self.rom.clear();
+                    self.here = START as usize;
+                }
+                self.define_label(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.next()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value(i64::MIN, i64::MAX)?;
+                self.define_constant(name, value as f64)?;
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.define_constant(name, value)?;
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let r = if self.peek() == Some("{") {
+                    self.next()?;
+                    self.calc()? as i64
+                } else {
+                    self.register()? as i64
+                };
+                if !(0..=0xF).contains(&r) {
+                    return Err(format!("register {} is out of range", r));
+                }
+                if !is_identifier(&name) {
+                    return Err(format!("`{}` is not a valid name", name));
+                }
+                self.aliases.insert(name, r as u8);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let address = self.value(START as i64, 0xFFFF)?;
+                self.here = address as usize;
+            }
+            ":byte" => {
+                let b = self.byte()?;
+                self.emit(&[b])?;
+            }
+            ":call" => self.op_nnn(0x2000)?,
+            ":unpack" => {
+                // v0 and v1 get the high and low halves of the address
+                let high = if self.peek() == Some("long") {
+                    self.next()?;
+                    Patch::HighByte
+                } else {
+                    Patch::HighNibble(self.nibble()?)
+                };
+                let at = self.here;
+                self.op(0x6000)?;
+                self.op(0x6100)?;
+                self.address(&[(at + 1, high), (at + 3, Patch::LowByte)])?;
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "return" | ";" => self.op(0x00EE)?,
+            "clear" => self.op(0x00E0)?,
+            "exit" => self.op(0x00FD)?,
+            "lores" => self.op(0x00FE)?,
+            "hires" => self.op(0x00FF)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.op(0x00C0 | n as u16)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.op(0x00D0 | n as u16)?;
+            }
+            "scroll-right" => self.op(0x00FB)?,
+            "scroll-left" => self.op(0x00FC)?,
+            "audio" => self.op(0xF002)?,
+            "plane" => {
+                let n = self.value(0, 3)?;
+                self.op(0xF001 | (n as u16) << 8)?;
+            }
+            "bcd" => self.op_x(0xF033)?,
+            "saveflags" => self.op_x(0xF075)?,
+            "loadflags" => self.op_x(0xF085)?,
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()? as u16;
+                    let low = if token == "save" { 0x2 } else { 0x3 };
+                    self.op(0x5000 | x << 8 | y << 4 | low)?;
+                } else {
+                    let low = if token == "save" { 0x55 } else { 0x65 };
+                    self.op(0xF000 | x << 8 | low)?;
+                }
+            }
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let n = self.nibble()? as u16;
+                self.op(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "jump" => self.op_nnn(0x1000)?,
+            "jump0" => self.op_nnn(0xB000)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let low = match token {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.op_x(0xF000 | low)?;
+            }
+            "i" => {
+                let operator = self.next()?;
+                match operator.as_str() {
+                    "+=" => self.op_x(0xF01E)?,
+                    ":=" => match self.peek() {
+                        Some("hex") => {
+                            self.next()?;
+                            self.op_x(0xF029)?;
+                        }
+                        Some("bighex") => {
+                            self.next()?;
+                            self.op_x(0xF030)?;
+                        }
+                        Some("long") => {
+                            self.next()?;
+                            let at = self.here;
+                            self.emit(&[0xF0, 0x00, 0x00, 0x00])?;
+                            self.address(&[(at + 2, Patch::Word)])?;
+                        }
+                        _ => self.op_nnn(0xA000)?,
+                    },
+                    _ => return Err(format!("unknown operator `i {}`", operator)),
+                }
+            }
+            "if" => {
+                let then = self
+                    .tokens
+                    .iter()
+                    .find(|t| t.text == "then" || t.text == "begin")
+                    .is_some_and(|t| t.text == "then");
+                if then {
+                    self.conditional(false)?;
+                    self.expect("then")?;
+                } else {
+                    self.conditional(true)?;
+                    self.expect("begin")?;
+                    self.blocks.push((Block::Begin(self.here), self.line));
+                    self.op(0x1000)?;
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((Block::Begin(jump), _)) => {
+                    let else_jump = self.here;
+                    self.op(0x1000)?;
+                    self.patch(jump, Patch::Nnn, self.here as u16)?;
+                    self.blocks.push((Block::Else(else_jump), self.line));
+                }
+                _ => return Err("`else` without `if ... begin`".to_owned()),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::Begin(jump), _)) | Some((Block::Else(jump), _)) => {
+                    self.patch(jump, Patch::Nnn, self.here as u16)?;
+                }
+                _ => return Err("`end` without `if ... begin`".to_owned()),
+            },
+            "loop" => self
+                .blocks
+                .push((Block::Loop(self.here, Vec::new()), self.line)),
+            "while" => {
+                self.conditional(true)?;
+                let jump = self.here;
+                self.op(0x1000)?;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop(_, whiles) => Some(whiles),
+                        _ => None,
+                    }) {
+                    Some(whiles) => whiles.push(jump),
+                    None => return Err("`while` outside of a loop".to_owned()),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop(start, whiles), _)) => {
+                    self.op(0x1000)?;
+                    self.patch(self.here - 2, Patch::Nnn, start as u16)?;
+                    for jump in whiles {
+                        self.patch(jump, Patch::Nnn, self.here as u16)?;
+                    }
+                }
+                _ => return Err("`again` without `loop`".to_owned()),
+            },
+            _ => {
+                if let Some(value) = self.number(token) {
+                    if !(-0x80..=0xFF).contains(&value) {
+                        return Err(format!("{} is out of range", value));
+                    }
+                    self.emit(&[value as u8])?;
+                } else if token.starts_with(':') {
+                    return Err(format!("unknown directive `{}`", token));
+                } else if is_identifier(token) {
+                    // a bare name calls the subroutine with that label
+                    self.tokens.push_front(Token {
+                        text: token.to_owned(),
+                        line: self.line,
+                    });
+                    self.op_nnn(0x2000)?;
+                } else {
+                    return Err(format!("unexpected `{}`", token));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits `opcode` with the register read next as X.
+    fn op_x(&mut self, opcode: u16) -> Result<(), String> {
+        let x = self.register()? as u16;
+        self.op(opcode | x << 8)
+    }
+
+    /// `vX op ...` statements.
+    fn arithmetic(&mut self, x: u8) -> Result<(), String> {
+        let x = x as u16;
+        let operator = self.next()?;
+        let alu = |y: u8, n: u16| 0x8000 | x << 8 | (y as u16) << 4 | n;
+        match operator.as_str() {
+            ":=" => match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    let nn = self.byte()? as u16;
+                    self.op(0xC000 | x << 8 | nn)
+                }
+                Some("key") => {
+                    self.next()?;
+                    self.op(0xF00A | x << 8)
+                }
+                Some("delay") => {
+                    self.next()?;
+                    self.op(0xF007 | x << 8)
+                }
+                _ if self.next_is_register() => {
+                    let y = self.register()?;
+                    self.op(alu(y, 0x0))
+                }
+                _ => {
+                    let nn = self.byte()? as u16;
+                    self.op(0x6000 | x << 8 | nn)
+                }
+            },
+            "+=" if !self.next_is_register() => {
+                let nn = self.byte()? as u16;
+                self.op(0x7000 | x << 8 | nn)
+            }
+            "-=" if !self.next_is_register() => {
+                let nn = self.byte()?.wrapping_neg() as u16;
+                self.op(0x7000 | x << 8 | nn)
+            }
+            _ => {
+                let n = match operator.as_str() {
+                    "|=" => 0x1,
+                    "&=" => 0x2,
+                    "^=" => 0x3,
+                    "+=" => 0x4,
+                    "-=" => 0x5,
+                    ">>=" => 0x6,
+                    "=-" => 0x7,
+                    "<<=" => 0xE,
+                    _ => return Err(format!("unknown operator `{}`", operator)),
+                };
+                let y = self.register()?;
+                self.op(alu(y, n))
+            }
+        }
+    }
+
+    /// Emits the skip for `if` and `while`. The skip jumps over the next
+    /// instruction unless the condition holds; `negated` turns that around, for
+    /// blocks where the next instruction is the jump past the block.
+    fn conditional(&mut self, negated: bool) -> Result<(), String> {
+        let x = self.register()? as u16;
+        let mut operator = self.next()?;
+        if negated {
+            operator = match operator.as_str() {
+                "==" => "!=",
+                "!=" => "==",
+                "key" => "-key",
+                "-key" => "key",
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                other => return Err(format!("unknown comparison `{}`", other)),
+            }
+            .to_owned();
+        }
+        let temp = 0xF;
+        match operator.as_str() {
+            "==" | "!=" => {
+                let equal = operator == "==";
+                if self.next_is_register() {
+                    let y = self.register()? as u16;
+                    self.op(if equal { 0x9000 } else { 0x5000 } | x << 8 | y << 4)
+                } else {
+                    let nn = self.byte()? as u16;
+                    self.op(if equal { 0x4000 } else { 0x3000 } | x << 8 | nn)
+                }
+            }
+            "key" => self.op(0xE0A1 | x << 8),
+            "-key" => self.op(0xE09E | x << 8),
+            "<" | ">" | "<=" | ">=" => {
+                // VF := the right-hand side, then subtract so that VF holds the
+                // borrow flag, and skip on it
+                if self.next_is_register() {
+                    let y = self.register()? as u16;
+                    self.op(0x8000 | temp << 8 | y << 4)?;
+                } else {
+                    let nn = self.byte()? as u16;
+                    self.op(0x6000 | temp << 8 | nn)?;
+                }
+                let (subtract, skip) = match operator.as_str() {
+                    ">" => (0x5, 0x3F01),
+                    "<" => (0x7, 0x3F01),
+                    ">=" => (0x7, 0x4F01),
+                    _ => (0x5, 0x4F01),
+                };
+                self.op(0x8000 | temp << 8 | x << 4 | subtract)?;
+                self.op(skip)
+            }
+            other => Err(format!("unknown comparison `{}`", other)),
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.next()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self
+                .tokens
+                .pop_front()
+                .ok_or("macro body is never closed")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+        if !is_identifier(&name) {
+            return Err(format!("`{}` is not a valid name", name));
+        }
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    /// Replaces a macro invocation with the macro's body.
+    fn expand(&mut self, name: &str) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(format!("macro `{}` expands forever", name));
+        }
+        let count = self.macros[name].params.len();
+        let mut args = HashMap::new();
+        for i in 0..count {
+            let arg = self.next()?;
+            args.insert(self.macros[name].params[i].clone(), arg);
+        }
+        let line = self.line;
+        for token in self.macros[name].body.iter().rev() {
+            let text = args.get(&token.text).unwrap_or(&token.text).clone();
+            self.tokens.push_front(Token { text, line });
+        }
+        Ok(())
+    }
+
+    /// Evaluates a `:calc` expression after its `{`, up to and including the
+    /// closing `}`.
+    fn calc(&mut self) -> Result<f64, String> {
+        let value = self.calc_expr()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expr(&mut self) -> Result<f64, String> {
+        let left = self.calc_term()?;
+        let operator = match self.peek() {
+            Some(op) if BINARY.contains(&op) => op.to_owned(),
+            _ => return Ok(left),
+        };
+        self.next()?;
+        let right = self.calc_expr()?;
+        let (l, r) = (left as i64, right as i64);
+        let value = match operator.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" if right == 0.0 => return Err("division by zero".to_owned()),
+            "/" => left / right,
+            "%" if r == 0 => return Err("division by zero".to_owned()),
+            "%" => l.checked_rem(r).ok_or("value out of range")? as f64,
+            "&" => (l & r) as f64,
+            "|" => (l | r) as f64,
+            "^" => (l ^ r) as f64,
+            "<<" => (l << (r & 63)) as f64,
+            ">>" => (l >> (r & 63)) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            _ => (left != right) as i64 as f64,
+        };
+        Ok(value)
+    }
+
+    fn calc_term(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        let value = match token.as_str() {
+            "(" => {
+                let value = self.calc_expr()?;
+                self.expect(")")?;
+                value
+            }
+            "-" => -self.calc_term()?,
+            "~" => !(self.calc_term()? as i64) as f64,
+            "!" => (self.calc_term()? == 0.0) as i64 as f64,
+            "abs" => self.calc_term()?.abs(),
+            "sqrt" => self.calc_term()?.sqrt(),
+            "sin" => self.calc_term()?.sin(),
+            "cos" => self.calc_term()?.cos(),
+            "tan" => self.calc_term()?.tan(),
+            "exp" => self.calc_term()?.exp(),
+            "log" => self.calc_term()?.ln(),
+            "floor" => self.calc_term()?.floor(),
+            "ceil" => self.calc_term()?.ceil(),
+            "sign" => self.calc_term()?.signum(),
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            "HERE" => self.here as f64,
+            _ => {
+                if let Some(&address) = self.labels.get(&token) {
+                    address as f64
+                } else if let Some(&value) = self.constants.get(&token) {
+                    value
+                } else if let Some(&r) = self.aliases.get(&token) {
+                    r as f64
+                } else {
+                    self.number(&token)
+                        .ok_or_else(|| format!("`{}` is not defined", token))?
+                        as f64
+                }
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// The binary operators of `:calc`.
+const BINARY: [&str; 19] = [
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", ">", ">=",
+    "==", "!=",
+];
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
@@ -0,0 +1,173 @@
+//! Rewinding through recently played frames.
+//!
+//! Every frame the frontend pushes the CPU into a `Rewind` buffer, which keeps
+//! the newest save state in full and, for each older frame, only the bytes
+//! that changed. Memory and vram rarely change much from one frame to the
+//! next, so the deltas are XORed against the following frame and run-length
+//! encoded, which keeps 30 seconds of history well under a megabyte.
+
+use crate::CPU;
+use std::collections::VecDeque;
+
+/// 30 seconds at 60 frames per second.
+pub const DEFAULT_FRAMES: usize = 30 * 60;
+
+/// How to get from a state back to the one pushed before it.
+enum Delta {
+    /// Run-length encoded XOR of the two states, which have the same length.
+    Xor(Vec<u8>),
+    /// The previous state in full, used when the state size changed in between,
+    /// e.g. after switching between lores and hires.
+    Full(Vec<u8>),
+}
+
+impl Delta {
+    fn len(&self) -> usize {
+        match self {
+            Delta::Xor(bytes) | Delta::Full(bytes) => bytes.len(),
+        }
+    }
+}
+
+/// A ring buffer of the last `capacity` frames.
+pub struct Rewind {
+    capacity: usize,
+    /// The most recently pushed (or rewound to) state.
+    head: Option<Vec<u8>>,
+    /// Deltas leading back from `head`, oldest first.
+    deltas: VecDeque<Delta>,
+}
+
+impl Rewind {
+    /// Creates a buffer that can rewind up to `capacity` frames.
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            capacity,
+            head: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Records the CPU's current state as the newest frame, dropping the oldest
+    /// frame once the buffer is full.
+    pub fn push(&mut self, cpu: &CPU) {
+        let state = cpu.save_state();
+        if let Some(previous) = self.head.take() {
+            let delta = if previous.len() == state.len() {
+                Delta::Xor(rle_encode(&xor(&previous, &state)))
+            } else {
+                Delta::Full(previous)
+            };
+            self.deltas.push_back(delta);
+            while self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.head = Some(state);
+    }
+
+    /// Steps back one frame, restoring `cpu` to the state pushed before the
+    /// newest one. Returns `false`, leaving `cpu` alone, when there is nothing
+    /// left to rewind.
+    pub fn rewind(&mut self, cpu: &mut CPU) -> bool {
+        let (head, delta) = match (self.head.as_mut(), self.deltas.pop_back()) {
+            (Some(head), Some(delta)) => (head, delta),
+            _ => return false,
+        };
+        match delta {
+            Delta::Xor(encoded) => rle_decode_xor(&encoded, head),
+            Delta::Full(previous) => *head = previous,
+        }
+        cpu.load_state(head)
+            .expect("rewind buffer holds a state the CPU wrote itself");
+        true
+    }
+
+    /// Number of frames that can currently be rewound.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Whether there is nothing left to rewind.
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Forgets all history, e.g. after a reset or loading a save state.
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.deltas.clear();
+    }
+
+    /// Approximate number of bytes used to store the history.
+    pub fn memory_usage(&self) -> usize {
+        self.head.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Delta::len).sum::<usize>()
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Rewind {
+        Rewind::new(DEFAULT_FRAMES)
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+/// Encodes `data` as a sequence of (zero run, literal length, literal bytes)
+/// records, with both lengths stored as LEB128 varints.
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let zeros = data[i..].iter().take_while(|&&b| b == 0).count();
+        i += zeros;
+        let literal = data[i..].iter().take_while(|&&b| b != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literal);
+        out.extend_from_slice(&data[i..i + literal]);
+        i += literal;
+    }
+    out
+}
+
+/// XORs the data encoded by `rle_encode` into `target`.
+fn rle_decode_xor(encoded: &[u8], target: &mut [u8]) {
+    let (mut i, mut pos) = (0, 0);
+    while i < encoded.len() {
+        let zeros = read_varint(encoded, &mut i);
+        let literal = read_varint(encoded, &mut i);
+        pos += zeros;
+        for (t, d) in target[pos..pos + literal]
+            .iter_mut()
+            .zip(&encoded[i..i + literal])
+        {
+            *t ^= d;
+        }
+        pos += literal;
+        i += literal;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*i];
+        *i += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
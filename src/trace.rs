@@ -0,0 +1,149 @@
+//! Execution traces with one line per instruction, for diffing two runs to
+//! find where they first diverge.
+//!
+//! Each line shows the machine as the instruction is about to execute. The
+//! native format adds the step, which counts instructions executed before
+//! this one, and the disassembly:
+//!
+//! ```text
+//! 000001 PC:0202 OP:A22A LD I, #22A            V:00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:00 DT:00 ST:00
+//! ```
+//!
+//! The `cadmium` format is the state line of Gulrak's Cadmium emulator, so a
+//! trace can be diffed against Cadmium's as is:
+//!
+//! ```text
+//! V0:00 V1:00 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 VE:00 VF:00 I:0000 SP:0 PC:0202 O:a22a
+//! ```
+
+use crate::disasm::{Instruction, Syntax};
+use crate::CPU;
+use std::io::{self, Write};
+
+/// The layout of a trace line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Step, PC, opcode, disassembly, registers and timers, as `line` writes.
+    Native,
+    /// Registers, PC and opcode in lowercase hex, as Cadmium writes them.
+    Cadmium,
+}
+
+impl Format {
+    /// Looks up a format by name: `native` or `cadmium`.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_ascii_lowercase().as_str() {
+            "native" => Some(Format::Native),
+            "cadmium" => Some(Format::Cadmium),
+            _ => None,
+        }
+    }
+
+    /// The trace line in this format for the instruction at the CPU's PC,
+    /// without a newline.
+    pub fn line(self, cpu: &CPU) -> String {
+        match self {
+            Format::Native => line(cpu),
+            Format::Cadmium => cadmium_line(cpu),
+        }
+    }
+}
+
+/// Writes a trace line for every instruction passed to `record`.
+pub struct Trace<W: Write> {
+    out: W,
+    format: Format,
+    /// The first write error; nothing more is written after it.
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Trace<W> {
+    pub fn new(out: W) -> Trace<W> {
+        Trace::with_format(out, Format::Native)
+    }
+
+    pub fn with_format(out: W, format: Format) -> Trace<W> {
+        Trace {
+            out,
+            format,
+            error: None,
+        }
+    }
+
+    /// Writes the line for the instruction at the CPU's PC. Call this before
+    /// `CPU::step`.
+    pub fn record(&mut self, cpu: &CPU) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.out, "{}", self.format.line(cpu)) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Flushes the trace and returns the writer, or the first error.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// The native trace line for the instruction at the CPU's PC, without a
+/// newline.
+pub fn line(cpu: &CPU) -> String {
+    let pc = cpu.pc as usize;
+    let bytes = cpu
+        .memory
+        .get(pc..(pc + 4).min(cpu.memory.len()))
+        .unwrap_or(&[]);
+    let opcode = opcode(bytes);
+    let text = Instruction::decode(bytes, cpu.platform)
+        .map(|i| i.format(Syntax::Cowgod, &|a| format!("#{:03X}", a)))
+        .unwrap_or_else(|| "???".to_owned());
+    let registers: Vec<String> = cpu.registers.iter().map(|v| format!("{:02X}", v)).collect();
+    format!(
+        "{:06} PC:{:04X} OP:{:04X} {:<21} V:{} I:{:04X} SP:{:02X} DT:{:02X} ST:{:02X}",
+        cpu.steps(),
+        cpu.pc,
+        opcode,
+        text,
+        registers.join(" "),
+        cpu.index_register,
+        cpu.sp,
+        cpu.delay_timer,
+        cpu.audio_timer
+    )
+}
+
+/// The Cadmium trace line for the instruction at the CPU's PC.
+fn cadmium_line(cpu: &CPU) -> String {
+    let pc = cpu.pc as usize;
+    let bytes = cpu
+        .memory
+        .get(pc..(pc + 2).min(cpu.memory.len()))
+        .unwrap_or(&[]);
+    let registers: Vec<String> = cpu
+        .registers
+        .iter()
+        .enumerate()
+        .map(|(r, v)| format!("V{:X}:{:02x}", r, v))
+        .collect();
+    format!(
+        "{} I:{:04x} SP:{:x} PC:{:04x} O:{:04x}",
+        registers.join(" "),
+        cpu.index_register,
+        cpu.sp,
+        cpu.pc,
+        opcode(bytes)
+    )
+}
+
+/// The opcode starting `bytes`, or 0 past the end of memory.
+fn opcode(bytes: &[u8]) -> u16 {
+    match bytes {
+        [high, low, ..] => u16::from_be_bytes([*high, *low]),
+        _ => 0,
+    }
+}
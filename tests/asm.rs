@@ -0,0 +1,201 @@
+use chip8_core::asm::{assemble, assemble_file, AsmError};
+use chip8_core::disasm::{disassemble, Syntax};
+use chip8_core::{Platform, CPU};
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+/// A directory in the temp directory, unique to this test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("chip8-asm-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn error(source: &str) -> (usize, String) {
+    let e = assemble(source).unwrap_err();
+    (e.line, e.message)
+}
+
+#[test]
+fn disassembly_assembles_back_to_the_rom() {
+    for name in [
+        "ibm.ch8",
+        "PONG",
+        "PONG2",
+        "TETRIS",
+        "BLITZ",
+        "MAZE",
+        "test_opcode.ch8",
+        "BC_test.ch8",
+        "c8_test.c8",
+        "invaders.c8",
+        "breakout.rom",
+        "life.ch8",
+    ] {
+        let rom = fs::read(format!("{}/programs/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+        let listing = disassemble(&rom, Platform::SuperChip, Syntax::Cowgod);
+        assert_eq!(assemble(&listing).as_deref(), Ok(&rom[..]), "{}", name);
+    }
+}
+
+#[test]
+fn xo_chip_round_trip() {
+    let rom = [
+        0x00, 0xD3, 0x51, 0x42, 0x51, 0x43, 0xF0, 0x00, 0x12, 0x34, 0xF2, 0x01, 0xF0, 0x02, 0xF5,
+        0x3A, 0x12, 0x00,
+    ];
+    let listing = disassemble(&rom, Platform::XoChip, Syntax::Cowgod);
+    assert!(listing.contains("LD I, LONG #1234"));
+    assert_eq!(assemble(&listing).as_deref(), Ok(&rom[..]));
+}
+
+#[test]
+fn labels_constants_and_data() {
+    let source = "
+        SPEED equ 2
+        TOP   EQU SPEED + #10 - 1   ; constants can use each other
+    start:
+        ld v0, SPEED
+        ld V1, TOP
+        ld i, sprite
+        drw v0, v1, sprite.end - sprite
+        add v0, -1
+        jp start
+    sprite: db %11110000, $90, 0x90, 144
+        DB 0b11110000
+    sprite.end:
+        dw start, #ABCD
+    ";
+    assert_eq!(
+        assemble(source).unwrap(),
+        [
+            0x60, 0x02, 0x61, 0x11, 0xA2, 0x0C, 0xD0, 0x15, 0x70, 0xFF, 0x12, 0x00, 0xF0, 0x90,
+            0x90, 0x90, 0xF0, 0x02, 0x00, 0xAB, 0xCD,
+        ]
+    );
+}
+
+#[test]
+fn includes_are_relative_to_the_including_file() {
+    let dir = temp_dir("include");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(
+        dir.join("main.asm"),
+        "include \"lib/font.asm\"\nld i, glyph\n",
+    )
+    .unwrap();
+    fs::write(dir.join("lib/font.asm"), "jp over\nglyph: db #F0\nover:\n").unwrap();
+    assert_eq!(
+        assemble_file(dir.join("main.asm")).unwrap(),
+        [0x12, 0x03, 0xF0, 0xA2, 0x02]
+    );
+
+    fs::write(dir.join("lib/font.asm"), "\n\nld v0, [i], v1\n").unwrap();
+    let e = assemble_file(dir.join("main.asm")).unwrap_err();
+    assert!(e.file.ends_with("font.asm"), "{}", e.file);
+    assert_eq!(e.line, 3);
+
+    fs::write(dir.join("main.asm"), "cls\ninclude \"missing.asm\"\n").unwrap();
+    let e = assemble_file(dir.join("main.asm")).unwrap_err();
+    assert_eq!(e.line, 2);
+    assert!(e.message.starts_with("cannot read"));
+
+    fs::write(dir.join("main.asm"), "include \"main.asm\"\n").unwrap();
+    let e = assemble_file(dir.join("main.asm")).unwrap_err();
+    assert_eq!(e.message, "includes are nested too deeply");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn errors_have_line_numbers() {
+    assert_eq!(
+        error("cls\n\nfoo v0"),
+        (3, "unknown instruction `foo`".to_owned())
+    );
+    assert_eq!(
+        error("cls\nld v0"),
+        (2, "invalid operands for `LD`".to_owned())
+    );
+    assert_eq!(
+        error("jp nowhere"),
+        (1, "undefined symbol `nowhere`".to_owned())
+    );
+    assert_eq!(
+        error("ld v0, 256"),
+        (1, "byte 256 is out of range".to_owned())
+    );
+    assert_eq!(
+        error("jp #1000"),
+        (1, "address 4096 is out of range".to_owned())
+    );
+    assert_eq!(
+        error("cls\ndw 0x7FFFFFFFFFFFFFFF + 3"),
+        (2, "value out of range".to_owned())
+    );
+    assert_eq!(
+        error("drw v0, v1, v2"),
+        (1, "`DRW` expects a nibble there".to_owned())
+    );
+    assert_eq!(
+        error("a:\na: cls"),
+        (2, "`a` is already defined".to_owned())
+    );
+    assert_eq!(error("dt: cls"), (1, "`dt` is a reserved name".to_owned()));
+    assert_eq!(
+        error("ld v0, #xyz"),
+        (1, "invalid number `#xyz`".to_owned())
+    );
+    assert_eq!(
+        error("x equ x\nld v0, x").1,
+        "constant `x` is defined in terms of itself"
+    );
+    assert_eq!(
+        AsmError {
+            file: "pong.asm".to_owned(),
+            line: 7,
+            message: "oops".to_owned()
+        }
+        .to_string(),
+        "pong.asm:7: oops"
+    );
+}
+
+#[test]
+fn program_must_fit_in_64_kb() {
+    // 0x7F00 words fill memory from 0x200 to the very end
+    let full = "dw 0\n".repeat(0x7F00);
+    assert_eq!(assemble(&full).unwrap().len(), 0xFE00);
+    assert_eq!(
+        assemble(&format!("{}db 1, 2", &full[5..])).unwrap().len(),
+        0xFE00
+    );
+    assert_eq!(
+        error(&format!("{}end: cls", full)),
+        (0x7F01, "program does not fit in 64 KB of memory".to_owned())
+    );
+    assert_eq!(
+        error(&format!("{}cls", full)),
+        (0x7F01, "program does not fit in 64 KB of memory".to_owned())
+    );
+    assert_eq!(
+        error(&format!("{}db 1, 2, 3", &full[5..])),
+        (0x7F00, "program does not fit in 64 KB of memory".to_owned())
+    );
+}
+
+#[test]
+fn assembled_rom_runs() {
+    let rom = assemble("ld v0, 5\nloop: add v0, 1\nsne v0, 9\nexit\njp loop").unwrap();
+    let path = temp_dir("run").join("count.ch8");
+    fs::write(&path, &rom).unwrap();
+
+    let mut cpu = CPU::new();
+    cpu.set_platform(Platform::SuperChip);
+    assert_eq!(cpu.load_rom(path.to_str().unwrap()).unwrap(), rom.len());
+    for _ in 0..20 {
+        cpu.step().unwrap();
+    }
+    assert!(cpu.exited);
+    assert_eq!(cpu.registers[0], 9);
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
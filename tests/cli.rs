@@ -0,0 +1,197 @@
+//! Runs the `chip-8` binary's subcommands that do not open a window.
+
+mod common;
+
+use common::program;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+
+fn chip8(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip-8"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// A path in the temp directory, unique to this test.
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("chip8-cli-{}-{}", process::id(), name))
+}
+
+fn stdout(out: &Output) -> String {
+    String::from_utf8(out.stdout.clone()).unwrap()
+}
+
+fn stderr(out: &Output) -> String {
+    String::from_utf8(out.stderr.clone()).unwrap()
+}
+
+#[test]
+fn bad_arguments_are_usage_errors() {
+    let out = chip8(&[]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(
+        stderr(&out).contains("Usage: chip-8 <COMMAND>"),
+        "{}",
+        stderr(&out)
+    );
+
+    for args in [
+        &["run"][..],
+        &["run", ""],
+        &["run", "rom.ch8", "--speed", "fast"],
+        &["run", "rom.ch8", "--speed", "0"],
+        &["run", "rom.ch8", "--quirks", "nes"],
+        &["run", "rom.ch8", "--mode", "fast"],
+        &["run", "rom.ch8", "--debug", "--play", "run.c8m"],
+        &["rom.ch8", "nes"],
+        &["disasm", "rom.ch8", "--syntax", "intel"],
+        &["trace", "rom.ch8", "--bogus"],
+    ] {
+        let out = chip8(args);
+        assert_eq!(out.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&out).starts_with("error: "), "{}", stderr(&out));
+    }
+}
+
+#[test]
+fn missing_rom_is_an_error() {
+    for args in [
+        &["info", "does-not-exist.ch8"][..],
+        &["test", "does-not-exist.ch8"],
+    ] {
+        let out = chip8(args);
+        assert_eq!(out.status.code(), Some(1), "{:?}", args);
+        assert!(stderr(&out).starts_with("Error: "), "{}", stderr(&out));
+    }
+}
+
+#[test]
+fn disassembles_and_assembles() {
+    let listing = chip8(&["disasm", &program("ibm.ch8")]);
+    assert_eq!(listing.status.code(), Some(0));
+    assert!(
+        stdout(&listing).starts_with("    CLS "),
+        "{}",
+        stdout(&listing)
+    );
+
+    let source = temp_path("ibm.asm");
+    let rom = temp_path("ibm.ch8");
+    fs::write(&source, listing.stdout).unwrap();
+    let out = chip8(&["asm", source.to_str().unwrap(), "-o", rom.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0), "{}", stderr(&out));
+    assert_eq!(
+        fs::read(&rom).unwrap(),
+        fs::read(program("ibm.ch8")).unwrap()
+    );
+    fs::remove_file(source).unwrap();
+    fs::remove_file(rom).unwrap();
+}
+
+#[test]
+fn info_detects_the_platform() {
+    let out = chip8(&["info", &program("PONG")]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(
+        stdout(&out).contains("size: 246 bytes\nplatform: chip8\n"),
+        "{}",
+        stdout(&out)
+    );
+
+    // 00FF switches to hires, then F000 NNNN loads a 16-bit address into I
+    for (bytes, platform) in [
+        (&[0x00, 0xFF, 0x12, 0x02][..], "schip"),
+        (&[0x00, 0xFF, 0xF0, 0x00, 0x12, 0x34, 0x12, 0x06], "xochip"),
+    ] {
+        let rom = temp_path(&format!("{}.ch8", platform));
+        fs::write(&rom, bytes).unwrap();
+        let out = chip8(&["info", rom.to_str().unwrap()]);
+        fs::remove_file(rom).unwrap();
+        assert!(
+            stdout(&out).contains(&format!("platform: {}\n", platform)),
+            "{}",
+            stdout(&out)
+        );
+    }
+}
+
+#[test]
+fn traces_runs_and_reports_faults() {
+    let out = chip8(&["trace", &program("ibm.ch8"), "--cycles", "20"]);
+    assert_eq!(out.status.code(), Some(0));
+    let trace = stdout(&out);
+    assert_eq!(trace.lines().count(), 20);
+    assert!(
+        trace.starts_with("000000 PC:0200 OP:00E0 CLS "),
+        "{}",
+        trace
+    );
+
+    let out = chip8(&[
+        "trace",
+        &program("ibm.ch8"),
+        "--cycles",
+        "1",
+        "--format",
+        "cadmium",
+    ]);
+    assert_eq!(
+        stdout(&out),
+        "V0:00 V1:00 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 \
+         VE:00 VF:00 I:0000 SP:0 PC:0200 O:00e0\n"
+    );
+
+    // 0x0123 is a machine code call, unsupported by the interpreter.
+    let rom = temp_path("fault.ch8");
+    fs::write(&rom, [0x60, 0x01, 0x01, 0x23]).unwrap();
+    let out = chip8(&["trace", rom.to_str().unwrap()]);
+    fs::remove_file(rom).unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stdout(&out).lines().count(), 2);
+    assert!(
+        stderr(&out).starts_with("CPU fault at 0x202: "),
+        "{}",
+        stderr(&out)
+    );
+}
+
+#[test]
+fn test_prints_the_screen() {
+    let out = chip8(&["test", &program("ibm.ch8"), "--frames", "60"]);
+    assert_eq!(out.status.code(), Some(0));
+    let golden = fs::read_to_string(format!(
+        "{}/tests/golden/ibm.txt",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap();
+    assert_eq!(stdout(&out), golden);
+
+    // 00FD exits, so a frame count that overflows the cycle count still ends.
+    let rom = temp_path("exit.ch8");
+    fs::write(&rom, [0x00, 0xFD]).unwrap();
+    let frames = u64::MAX.to_string();
+    let out = chip8(&[
+        "test",
+        rom.to_str().unwrap(),
+        "--quirks",
+        "schip",
+        "--frames",
+        &frames,
+    ]);
+    fs::remove_file(rom).unwrap();
+    assert_eq!(out.status.code(), Some(0));
+}
+
+#[test]
+fn bench_counts_instructions() {
+    let out = chip8(&["bench", &program("PONG"), "--cycles", "5000"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(
+        stdout(&out).starts_with("5000 instructions in "),
+        "{}",
+        stdout(&out)
+    );
+}
@@ -0,0 +1,128 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use chip8_core::{Platform, Quirks, Rng, CPU};
+
+/// Builds a CPU in a known state for exercising individual instructions.
+///
+/// ```ignore
+/// let cpu = CpuBuilder::new().reg(0, 0xFF).reg(1, 0x01).program(&[0x8014]).run(1);
+/// assert_eq!(cpu.registers[0xF], 1);
+/// ```
+pub struct CpuBuilder {
+    cpu: CPU,
+}
+
+impl CpuBuilder {
+    pub fn new() -> Self {
+        CpuBuilder { cpu: CPU::new() }
+    }
+
+    /// Writes `opcodes` big-endian starting at 0x200.
+    pub fn program(self, opcodes: &[u16]) -> Self {
+        self.program_at(0x200, opcodes)
+    }
+
+    /// Writes `opcodes` big-endian starting at `addr`.
+    pub fn program_at(mut self, addr: usize, opcodes: &[u16]) -> Self {
+        for (i, op) in opcodes.iter().enumerate() {
+            self.cpu.memory[addr + i * 2..addr + i * 2 + 2].copy_from_slice(&op.to_be_bytes());
+        }
+        self
+    }
+
+    pub fn reg(mut self, r: usize, value: u8) -> Self {
+        self.cpu.registers[r] = value;
+        self
+    }
+
+    pub fn i(mut self, value: u16) -> Self {
+        self.cpu.index_register = value;
+        self
+    }
+
+    pub fn pc(mut self, value: u16) -> Self {
+        self.cpu.pc = value;
+        self
+    }
+
+    pub fn mem(mut self, addr: usize, bytes: &[u8]) -> Self {
+        self.cpu.memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+
+    /// Pushes return addresses onto the stack, as if that many 2NNN calls had run.
+    pub fn stack(mut self, frames: &[u16]) -> Self {
+        for &addr in frames {
+            self.cpu.stack[self.cpu.sp as usize] = addr;
+            self.cpu.sp += 1;
+        }
+        self
+    }
+
+    pub fn key(mut self, k: usize) -> Self {
+        self.cpu.key[k] = 1;
+        self
+    }
+
+    pub fn delay(mut self, value: u8) -> Self {
+        self.cpu.delay_timer = value;
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.cpu.quirks = quirks;
+        self
+    }
+
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.cpu.set_platform(platform);
+        self
+    }
+
+    pub fn rng(mut self, rng: Rng) -> Self {
+        self.cpu.rng = rng;
+        self
+    }
+
+    pub fn build(self) -> CPU {
+        self.cpu
+    }
+
+    /// Builds the CPU and executes `steps` instructions, panicking on a fault.
+    pub fn run(self, steps: usize) -> CPU {
+        let mut cpu = self.cpu;
+        for n in 0..steps {
+            if let Err(e) = cpu.step() {
+                panic!("step {} faulted: {}", n, e);
+            }
+        }
+        cpu
+    }
+}
+
+/// Asserts that each `(register, value)` pair holds.
+pub fn assert_regs(cpu: &CPU, expected: &[(usize, u8)]) {
+    for &(r, value) in expected {
+        assert_eq!(cpu.registers[r], value, "V{:X}", r);
+    }
+}
+
+/// Whether the pixel at (`x`, `y`) is lit.
+pub fn pixel(cpu: &CPU, x: usize, y: usize) -> bool {
+    cpu.vram[y * cpu.width + x] != 0
+}
+
+/// A CPU with the ROM `name` from `programs/` loaded.
+pub fn rom_cpu(name: &str) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_rom(&format!("{}/programs/{}", env!("CARGO_MANIFEST_DIR"), name))
+        .unwrap();
+    cpu
+}
+
+/// The path of `name` in the repository's `programs/` directory.
+pub fn program(name: &str) -> String {
+    format!("{}/programs/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
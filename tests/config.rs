@@ -0,0 +1,113 @@
+use chip8_core::config::{Config, Settings, DEFAULT_KEYS, DEFAULT_PALETTE};
+use chip8_core::Mode;
+use std::{env, fs, process};
+
+fn error(text: &str) -> String {
+    Config::parse(text).unwrap_err().message
+}
+
+#[test]
+fn reads_the_repository_config() {
+    let config = Config::load(format!("{}/config.toml", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    assert_eq!(config.global.clock_speed(), 500);
+    assert_eq!(config.global.mode(), Mode::Normal);
+}
+
+#[test]
+fn defaults_apply_to_unset_keys() {
+    let settings = Config::parse("").unwrap().for_rom("PONG");
+    assert_eq!(settings, Settings::default());
+    assert_eq!(settings.clock_speed(), 600);
+    assert_eq!(settings.mode(), Mode::Normal);
+    assert_eq!(settings.scale(), 10);
+    assert!(!settings.mute());
+    assert_eq!(settings.palette(), DEFAULT_PALETTE);
+    assert_eq!(settings.keys(), DEFAULT_KEYS.map(str::to_owned));
+}
+
+#[test]
+fn rom_overrides_and_command_line_are_layered() {
+    let config = Config::parse(
+        r##"
+        clock_speed = 700
+        mode = "debug"
+        palette = ["#102030", "#FFFFFF", "#AAAAAA", "#555555"]
+
+        [keys]
+        A = "Space"
+        5 = "Up"
+
+        [roms."PONG"]
+        clock_speed = 400
+        quirks = "vip"
+
+        [roms."PONG".keys]
+        5 = "W"
+        "##,
+    )
+    .unwrap();
+
+    let pong = config.for_rom("programs/PONG");
+    assert_eq!(pong.clock_speed(), 400);
+    assert_eq!(pong.quirks.as_deref(), Some("vip"));
+    assert_eq!(pong.mode(), Mode::Debug);
+    assert_eq!(pong.palette()[0], (0x10, 0x20, 0x30));
+    let keys = pong.keys();
+    assert_eq!(keys[0xA], "Space");
+    assert_eq!(keys[0x5], "W");
+    assert_eq!(keys[0x0], "X");
+
+    let tetris = config.for_rom("TETRIS");
+    assert_eq!(tetris.clock_speed(), 700);
+    assert_eq!(tetris.keys()[0x5], "Up");
+
+    let cli = Settings {
+        clock_speed: Some(900),
+        mode: Some("normal".to_owned()),
+        mute: Some(true),
+        ..Settings::default()
+    };
+    let settings = pong.merge(&cli);
+    assert_eq!(settings.clock_speed(), 900);
+    assert_eq!(settings.mode(), Mode::Normal);
+    assert!(settings.mute());
+    assert_eq!(settings.quirks.as_deref(), Some("vip"));
+}
+
+#[test]
+fn errors_name_the_problem() {
+    assert_eq!(
+        error("mute = true\nscale = \n"),
+        "line 2: invalid string, expected `\"`, `'`"
+    );
+    assert!(error("clock = 5").starts_with("unknown field `clock`"));
+    assert_eq!(
+        error("clock_speed = \"fast\""),
+        "`clock_speed`: invalid type: string \"fast\", expected u32"
+    );
+    assert_eq!(error("scale = 0"), "scale must be at least 1");
+    assert_eq!(
+        error("mode = \"fast\""),
+        "unknown mode `fast` (expected normal or debug)"
+    );
+    assert_eq!(
+        error("[roms.PONG]\nquirks = \"nes\""),
+        "[roms.\"PONG\"]: unknown quirks `nes` (expected chip8, schip, xochip, vip or chip48)"
+    );
+    assert_eq!(
+        error("palette = [\"#000\", \"#FFFFFF\", \"#AAAAAA\", \"#555555\"]"),
+        "invalid color `#000` (expected #RRGGBB)"
+    );
+    assert_eq!(
+        error("[keys]\nG = \"X\""),
+        "unknown keypad key `G` (expected 0-F)"
+    );
+    assert_eq!(error("roms = 5"), "`roms` must be a table");
+
+    let path = env::temp_dir().join(format!("chip8-config-{}-missing.toml", process::id()));
+    let _ = fs::remove_file(&path);
+    assert!(Config::load(&path)
+        .unwrap_err()
+        .message
+        .starts_with("cannot read"));
+}
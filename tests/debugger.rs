@@ -0,0 +1,273 @@
+mod common;
+
+use chip8_core::debugger::{Condition, Stop};
+use chip8_core::{Access, Debugger, WatchAction, WatchHit, Watchpoint};
+use common::CpuBuilder;
+
+/// V0 += 1 in a loop: 0x200 add, 0x202 jump back.
+fn counter() -> CpuBuilder {
+    CpuBuilder::new().program(&[0x7001, 0x1200])
+}
+
+#[test]
+fn breakpoint_stops_before_executing_and_continue_skips_it_once() {
+    let mut cpu = counter().build();
+    let mut debugger = Debugger::new();
+    debugger.add_breakpoint(0x202, None);
+
+    assert_eq!(
+        debugger.run_frame(&mut cpu, 10),
+        Ok(Some(Stop::Breakpoint(0x202)))
+    );
+    assert!(debugger.is_paused());
+    assert_eq!((cpu.pc, cpu.registers[0]), (0x202, 1));
+
+    // Paused frames do nothing.
+    assert_eq!(debugger.run_frame(&mut cpu, 10), Ok(None));
+    assert_eq!(cpu.pc, 0x202);
+
+    debugger.resume(&cpu);
+    assert_eq!(
+        debugger.run_frame(&mut cpu, 10),
+        Ok(Some(Stop::Breakpoint(0x202)))
+    );
+    assert_eq!(cpu.registers[0], 2);
+}
+
+#[test]
+fn conditional_breakpoint_waits_for_condition() {
+    let mut cpu = counter().build();
+    let mut debugger = Debugger::new();
+    debugger.add_breakpoint(0x200, Some(Condition::parse("V0 >= 0x05").unwrap()));
+
+    let mut stop = None;
+    for _ in 0..10 {
+        stop = debugger.run_frame(&mut cpu, 10).unwrap();
+        if stop.is_some() {
+            break;
+        }
+    }
+    assert_eq!(stop, Some(Stop::Breakpoint(0x200)));
+    assert_eq!(cpu.registers[0], 5);
+}
+
+#[test]
+fn partial_frames_tick_timers_once_per_frame() {
+    let mut cpu = counter().delay(10).build();
+    let mut debugger = Debugger::new();
+    debugger.add_breakpoint(0x202, None);
+
+    // Stops after 1 of 4 instructions; the timer only ticks once the rest
+    // of the frame has run.
+    debugger.run_frame(&mut cpu, 4).unwrap();
+    assert_eq!(cpu.delay_timer, 10);
+    debugger.remove_breakpoint(0x202);
+    debugger.resume(&cpu);
+    assert_eq!(debugger.run_frame(&mut cpu, 4), Ok(None));
+    assert_eq!((cpu.delay_timer, cpu.steps()), (9, 4));
+}
+
+#[test]
+fn step_over_runs_calls_until_they_return() {
+    // 0x200 call 0x300, 0x202 V1 = 1; 0x300 V0 = 7, 0x302 return.
+    let mut cpu = CpuBuilder::new()
+        .program(&[0x2300, 0x6101])
+        .program_at(0x300, &[0x6007, 0x00EE])
+        .build();
+    let mut debugger = Debugger::new();
+    debugger.pause();
+
+    assert_eq!(debugger.step_over(&mut cpu), Ok(true));
+    assert_eq!(
+        debugger.run_frame(&mut cpu, 10),
+        Ok(Some(Stop::Reached(0x202)))
+    );
+    assert_eq!((cpu.registers[0], cpu.sp), (7, 0));
+
+    assert_eq!(debugger.step_over(&mut cpu), Ok(false));
+    assert_eq!((cpu.pc, cpu.registers[1]), (0x204, 1));
+    assert!(debugger.is_paused());
+}
+
+#[test]
+fn run_to_stops_at_address() {
+    let mut cpu = CpuBuilder::new()
+        .program(&[0x6001, 0x6102, 0x6203, 0x1206])
+        .build();
+    let mut debugger = Debugger::new();
+    debugger.run_to(&cpu, 0x204);
+    assert_eq!(
+        debugger.run_frame(&mut cpu, 10),
+        Ok(Some(Stop::Reached(0x204)))
+    );
+    assert_eq!(cpu.registers[..3], [1, 2, 0]);
+}
+
+#[test]
+fn repl_commands() {
+    let mut cpu = counter().build();
+    let mut debugger = Debugger::new();
+
+    assert_eq!(
+        debugger.execute(&mut cpu, "b 202 if v0 == 3"),
+        Ok("breakpoint at 0x0202 if V0 == 0x3".to_owned())
+    );
+    assert!(debugger
+        .execute(&mut cpu, "s 3")
+        .unwrap()
+        .contains("PC 0x0202"));
+    assert!(debugger.is_paused());
+    assert_eq!(cpu.registers[0], 2);
+    assert!(debugger
+        .execute(&mut cpu, "m 200 4")
+        .unwrap()
+        .contains("70 01 12 00"));
+    assert_eq!(
+        debugger.execute(&mut cpu, "bl"),
+        Ok("breakpoint at 0x0202 if V0 == 0x3".to_owned())
+    );
+
+    assert!(debugger.execute(&mut cpu, "b zz").is_err());
+    assert!(debugger.execute(&mut cpu, "b 202 when v0 == 1").is_err());
+    assert!(debugger.execute(&mut cpu, "b 202 if v0 ~ 1").is_err());
+    assert!(debugger.execute(&mut cpu, "d 300").is_err());
+    assert!(debugger.execute(&mut cpu, "frobnicate").is_err());
+    assert_eq!(debugger.execute(&mut cpu, "  "), Ok(String::new()));
+
+    assert_eq!(debugger.execute(&mut cpu, "c"), Ok("continuing".to_owned()));
+    assert_eq!(
+        debugger.run_frame(&mut cpu, 10),
+        Ok(Some(Stop::Breakpoint(0x202)))
+    );
+    assert_eq!(cpu.registers[0], 3);
+}
+
+#[test]
+fn write_watchpoint_breaks_after_the_store() {
+    // V0 = 0x2A, I = 0x300, store V0-V2, then V3 = 1.
+    let mut cpu = CpuBuilder::new()
+        .program(&[0x602A, 0xA300, 0xF255, 0x6301])
+        .build();
+    cpu.watchpoints.push(Watchpoint::writes(0x301, 0x3FF));
+    let mut debugger = Debugger::new();
+
+    let stop = debugger.run_frame(&mut cpu, 10).unwrap();
+    let hit = WatchHit {
+        pc: 0x204,
+        opcode: 0xF255,
+        address: 0x301,
+        access: Access::Write,
+        value: 0,
+        action: WatchAction::Break,
+    };
+    assert_eq!(stop, Some(Stop::Watchpoint(hit)));
+    assert_eq!(
+        stop.unwrap().to_string(),
+        "watchpoint: write 0x00 to 0x0301 by F255 at 0x0204"
+    );
+    assert_eq!((cpu.pc, cpu.registers[3]), (0x206, 0));
+    // The second byte of the same store is logged rather than lost.
+    assert_eq!(debugger.take_log()[0].address, 0x302);
+}
+
+#[test]
+fn stepping_reports_watchpoint_hits() {
+    // V0 = 0x2A, I = 0x300, store V0-V2, then V3 = 1.
+    let store = || {
+        let mut cpu = CpuBuilder::new()
+            .program(&[0x602A, 0xA300, 0xF255, 0x6301])
+            .build();
+        cpu.watchpoints.push(Watchpoint::writes(0x300, 0x300));
+        cpu
+    };
+    let mut cpu = store();
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.step(&mut cpu), Ok(None));
+    assert_eq!(debugger.step(&mut cpu), Ok(None));
+    let stop = debugger.step(&mut cpu).unwrap().unwrap();
+    assert_eq!(
+        stop.to_string(),
+        "watchpoint: write 0x2A to 0x0300 by F255 at 0x0204"
+    );
+
+    // `s N` stops at the hit; `s` and `n` show it above the registers.
+    let mut cpu = store();
+    let out = debugger.execute(&mut cpu, "s 10").unwrap();
+    assert!(out.starts_with("watchpoint: write 0x2A "), "{}", out);
+    assert_eq!(cpu.pc, 0x206);
+    for command in ["s", "n"] {
+        let mut cpu = store();
+        debugger.execute(&mut cpu, "s 2").unwrap();
+        let out = debugger.execute(&mut cpu, command).unwrap();
+        assert!(out.starts_with("watchpoint: write 0x2A "), "{}", out);
+        assert_eq!(cpu.pc, 0x206);
+    }
+}
+
+#[test]
+fn logging_watchpoints_keep_running() {
+    // Draw the 0 glyph, load it back into V0-V1 and store V0 as BCD.
+    let mut cpu = CpuBuilder::new()
+        .program(&[0xA000, 0xD005, 0xF165, 0xA300, 0xF033, 0x120A])
+        .build();
+    cpu.watchpoints.push(Watchpoint {
+        start: 0x000,
+        end: 0x001,
+        reads: true,
+        writes: false,
+        action: WatchAction::Log,
+    });
+    cpu.watchpoints.push(Watchpoint {
+        action: WatchAction::Log,
+        ..Watchpoint::writes(0x300, 0x302)
+    });
+    let mut debugger = Debugger::new();
+
+    assert_eq!(debugger.run_frame(&mut cpu, 10), Ok(None));
+    let log: Vec<(u16, u16, Access, u8)> = debugger
+        .take_log()
+        .iter()
+        .map(|hit| (hit.opcode, hit.address, hit.access, hit.value))
+        .collect();
+    assert_eq!(
+        log,
+        [
+            (0xD005, 0x000, Access::Read, 0xF0),
+            (0xD005, 0x001, Access::Read, 0x90),
+            (0xF165, 0x000, Access::Read, 0xF0),
+            (0xF165, 0x001, Access::Read, 0x90),
+            (0xF033, 0x300, Access::Write, 2),
+            (0xF033, 0x301, Access::Write, 4),
+            (0xF033, 0x302, Access::Write, 0),
+        ]
+    );
+    assert!(debugger.take_log().is_empty());
+}
+
+#[test]
+fn repl_watch_commands() {
+    let mut cpu = CpuBuilder::new().build();
+    let mut debugger = Debugger::new();
+
+    assert_eq!(
+        debugger.execute(&mut cpu, "w 300-30F"),
+        Ok("watch 0x0300-0x030F writes, break".to_owned())
+    );
+    assert_eq!(
+        debugger.execute(&mut cpu, "watch 0x400 rw log"),
+        Ok("watch 0x0400 reads and writes, log".to_owned())
+    );
+    assert_eq!(cpu.watchpoints.len(), 2);
+    assert_eq!(
+        debugger.execute(&mut cpu, "wl"),
+        Ok("watch 0x0300-0x030F writes, break\nwatch 0x0400 reads and writes, log".to_owned())
+    );
+
+    assert!(debugger.execute(&mut cpu, "w 30F-300").is_err());
+    assert!(debugger.execute(&mut cpu, "w 300 x").is_err());
+    assert!(debugger.execute(&mut cpu, "uw 500").is_err());
+    assert!(debugger.execute(&mut cpu, "uw 300").is_ok());
+    assert_eq!(cpu.watchpoints.len(), 1);
+    assert!(debugger.execute(&mut cpu, "uw").is_ok());
+    assert!(cpu.watchpoints.is_empty());
+}
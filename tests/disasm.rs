@@ -0,0 +1,157 @@
+use chip8_core::disasm::{disassemble, trace, AluOp, Instruction, Syntax};
+use chip8_core::Platform;
+
+fn rom(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/programs/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+fn bytes(opcodes: &[u16]) -> Vec<u8> {
+    opcodes.iter().flat_map(|op| op.to_be_bytes()).collect()
+}
+
+/// The bytes from the address comments at the end of each line.
+fn listed_bytes(listing: &str) -> Vec<u8> {
+    listing
+        .lines()
+        .filter(|line| line.starts_with("    "))
+        .flat_map(|line| {
+            let hex = line.rsplit("  ").next().unwrap();
+            (0..hex.len())
+                .step_by(2)
+                .map(move |i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        })
+        .collect()
+}
+
+#[test]
+fn ibm_logo_listing() {
+    let listing = disassemble(&rom("ibm.ch8"), Platform::Chip8, Syntax::Cowgod);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], "    CLS                         ; 0200  00E0");
+    assert_eq!(lines[1], "    LD I, L22A                  ; 0202  A22A");
+    assert_eq!(lines[4], "    DRW V0, V1, 15              ; 0208  D01F");
+    assert_eq!(
+        lines[20..23],
+        [
+            "L228:",
+            "    JP L228                     ; 0228  1228",
+            "L22A:"
+        ]
+    );
+    assert!(lines[23].starts_with("    DB #FF, #00, #FF, #00"));
+
+    let octo = disassemble(&rom("ibm.ch8"), Platform::Chip8, Syntax::Octo);
+    assert!(octo.starts_with(": main\n    clear"));
+    assert!(octo.contains("\n    i := L22A "));
+    assert!(octo.contains("\n: L228\n    jump L228 "));
+    assert!(octo.contains("\n    0xFF 0x00 0xFF 0x00"));
+}
+
+#[test]
+fn listings_cover_every_byte() {
+    for name in [
+        "ibm.ch8",
+        "PONG",
+        "TETRIS",
+        "test_opcode.ch8",
+        "BC_test.ch8",
+        "invaders.c8",
+    ] {
+        let rom = rom(name);
+        for syntax in [Syntax::Cowgod, Syntax::Octo] {
+            let listing = disassemble(&rom, Platform::SuperChip, syntax);
+            assert_eq!(listed_bytes(&listing), rom, "{} {:?}", name, syntax);
+        }
+    }
+}
+
+#[test]
+fn unreachable_bytes_are_data() {
+    // 0x200 skip, 0x202 jump over 0x204, 0x206 call, 0x208 exit; 0x20A is the subroutine.
+    let rom = bytes(&[0x3000, 0x1206, 0x6001, 0x220A, 0x1208, 0x00EE, 0xFFFF]);
+    let code = trace(&rom, Platform::Chip8);
+    let addrs: Vec<u16> = code.keys().copied().collect();
+    assert_eq!(addrs, [0x200, 0x202, 0x204, 0x206, 0x208, 0x20A]);
+
+    let rom = bytes(&[0x1204, 0x6001, 0x00EE]);
+    let listing = disassemble(&rom, Platform::Chip8, Syntax::Cowgod);
+    assert!(listing.contains("    DB #60, #01 "));
+    assert!(listing.contains("L204:\n    RET "));
+}
+
+#[test]
+fn skip_over_long_load_skips_four_bytes() {
+    let rom = bytes(&[0x3000, 0xF000, 0x0300, 0x00E0]);
+    let code = trace(&rom, Platform::XoChip);
+    assert_eq!(code[&0x202], Instruction::LoadILong(0x300));
+    assert_eq!(code[&0x206], Instruction::Cls);
+    assert!(!code.contains_key(&0x204));
+}
+
+#[test]
+fn calls_and_jump_tables() {
+    let rom = bytes(&[0x2206, 0xB208, 0x0000, 0x00EE, 0x1200]);
+    let listing = disassemble(&rom, Platform::Chip8, Syntax::Octo);
+    assert!(listing.contains("\n    L206 "));
+    assert!(listing.contains("\n    jump0 L208 "));
+    assert!(listing.contains("\n: L208\n    jump main "));
+    // 0x204 is only reached through the table and stays data.
+    assert!(listing.contains("\n    0x00 0x00 "));
+}
+
+#[test]
+fn decodes_per_platform() {
+    let decode = |op: u16, platform| Instruction::decode(&op.to_be_bytes(), platform);
+    assert_eq!(decode(0x00FF, Platform::Chip8), None);
+    assert_eq!(
+        decode(0x00FF, Platform::SuperChip),
+        Some(Instruction::Hires)
+    );
+    assert_eq!(decode(0x00D2, Platform::SuperChip), None);
+    assert_eq!(
+        decode(0x00D2, Platform::XoChip),
+        Some(Instruction::ScrollUp(2))
+    );
+    assert_eq!(
+        decode(0x5122, Platform::XoChip),
+        Some(Instruction::SaveRange(1, 2))
+    );
+    assert_eq!(decode(0x5122, Platform::SuperChip), None);
+    assert_eq!(
+        decode(0x8AB6, Platform::Chip8),
+        Some(Instruction::Alu(AluOp::Shr, 0xA, 0xB))
+    );
+    assert_eq!(decode(0x8AB8, Platform::Chip8), None);
+    assert_eq!(decode(0x9121, Platform::Chip8), None);
+    assert_eq!(decode(0xF000, Platform::XoChip), None);
+    assert_eq!(
+        decode(0xF330, Platform::SuperChip),
+        Some(Instruction::BigFont(3))
+    );
+}
+
+#[test]
+fn formats_both_syntaxes() {
+    let hex = |addr: u16| format!("{:X}", addr);
+    let cases = [
+        (0x3A05u16, "SE VA, #05", "if va != 0x05 then"),
+        (0x9AB0, "SNE VA, VB", "if va == vb then"),
+        (0x8AB7, "SUBN VA, VB", "va =- vb"),
+        (0xC1FF, "RND V1, #FF", "v1 := random 0xFF"),
+        (0xE29E, "SKP V2", "if v2 -key then"),
+        (0xF40A, "LD V4, K", "v4 := key"),
+        (0xF518, "LD ST, V5", "buzzer := v5"),
+        (0xF629, "LD F, V6", "i := hex v6"),
+        (0xF733, "LD B, V7", "bcd v7"),
+        (0xF855, "LD [I], V8", "save v8"),
+        (0xF965, "LD V9, [I]", "load v9"),
+        (0xFA85, "LD VA, R", "loadflags va"),
+        (0x00C4, "SCD 4", "scroll-down 4"),
+        (0x2345, "CALL 345", ":call 345"),
+    ];
+    for (op, cowgod, octo) in cases {
+        let instruction = Instruction::decode(&op.to_be_bytes(), Platform::SuperChip).unwrap();
+        assert_eq!(instruction.format(Syntax::Cowgod, &hex), cowgod);
+        assert_eq!(instruction.format(Syntax::Octo, &hex), octo);
+    }
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use chip8_core::gdb::GdbStub;
+use chip8_core::{Platform, CPU};
+use common::CpuBuilder;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+/// A minimal RSP client: sends packets and checks the acks and checksums of
+/// the replies.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn byte(&mut self) -> u8 {
+        let mut b = [0];
+        self.stream.read_exact(&mut b).unwrap();
+        b[0]
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        self.stream.write_all(bytes).unwrap();
+    }
+
+    fn send(&mut self, data: &str) {
+        let sum = data.bytes().fold(0u8, |s, b| s.wrapping_add(b));
+        self.send_raw(format!("${}#{:02x}", data, sum).as_bytes());
+        assert_eq!(self.byte(), b'+', "ack for {}", data);
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                b'}' => {
+                    let b = self.byte();
+                    data.push(b ^ 0x20);
+                }
+                b => data.push(b),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let escaped_sum = data.iter().fold(0u8, |s, &b| {
+            let escaped = matches!(b, b'$' | b'#' | b'}' | b'*');
+            s.wrapping_add(if escaped {
+                b'}'.wrapping_add(b ^ 0x20)
+            } else {
+                b
+            })
+        });
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!("{:02x}", escaped_sum)
+        );
+        self.send_raw(b"+");
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.reply()
+    }
+}
+
+/// Serves `cpu` on a local port, returning a connected client and the
+/// thread, which hands the CPU back when the session ends.
+fn start(cpu: CPU) -> (Client, JoinHandle<CPU>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut cpu = cpu;
+        let (stream, _) = listener.accept().unwrap();
+        GdbStub::new(stream, 10).serve(&mut cpu).unwrap();
+        cpu
+    });
+    let stream = TcpStream::connect(address).unwrap();
+    stream.set_nodelay(true).unwrap();
+    (Client { stream }, server)
+}
+
+#[test]
+fn registers_and_memory() {
+    let cpu = CpuBuilder::new()
+        .program(&[0x6001, 0x7001])
+        .reg(3, 0x2A)
+        .i(0x345)
+        .build();
+    let (mut client, server) = start(cpu);
+
+    assert_eq!(client.request("?"), "S05");
+    assert_eq!(
+        client.request("g"),
+        format!("0000002a{}{}", "00".repeat(12), "4503000200")
+    );
+    assert_eq!(client.request("p11"), "0002");
+    assert_eq!(client.request("P3=07"), "OK");
+    assert_eq!(client.request("p3"), "07");
+    assert_eq!(client.request("P10=0003"), "OK");
+    assert_eq!(client.request("p20"), "E01");
+    // a stack pointer past the 16-entry stack would make the next 00EE panic
+    assert_eq!(client.request("P12=11"), "E01");
+    assert_eq!(client.request(&format!("G{}", "ff".repeat(21))), "E01");
+    assert_eq!(client.request("p0"), "00");
+    assert_eq!(client.request("P12=10"), "OK");
+    assert_eq!(client.request("P12=00"), "OK");
+    assert_eq!(
+        client.request(&format!("G{}3412070300", "11".repeat(16))),
+        "OK"
+    );
+    assert_eq!(client.request("p0"), "11");
+    assert_eq!(client.request("p12"), "00");
+
+    assert_eq!(client.request("m200,4"), "60017001");
+    assert_eq!(client.request("M300,2:abcd"), "OK");
+    assert_eq!(client.request("m300,2"), "abcd");
+    assert_eq!(client.request("mfff,2"), "E01");
+    assert_eq!(client.request("M300,2:ab"), "E01");
+
+    assert!(client
+        .request("qSupported:multiprocess+")
+        .contains("qXfer:features:read+"));
+    let xml = client.request("qXfer:features:read:target.xml:0,20");
+    assert!(xml.starts_with("m<?xml"), "{}", xml);
+    let xml = client.request("qXfer:features:read:target.xml:10,ffffffffffffffff");
+    assert!(xml.starts_with('l'), "{}", xml);
+    assert_eq!(client.request("qAttached"), "1");
+    assert_eq!(client.request("vMustReplyEmpty"), "");
+    assert_eq!(client.request("Z2,300,1"), "");
+    assert_eq!(client.request("D"), "OK");
+
+    let cpu = server.join().unwrap();
+    assert_eq!(cpu.registers[3], 0x11);
+    assert_eq!(cpu.index_register, 0x1234);
+    assert_eq!(cpu.pc, 0x307);
+    assert_eq!(&cpu.memory[0x300..0x302], [0xAB, 0xCD]);
+}
+
+#[test]
+fn step_continue_breakpoints_and_interrupt() {
+    // V0 := 1, then V0 += 1 in a loop
+    let cpu = CpuBuilder::new().program(&[0x6001, 0x7001, 0x1202]).build();
+    let (mut client, server) = start(cpu);
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p11"), "0202");
+    assert_eq!(client.request("Z0,204,2"), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p11"), "0402");
+    assert_eq!(client.request("p0"), "02");
+    // continuing from the breakpoint runs a whole loop before it stops again
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p0"), "03");
+    assert_eq!(client.request("z0,204,2"), "OK");
+
+    client.send("c");
+    client.send_raw(&[0x03]);
+    assert_eq!(client.reply(), "S02");
+    // continuing at an address sets PC first
+    client.send("c202");
+    client.send_raw(&[0x03]);
+    assert_eq!(client.reply(), "S02");
+    client.send("k");
+
+    let cpu = server.join().unwrap();
+    assert!(cpu.registers[0] > 3);
+}
+
+#[test]
+fn faults_exits_and_bad_checksums() {
+    let (mut client, server) = start(CpuBuilder::new().program(&[0xFFFF]).build());
+    client.send_raw(b"$g#00");
+    assert_eq!(client.byte(), b'-');
+    assert_eq!(client.request("c"), "S04");
+    assert_eq!(client.request("p11"), "0002");
+    client.send("k");
+    server.join().unwrap();
+
+    let cpu = CpuBuilder::new()
+        .platform(Platform::SuperChip)
+        .program(&[0x6005, 0x00FD])
+        .build();
+    let (mut client, server) = start(cpu);
+    assert_eq!(client.request("c"), "W00");
+    let cpu = server.join().unwrap();
+    assert!(cpu.exited);
+    assert_eq!(cpu.registers[0], 5);
+}
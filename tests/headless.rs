@@ -0,0 +1,230 @@
+//! Runs the `chip8-headless` binary the way a CI job would.
+
+mod common;
+
+use common::program;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+
+fn headless(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8-headless"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Writes `bytes` to a file in the temp directory, unique to this test.
+fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = env::temp_dir().join(format!("chip8-headless-{}-{}", process::id(), name));
+    fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn reports_state_and_draws_text_screen() {
+    let out = headless(&[&program("ibm.ch8"), "--frames", "60", "--screen", "-"]);
+    assert_eq!(out.status.code(), Some(0));
+
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("status: ok\n"), "{}", stdout);
+    assert!(stdout.contains("cycles: 600\n"), "{}", stdout);
+    assert!(stdout.contains("memory: "), "{}", stdout);
+    let golden = fs::read_to_string(format!(
+        "{}/tests/golden/ibm.txt",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap();
+    assert!(stdout.ends_with(&golden), "{}", stdout);
+}
+
+#[test]
+fn identical_runs_hash_the_same() {
+    let memory_line = |out: Output| {
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .lines()
+            .find(|l| l.starts_with("memory: "))
+            .unwrap()
+            .to_owned()
+    };
+    let a = headless(&[&program("PONG"), "--frames", "120"]);
+    let b = headless(&[&program("PONG"), "--frames", "120"]);
+    assert_eq!(memory_line(a), memory_line(b));
+}
+
+#[test]
+fn fault_sets_exit_code() {
+    // 0x0123 is a machine code call, unsupported by the interpreter.
+    let rom = temp_file("fault.ch8", &[0x60, 0x01, 0x01, 0x23]);
+    let out = headless(&[rom.to_str().unwrap(), "--cycles", "10"]);
+    fs::remove_file(&rom).unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("status: fault: "), "{}", stdout);
+    assert!(stdout.contains("cycles: 1\n"), "{}", stdout);
+}
+
+#[test]
+fn scripted_keys_reach_the_program() {
+    // FX0A waits for a key, then the key is copied into V1 and the program loops.
+    let rom = temp_file("keys.ch8", &[0xF1, 0x0A, 0x12, 0x02]);
+    let out = headless(&[rom.to_str().unwrap(), "--frames", "5", "--press", "b@2"]);
+    fs::remove_file(&rom).unwrap();
+
+    assert_eq!(out.status.code(), Some(0));
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("v: 00 0B "), "{}", stdout);
+}
+
+#[test]
+fn writes_png_screen() {
+    let png = env::temp_dir().join(format!("chip8-headless-{}-ibm.png", process::id()));
+    let out = headless(&[
+        &program("ibm.ch8"),
+        "--frames",
+        "60",
+        "--screen",
+        png.to_str().unwrap(),
+        "--scale",
+        "2",
+    ]);
+    assert_eq!(out.status.code(), Some(0));
+    let bytes = fs::read(&png).unwrap();
+    fs::remove_file(&png).unwrap();
+    assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+}
+
+#[test]
+fn recorded_movie_plays_back_in_sync() {
+    let movie = env::temp_dir().join(format!("chip8-headless-{}-pong.c8m", process::id()));
+    let movie = movie.to_str().unwrap();
+    let rom = program("PONG");
+    let record = headless(&[
+        &rom,
+        "--frames",
+        "300",
+        "--seed",
+        "5",
+        "--press",
+        "1@0:100",
+        "--press",
+        "4@150:100",
+        "--record",
+        movie,
+    ]);
+    assert_eq!(record.status.code(), Some(0));
+
+    let play = headless(&[&rom, "--play", movie]);
+    let stdout = String::from_utf8(play.stdout).unwrap();
+    assert_eq!(play.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("cycles: 3000\n"), "{}", stdout);
+    assert!(stdout.contains("movie: in sync\n"), "{}", stdout);
+    let state_lines = |out: &str| {
+        out.lines()
+            .take_while(|l| !l.starts_with("movie"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    assert_eq!(
+        state_lines(&stdout),
+        state_lines(&String::from_utf8(record.stdout).unwrap())
+    );
+
+    // Flip a key in the last frame: the run no longer ends in the recorded state.
+    let mut bytes = fs::read(movie).unwrap();
+    let last = bytes.len() - 2;
+    bytes[last] ^= 0x01;
+    fs::write(movie, &bytes).unwrap();
+    let desync = headless(&[&rom, "--play", movie]);
+    fs::remove_file(movie).unwrap();
+    assert_eq!(desync.status.code(), Some(3));
+}
+
+#[test]
+fn missing_rom_is_a_usage_error() {
+    let out = headless(&["does-not-exist.ch8"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn writes_a_trace_line_per_instruction() {
+    let path = temp_file("trace.txt", b"");
+    let out = headless(&[
+        &program("ibm.ch8"),
+        "--cycles",
+        "20",
+        "--trace",
+        path.to_str().unwrap(),
+    ]);
+    assert_eq!(out.status.code(), Some(0));
+
+    let trace = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines.len(), 20);
+    assert!(
+        lines[0].starts_with("000000 PC:0200 OP:00E0 CLS "),
+        "{}",
+        lines[0]
+    );
+    assert!(lines[19].starts_with("000019 "), "{}", lines[19]);
+
+    let out = headless(&[
+        &program("ibm.ch8"),
+        "--cycles",
+        "2",
+        "--trace",
+        path.to_str().unwrap(),
+        "--trace-format",
+        "cadmium",
+    ]);
+    assert_eq!(out.status.code(), Some(0));
+    let trace = fs::read_to_string(&path).unwrap();
+    assert!(
+        trace.ends_with(" I:0000 SP:0 PC:0202 O:a22a\n"),
+        "{}",
+        trace
+    );
+    let out = headless(&[&program("ibm.ch8"), "--trace-format", "bochs"]);
+    assert_eq!(out.status.code(), Some(2));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn quirks_preset_is_checked() {
+    // 00FF switches to hires on SUPER-CHIP, and is an unknown opcode on CHIP-8.
+    let rom = temp_file("hires.ch8", &[0x00, 0xFF, 0x12, 0x02]);
+    let run = |option: &str, name: &str| {
+        let out = headless(&[rom.to_str().unwrap(), "--cycles", "4", option, name]);
+        out.status.code()
+    };
+    assert_eq!(run("--quirks", "schip"), Some(0));
+    // --profile is the old name of --quirks
+    assert_eq!(run("--profile", "chip8"), Some(1));
+    assert_eq!(run("--quirks", "nes"), Some(2));
+    fs::remove_file(&rom).unwrap();
+}
+
+#[test]
+fn huge_counts_do_not_overflow() {
+    let max = u64::MAX.to_string();
+    let press = format!("1@1:{}", max);
+    let out = headless(&[&program("ibm.ch8"), "--cycles", "100", "--press", &press]);
+    assert_eq!(out.status.code(), Some(0));
+
+    // 00FD exits, so the run ends long before u64::MAX instructions.
+    let rom = temp_file("exit.ch8", &[0x00, 0xFD]);
+    let out = headless(&[
+        rom.to_str().unwrap(),
+        "--quirks",
+        "schip",
+        "--frames",
+        &max,
+        "--cycles-per-frame",
+        "1000",
+    ]);
+    fs::remove_file(&rom).unwrap();
+    assert_eq!(out.status.code(), Some(0));
+}
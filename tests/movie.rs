@@ -0,0 +1,107 @@
+mod common;
+
+use chip8_core::movie::{MOVIE_MAGIC, MOVIE_VERSION};
+use chip8_core::{Movie, MovieError, Platform, Rng, CPU};
+use common::rom_cpu;
+
+/// Records `frames` frames of PONG with a paddle key pattern and a seeded RNG.
+fn record_pong(frames: usize) -> (Movie, CPU) {
+    let mut cpu = rom_cpu("PONG");
+    cpu.rng = Rng::new(1234);
+    let mut movie = Movie::new(&cpu, 10);
+    for frame in 0..frames {
+        cpu.key = [0; 16];
+        cpu.key[if frame / 20 % 2 == 0 { 0x1 } else { 0x4 }] = 1;
+        movie.record(&cpu.key);
+        cpu.run_frame(10).unwrap();
+    }
+    movie.finish(&cpu);
+    (movie, cpu)
+}
+
+#[test]
+fn playback_reproduces_recording() {
+    let (movie, recorded) = record_pong(600);
+    let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    assert_eq!(movie.len(), 600);
+
+    let mut cpu = rom_cpu("PONG");
+    movie.start(&mut cpu).unwrap();
+    movie.play(&mut cpu).unwrap();
+    assert_eq!(cpu.state_hash(), recorded.state_hash());
+    assert_eq!(movie.verify(&cpu), Ok(()));
+}
+
+#[test]
+fn changed_input_desyncs() {
+    let (mut movie, _) = record_pong(300);
+    for mask in &mut movie.frames[100..200] {
+        *mask = 0;
+    }
+    let mut cpu = rom_cpu("PONG");
+    movie.start(&mut cpu).unwrap();
+    movie.play(&mut cpu).unwrap();
+    assert!(matches!(movie.verify(&cpu), Err(MovieError::Desync { .. })));
+}
+
+#[test]
+fn restores_run_settings() {
+    let mut cpu = rom_cpu("ibm.ch8");
+    cpu.set_platform(Platform::SuperChip);
+    cpu.rng = Rng::cosmac_vip(77);
+    let movie = Movie::from_bytes(&Movie::new(&cpu, 15).to_bytes()).unwrap();
+
+    let mut fresh = rom_cpu("ibm.ch8");
+    movie.start(&mut fresh).unwrap();
+    assert_eq!(fresh.platform, Platform::SuperChip);
+    assert_eq!(fresh.quirks, cpu.quirks);
+    assert_eq!(fresh.rng, cpu.rng);
+    assert_eq!(movie.cycles_per_frame, 15);
+    assert_eq!(movie.final_hash, None);
+}
+
+#[test]
+fn keys_round_trip_through_masks() {
+    let mut movie = Movie::new(&rom_cpu("ibm.ch8"), 10);
+    let mut keys = [0; 16];
+    keys[0x0] = 1;
+    keys[0xA] = 1;
+    keys[0xF] = 1;
+    movie.record(&keys);
+    assert_eq!(movie.frames, [0x8401]);
+    assert_eq!(movie.keys(0), Some(keys));
+    assert_eq!(movie.keys(1), None);
+}
+
+#[test]
+fn rejects_other_rom() {
+    let (movie, _) = record_pong(1);
+    let mut cpu = rom_cpu("ibm.ch8");
+    assert!(matches!(
+        movie.start(&mut cpu),
+        Err(MovieError::RomMismatch { .. })
+    ));
+}
+
+#[test]
+fn rejects_bad_files() {
+    let (movie, _) = record_pong(10);
+    let good = movie.to_bytes();
+    assert_eq!(good[..4], MOVIE_MAGIC);
+
+    let mut bad_magic = good.clone();
+    bad_magic[..4].copy_from_slice(b"CH8S");
+    assert_eq!(Movie::from_bytes(&bad_magic), Err(MovieError::BadMagic));
+
+    let mut future = good.clone();
+    future[4..6].copy_from_slice(&(MOVIE_VERSION + 1).to_le_bytes());
+    assert_eq!(
+        Movie::from_bytes(&future),
+        Err(MovieError::UnsupportedVersion(MOVIE_VERSION + 1))
+    );
+
+    assert_eq!(
+        Movie::from_bytes(&good[..good.len() - 1]),
+        Err(MovieError::Truncated)
+    );
+}
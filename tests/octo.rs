@@ -0,0 +1,224 @@
+use chip8_core::disasm::{disassemble, Syntax};
+use chip8_core::octo::{compile, OctoError};
+use chip8_core::{Platform, CPU};
+use std::{env, fs, process};
+
+fn error(source: &str) -> (usize, String) {
+    let e = compile(source).unwrap_err();
+    (e.line, e.message)
+}
+
+/// Runs `rom` on SUPER-CHIP until it exits.
+fn run(rom: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.set_platform(Platform::SuperChip);
+    cpu.load_rom_bytes(rom).unwrap();
+    for _ in 0..100 {
+        if cpu.exited {
+            return cpu;
+        }
+        cpu.step().unwrap();
+    }
+    panic!("program did not exit");
+}
+
+#[test]
+fn disassembly_compiles_back_to_the_rom() {
+    for name in [
+        "ibm.ch8",
+        "PONG",
+        "PONG2",
+        "TETRIS",
+        "BLITZ",
+        "MAZE",
+        "test_opcode.ch8",
+        "BC_test.ch8",
+        "c8_test.c8",
+        "invaders.c8",
+        "breakout.rom",
+        "life.ch8",
+    ] {
+        let rom = fs::read(format!("{}/programs/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+        let listing = disassemble(&rom, Platform::SuperChip, Syntax::Octo);
+        assert_eq!(compile(&listing).as_deref(), Ok(&rom[..]), "{}", name);
+    }
+
+    let rom = [
+        0x00, 0xD3, 0x51, 0x42, 0x51, 0x43, 0xF0, 0x00, 0x12, 0x34, 0xF2, 0x01, 0xF0, 0x02, 0xF5,
+        0x3A, 0x12, 0x00,
+    ];
+    let listing = disassemble(&rom, Platform::XoChip, Syntax::Octo);
+    assert!(listing.contains("i := long 0x1234"));
+    assert_eq!(compile(&listing).as_deref(), Ok(&rom[..]));
+}
+
+#[test]
+fn structured_control_flow() {
+    let source = "
+    : main
+        loop
+            v0 -= 1
+            while v0 != 0
+        again
+        if v1 == v2 begin
+            v3 := 1
+        else
+            v3 := 2
+        end
+        if v4 > 7 then v5 := 1
+        if v6 key then v7 := 0
+    ";
+    assert_eq!(
+        compile(source).unwrap(),
+        [
+            0x70, 0xFF, 0x40, 0x00, 0x12, 0x08, 0x12, 0x00, 0x51, 0x20, 0x12, 0x10, 0x63, 0x01,
+            0x12, 0x12, 0x63, 0x02, 0x6F, 0x07, 0x8F, 0x45, 0x3F, 0x01, 0x65, 0x01, 0xE6, 0xA1,
+            0x67, 0x00,
+        ]
+    );
+}
+
+#[test]
+fn comparisons_run_correctly() {
+    type Compare = fn(u8, u8) -> bool;
+    let operators: [(&str, Compare); 6] = [
+        ("==", |a, b| a == b),
+        ("!=", |a, b| a != b),
+        ("<", |a, b| a < b),
+        (">", |a, b| a > b),
+        ("<=", |a, b| a <= b),
+        (">=", |a, b| a >= b),
+    ];
+    for (operator, expected) in operators.iter() {
+        for &(a, b) in &[(3, 3), (2, 9), (9, 2), (0, 255)] {
+            // both forms, with a register and with a constant on the right
+            let source = format!(
+                ": main
+                    v0 := {a}  v1 := {b}
+                    v2 := 0  v3 := 0
+                    if v0 {op} v1 then v2 := 1
+                    if v0 {op} {b} begin v3 := 1 else v3 := 2 end
+                    exit",
+                a = a,
+                b = b,
+                op = operator
+            );
+            let cpu = run(&compile(&source).unwrap());
+            let (then, begin) = if expected(a, b) { (1, 1) } else { (0, 2) };
+            assert_eq!(cpu.registers[2], then, "{} {} {}", a, operator, b);
+            assert_eq!(cpu.registers[3], begin, "{} {} {}", a, operator, b);
+        }
+    }
+}
+
+#[test]
+fn macros_calc_alias_and_next() {
+    let source = "
+        :alias x v3
+        :const SPEED 2
+        :calc DOUBLE { SPEED * 2 + 1 }  # no precedence: 2 * (2 + 1)
+        :macro add-to reg n { reg += n }
+    : main
+        x := DOUBLE
+        add-to x SPEED
+        :next target v4 := 9
+        i := target
+        :unpack 0xA target
+        :byte { HERE & 0xFF }
+        0x12 -1
+    ";
+    assert_eq!(
+        compile(source).unwrap(),
+        [
+            0x63, 0x06, 0x73, 0x02, 0x64, 0x09, 0xA2, 0x05, 0x60, 0xA2, 0x61, 0x05, 0x0C, 0x12,
+            0xFF,
+        ]
+    );
+}
+
+#[test]
+fn main_org_and_forward_labels() {
+    assert_eq!(compile(": main clear").unwrap(), [0x00, 0xE0]);
+    assert_eq!(
+        compile(": sub return : main sub").unwrap(),
+        [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]
+    );
+    assert_eq!(
+        compile(": main draw-it ; : draw-it clear ;").unwrap(),
+        [0x22, 0x04, 0x00, 0xEE, 0x00, 0xE0, 0x00, 0xEE]
+    );
+    assert_eq!(
+        compile(": main jump far :org 0x208 : far exit").unwrap(),
+        [0x12, 0x08, 0, 0, 0, 0, 0, 0, 0x00, 0xFD]
+    );
+    let rom = compile(": main i := long data :org 0x210 : data 1").unwrap();
+    assert_eq!(rom[..4], [0xF0, 0x00, 0x02, 0x10]);
+    assert_eq!(rom.len(), 0x11);
+    assert_eq!(rom[0x10], 1);
+}
+
+#[test]
+fn errors_have_line_numbers() {
+    assert_eq!(error("clear").1, "the program has no `main` label");
+    assert_eq!(
+        error(": main\n\njump nowhere"),
+        (3, "undefined label `nowhere`".to_owned())
+    );
+    assert_eq!(
+        error(": main\nloop\nv0 += 1"),
+        (2, "block is never closed".to_owned())
+    );
+    assert_eq!(
+        error(": main\nv0 := 256"),
+        (2, "256 is out of range".to_owned())
+    );
+    assert_eq!(
+        error(": main\njump 0x1000"),
+        (2, "address 0x1000 does not fit in 12 bits".to_owned())
+    );
+    assert_eq!(
+        error(": main\nelse"),
+        (2, "`else` without `if ... begin`".to_owned())
+    );
+    assert_eq!(
+        error(": main\n: main"),
+        (2, "`main` is already defined".to_owned())
+    );
+    assert_eq!(
+        error(": main\n:calc X { 1 % 0 }"),
+        (2, "division by zero".to_owned())
+    );
+    assert_eq!(
+        error(": main\n:calc X { ( - 2 pow 63 ) % - 1 }"),
+        (2, "value out of range".to_owned())
+    );
+    assert_eq!(
+        error(":macro forever { forever }\n: main forever").1,
+        "macro `forever` expands forever"
+    );
+    assert_eq!(
+        OctoError {
+            line: 7,
+            message: "oops".to_owned()
+        }
+        .to_string(),
+        "line 7: oops"
+    );
+}
+
+#[test]
+fn compiled_rom_loads() {
+    let rom = compile(": main v0 := 5 loop v0 += 1 while v0 != 9 again exit").unwrap();
+    let path = env::temp_dir().join(format!("chip8-octo-{}-count.ch8", process::id()));
+    fs::write(&path, &rom).unwrap();
+
+    let mut cpu = CPU::new();
+    cpu.set_platform(Platform::SuperChip);
+    assert_eq!(cpu.load_rom(path.to_str().unwrap()).unwrap(), rom.len());
+    for _ in 0..30 {
+        cpu.step().unwrap();
+    }
+    assert!(cpu.exited);
+    assert_eq!(cpu.registers[0], 9);
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,1059 @@
+//! One or more focused tests per CHIP-8 instruction.
+
+mod common;
+
+use chip8_core::{CpuError, LoadStore, Platform, Quirks, Rng};
+use common::{assert_regs, pixel, CpuBuilder};
+
+// 0NNN / 00E0 / 00EE
+
+#[test]
+fn sys_0nnn_is_unknown() {
+    let mut cpu = CpuBuilder::new().program(&[0x0123]).build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0x0123
+        })
+    );
+    assert_eq!(cpu.pc, 0x200);
+}
+
+#[test]
+fn cls_00e0_clears_screen() {
+    let mut cpu = CpuBuilder::new().program(&[0x00E0]).build();
+    cpu.vram.iter_mut().for_each(|p| *p = 1);
+    cpu.step().unwrap();
+    assert!(cpu.vram.iter().all(|&p| p == 0));
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+fn ret_00ee_pops_stack() {
+    let cpu = CpuBuilder::new().stack(&[0x300]).program(&[0x00EE]).run(1);
+    assert_eq!(cpu.sp, 0);
+    assert_eq!(cpu.pc, 0x302);
+}
+
+#[test]
+fn ret_00ee_on_empty_stack_underflows() {
+    let mut cpu = CpuBuilder::new().program(&[0x00EE]).build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::StackUnderflow {
+            pc: 0x200,
+            opcode: 0x00EE
+        })
+    );
+    assert_eq!(cpu.sp, 0);
+}
+
+// 1NNN / 2NNN / BNNN
+
+#[test]
+fn jp_1nnn() {
+    let cpu = CpuBuilder::new().program(&[0x1ABC]).run(1);
+    assert_eq!(cpu.pc, 0xABC);
+}
+
+#[test]
+fn call_2nnn_then_ret() {
+    let cpu = CpuBuilder::new()
+        .program(&[0x2300])
+        .program_at(0x300, &[0x00EE])
+        .run(2);
+    assert_eq!(cpu.pc, 0x202);
+    assert_eq!(cpu.sp, 0);
+}
+
+#[test]
+fn call_2nnn_pushes_return_address() {
+    let cpu = CpuBuilder::new().program(&[0x2300]).run(1);
+    assert_eq!(cpu.pc, 0x300);
+    assert_eq!(cpu.sp, 1);
+    assert_eq!(cpu.stack[0], 0x200);
+}
+
+#[test]
+fn call_2nnn_with_full_stack_overflows() {
+    let mut cpu = CpuBuilder::new()
+        .stack(&[0x200; 16])
+        .program(&[0x2300])
+        .build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::StackOverflow {
+            pc: 0x200,
+            opcode: 0x2300
+        })
+    );
+    assert_eq!(cpu.sp, 16);
+}
+
+#[test]
+fn jp_bnnn_adds_v0() {
+    let cpu = CpuBuilder::new()
+        .reg(0, 0x10)
+        .reg(3, 0x20)
+        .program(&[0xB300])
+        .run(1);
+    assert_eq!(cpu.pc, 0x310);
+}
+
+#[test]
+fn jp_bxnn_adds_vx_with_jump_quirk() {
+    let cpu = CpuBuilder::new()
+        .quirks(Quirks::super_chip())
+        .reg(0, 0x10)
+        .reg(3, 0x20)
+        .program(&[0xB300])
+        .run(1);
+    assert_eq!(cpu.pc, 0x320);
+}
+
+// Skips: 3XNN / 4XNN / 5XY0 / 9XY0
+
+#[test]
+fn se_3xnn() {
+    assert_eq!(
+        CpuBuilder::new().reg(1, 0x42).program(&[0x3142]).run(1).pc,
+        0x204
+    );
+    assert_eq!(
+        CpuBuilder::new().reg(1, 0x41).program(&[0x3142]).run(1).pc,
+        0x202
+    );
+}
+
+#[test]
+fn sne_4xnn() {
+    assert_eq!(
+        CpuBuilder::new().reg(1, 0x41).program(&[0x4142]).run(1).pc,
+        0x204
+    );
+    assert_eq!(
+        CpuBuilder::new().reg(1, 0x42).program(&[0x4142]).run(1).pc,
+        0x202
+    );
+}
+
+#[test]
+fn se_5xy0() {
+    assert_eq!(
+        CpuBuilder::new()
+            .reg(1, 7)
+            .reg(2, 7)
+            .program(&[0x5120])
+            .run(1)
+            .pc,
+        0x204
+    );
+    assert_eq!(
+        CpuBuilder::new()
+            .reg(1, 7)
+            .reg(2, 8)
+            .program(&[0x5120])
+            .run(1)
+            .pc,
+        0x202
+    );
+}
+
+#[test]
+fn sne_9xy0() {
+    assert_eq!(
+        CpuBuilder::new()
+            .reg(1, 7)
+            .reg(2, 8)
+            .program(&[0x9120])
+            .run(1)
+            .pc,
+        0x204
+    );
+    assert_eq!(
+        CpuBuilder::new()
+            .reg(1, 7)
+            .reg(2, 7)
+            .program(&[0x9120])
+            .run(1)
+            .pc,
+        0x202
+    );
+}
+
+#[test]
+fn skip_with_nonzero_low_nibble_is_unknown() {
+    let mut cpu = CpuBuilder::new().program(&[0x9121]).build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0x9121
+        })
+    );
+}
+
+// 6XNN / 7XNN
+
+#[test]
+fn ld_6xnn() {
+    let cpu = CpuBuilder::new().program(&[0x6A42]).run(1);
+    assert_regs(&cpu, &[(0xA, 0x42)]);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+fn add_7xnn_leaves_vf() {
+    let cpu = CpuBuilder::new()
+        .reg(2, 0x10)
+        .reg(0xF, 0x5)
+        .program(&[0x7205])
+        .run(1);
+    assert_regs(&cpu, &[(2, 0x15), (0xF, 0x5)]);
+}
+
+#[test]
+fn add_7xnn_wraps_without_carry() {
+    let cpu = CpuBuilder::new().reg(2, 0xFF).program(&[0x7202]).run(1);
+    assert_regs(&cpu, &[(2, 0x01), (0xF, 0)]);
+}
+
+// 8XYN
+
+#[test]
+fn ld_8xy0() {
+    let cpu = CpuBuilder::new().reg(2, 0x33).program(&[0x8120]).run(1);
+    assert_regs(&cpu, &[(1, 0x33), (2, 0x33)]);
+}
+
+#[test]
+fn or_8xy1() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0xF0)
+        .reg(2, 0x0F)
+        .reg(0xF, 9)
+        .program(&[0x8121])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0xFF), (0xF, 9)]);
+}
+
+#[test]
+fn and_8xy2() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0xFC)
+        .reg(2, 0x3F)
+        .program(&[0x8122])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x3C)]);
+}
+
+#[test]
+fn xor_8xy3() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0xFF)
+        .reg(2, 0x0F)
+        .program(&[0x8123])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0xF0)]);
+}
+
+#[test]
+fn logic_ops_reset_vf_with_vip_quirks() {
+    for op in [0x8121, 0x8122, 0x8123] {
+        let cpu = CpuBuilder::new()
+            .quirks(Quirks::cosmac_vip())
+            .reg(0xF, 1)
+            .program(&[op])
+            .run(1);
+        assert_regs(&cpu, &[(0xF, 0)]);
+    }
+}
+
+#[test]
+fn add_8xy4_without_carry() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0x10)
+        .reg(2, 0x20)
+        .program(&[0x8124])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x30), (0xF, 0)]);
+}
+
+#[test]
+fn add_8xy4_with_carry() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0xFF)
+        .reg(2, 0x02)
+        .program(&[0x8124])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x01), (0xF, 1)]);
+}
+
+#[test]
+fn sub_8xy5_without_borrow() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0x30)
+        .reg(2, 0x10)
+        .program(&[0x8125])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x20), (0xF, 1)]);
+}
+
+#[test]
+fn sub_8xy5_equal_operands_sets_vf() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0x30)
+        .reg(2, 0x30)
+        .program(&[0x8125])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x00), (0xF, 1)]);
+}
+
+#[test]
+fn sub_8xy5_with_borrow() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0x10)
+        .reg(2, 0x20)
+        .program(&[0x8125])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0xF0), (0xF, 0)]);
+}
+
+#[test]
+fn arithmetic_into_vf_keeps_the_flag() {
+    // VF is written after the result, so the flag wins when X is F.
+    for (op, flag) in [(0x8F14, 1), (0x8F15, 1), (0x8F17, 0)] {
+        let cpu = CpuBuilder::new()
+            .reg(0xF, 0xF0)
+            .reg(1, 0x20)
+            .program(&[op])
+            .run(1);
+        assert_regs(&cpu, &[(0xF, flag)]);
+    }
+}
+
+#[test]
+fn shr_8xy6_shifts_vx() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0x05)
+        .reg(2, 0x80)
+        .program(&[0x8126])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x02), (0xF, 1)]);
+}
+
+#[test]
+fn shr_8xy6_shifts_vy_with_vip_quirks() {
+    let cpu = CpuBuilder::new()
+        .quirks(Quirks::cosmac_vip())
+        .reg(1, 0x05)
+        .reg(2, 0x80)
+        .program(&[0x8126])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x40), (0xF, 0)]);
+}
+
+#[test]
+fn subn_8xy7_without_borrow() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0x10)
+        .reg(2, 0x30)
+        .program(&[0x8127])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x20), (0xF, 1)]);
+}
+
+#[test]
+fn subn_8xy7_with_borrow() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0x30)
+        .reg(2, 0x10)
+        .program(&[0x8127])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0xE0), (0xF, 0)]);
+}
+
+#[test]
+fn shl_8xye_shifts_vx() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 0x81)
+        .reg(2, 0x01)
+        .program(&[0x812E])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x02), (0xF, 1)]);
+}
+
+#[test]
+fn shl_8xye_shifts_vy_with_vip_quirks() {
+    let cpu = CpuBuilder::new()
+        .quirks(Quirks::cosmac_vip())
+        .reg(1, 0x81)
+        .reg(2, 0x01)
+        .program(&[0x812E])
+        .run(1);
+    assert_regs(&cpu, &[(1, 0x02), (0xF, 0)]);
+}
+
+#[test]
+fn unknown_8xyn_is_unknown() {
+    let mut cpu = CpuBuilder::new().program(&[0x8128]).build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0x8128
+        })
+    );
+}
+
+// ANNN / CXNN
+
+#[test]
+fn ld_annn() {
+    let cpu = CpuBuilder::new().program(&[0xA123]).run(1);
+    assert_eq!(cpu.index_register, 0x123);
+}
+
+#[test]
+fn rnd_cxnn_is_masked() {
+    let cpu = CpuBuilder::new().reg(1, 0xFF).program(&[0xC100]).run(1);
+    assert_regs(&cpu, &[(1, 0)]);
+    for seed in 0..32 {
+        let cpu = CpuBuilder::new()
+            .rng(Rng::new(seed))
+            .program(&[0xC10F])
+            .run(1);
+        assert_eq!(cpu.registers[1] & 0xF0, 0);
+    }
+}
+
+/// Runs `CXFF; JP 0x200` and collects `count` random bytes.
+fn random_bytes(rng: Rng, count: usize) -> Vec<u8> {
+    let mut cpu = CpuBuilder::new()
+        .rng(rng)
+        .program(&[0xC1FF, 0x1200])
+        .build();
+    (0..count)
+        .map(|_| {
+            cpu.step().unwrap();
+            cpu.step().unwrap();
+            cpu.registers[1]
+        })
+        .collect()
+}
+
+#[test]
+fn rnd_cxnn_covers_full_range() {
+    let mut seen = [false; 256];
+    for byte in random_bytes(Rng::new(1), 4096) {
+        seen[byte as usize] = true;
+    }
+    assert!(
+        seen.iter().all(|&s| s),
+        "never produced {:?}",
+        seen.iter().position(|&s| !s)
+    );
+}
+
+#[test]
+fn rnd_cxnn_is_reproducible_from_seed() {
+    assert_eq!(
+        random_bytes(Rng::new(42), 64),
+        random_bytes(Rng::new(42), 64)
+    );
+    assert_ne!(
+        random_bytes(Rng::new(42), 64),
+        random_bytes(Rng::new(43), 64)
+    );
+}
+
+#[test]
+fn rnd_cxnn_vip_routine_is_reproducible() {
+    let bytes = random_bytes(Rng::cosmac_vip(0x1234), 64);
+    assert_eq!(bytes, random_bytes(Rng::cosmac_vip(0x1234), 64));
+    assert!(bytes.iter().any(|&b| b != bytes[0]));
+}
+
+// DXYN
+
+#[test]
+fn drw_dxyn_draws_sprite() {
+    // font glyph "0": F0 90 90 90 F0
+    let cpu = CpuBuilder::new()
+        .reg(1, 2)
+        .reg(2, 3)
+        .i(0)
+        .program(&[0xD125])
+        .run(1);
+    assert!(pixel(&cpu, 2, 3) && pixel(&cpu, 5, 3));
+    assert!(pixel(&cpu, 2, 4) && !pixel(&cpu, 3, 4) && pixel(&cpu, 5, 4));
+    assert!(!pixel(&cpu, 6, 3));
+    assert_regs(&cpu, &[(0xF, 0)]);
+    assert!(cpu.draw);
+}
+
+#[test]
+fn drw_dxyn_collision_sets_vf_and_erases() {
+    let cpu = CpuBuilder::new().i(0).program(&[0xD015, 0xD015]).run(2);
+    assert_regs(&cpu, &[(0xF, 1)]);
+    assert!(cpu.vram.iter().all(|&p| p == 0));
+}
+
+#[test]
+fn drw_dxyn_wraps_by_default() {
+    let cpu = CpuBuilder::new()
+        .reg(1, 62)
+        .reg(2, 30)
+        .i(0)
+        .program(&[0xD125])
+        .run(1);
+    assert!(pixel(&cpu, 62, 30) && pixel(&cpu, 63, 30));
+    assert!(pixel(&cpu, 0, 30) && pixel(&cpu, 1, 30));
+    assert!(pixel(&cpu, 62, 0));
+}
+
+#[test]
+fn drw_dxyn_clips_with_clipping_quirk() {
+    let cpu = CpuBuilder::new()
+        .quirks(Quirks::cosmac_vip())
+        .reg(1, 62)
+        .reg(2, 30)
+        .i(0)
+        .program(&[0xD125])
+        .run(1);
+    assert!(pixel(&cpu, 62, 30) && pixel(&cpu, 63, 30));
+    assert!(!pixel(&cpu, 0, 30) && !pixel(&cpu, 62, 0));
+}
+
+#[test]
+fn drw_dxyn_start_position_wraps() {
+    let cpu = CpuBuilder::new()
+        .quirks(Quirks::cosmac_vip())
+        .reg(1, 64 + 4)
+        .reg(2, 32 + 1)
+        .i(0)
+        .program(&[0xD121])
+        .run(1);
+    assert!(pixel(&cpu, 4, 1));
+}
+
+#[test]
+fn drw_dxyn_past_end_of_memory_faults() {
+    let mut cpu = CpuBuilder::new().i(0xFFE).program(&[0xD125]).build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::MemoryOutOfBounds {
+            pc: 0x200,
+            opcode: 0xD125,
+            address: 0x1000
+        })
+    );
+}
+
+// EX9E / EXA1
+
+#[test]
+fn skp_ex9e() {
+    assert_eq!(
+        CpuBuilder::new()
+            .reg(1, 0xA)
+            .key(0xA)
+            .program(&[0xE19E])
+            .run(1)
+            .pc,
+        0x204
+    );
+    assert_eq!(
+        CpuBuilder::new().reg(1, 0xA).program(&[0xE19E]).run(1).pc,
+        0x202
+    );
+}
+
+#[test]
+fn sknp_exa1() {
+    assert_eq!(
+        CpuBuilder::new().reg(1, 0xA).program(&[0xE1A1]).run(1).pc,
+        0x204
+    );
+    assert_eq!(
+        CpuBuilder::new()
+            .reg(1, 0xA)
+            .key(0xA)
+            .program(&[0xE1A1])
+            .run(1)
+            .pc,
+        0x202
+    );
+}
+
+#[test]
+fn skp_uses_low_nibble_of_vx() {
+    assert_eq!(
+        CpuBuilder::new()
+            .reg(1, 0x1A)
+            .key(0xA)
+            .program(&[0xE19E])
+            .run(1)
+            .pc,
+        0x204
+    );
+}
+
+// FX07 / FX0A / FX15 / FX18
+
+#[test]
+fn ld_fx07_reads_delay_timer() {
+    let cpu = CpuBuilder::new().delay(0x2A).program(&[0xF107]).run(1);
+    assert_regs(&cpu, &[(1, 0x2A)]);
+}
+
+#[test]
+fn ld_fx0a_blocks_until_key() {
+    let mut cpu = CpuBuilder::new().program(&[0xF10A]).run(3);
+    assert_eq!(cpu.pc, 0x200);
+    cpu.key[0x7] = 1;
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 0x202);
+    assert_regs(&cpu, &[(1, 0x7)]);
+}
+
+#[test]
+fn ld_fx15_and_fx18_set_timers() {
+    let mut cpu = CpuBuilder::new()
+        .reg(1, 3)
+        .reg(2, 5)
+        .program(&[0xF115, 0xF218])
+        .run(2);
+    assert_eq!((cpu.delay_timer, cpu.audio_timer), (3, 5));
+    cpu.tick_timers();
+    assert_eq!((cpu.delay_timer, cpu.audio_timer), (2, 4));
+}
+
+#[test]
+fn timers_only_tick_per_frame() {
+    let mut cpu = CpuBuilder::new()
+        .reg(1, 10)
+        .program(&[0xF115, 0x1202])
+        .run(1);
+    for _ in 0..100 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.delay_timer, 10);
+    cpu.run_frame(10).unwrap();
+    assert_eq!(cpu.delay_timer, 9);
+}
+
+// FX1E / FX29 / FX33
+
+#[test]
+fn add_fx1e() {
+    let cpu = CpuBuilder::new()
+        .i(0x100)
+        .reg(1, 0x20)
+        .program(&[0xF11E])
+        .run(1);
+    assert_eq!(cpu.index_register, 0x120);
+    assert_regs(&cpu, &[(0xF, 0)]);
+}
+
+#[test]
+fn add_fx1e_sets_vf_past_0xfff() {
+    let cpu = CpuBuilder::new()
+        .i(0xFF8)
+        .reg(1, 0x10)
+        .program(&[0xF11E])
+        .run(1);
+    assert_eq!(cpu.index_register, 0x1008);
+    assert_regs(&cpu, &[(0xF, 1)]);
+}
+
+#[test]
+fn ld_fx29_points_at_font_glyph() {
+    let cpu = CpuBuilder::new().reg(1, 0xA).program(&[0xF129]).run(1);
+    assert_eq!(cpu.index_register, 0xA * 5);
+}
+
+#[test]
+fn ld_fx29_uses_low_nibble() {
+    let cpu = CpuBuilder::new().reg(1, 0x3A).program(&[0xF129]).run(1);
+    assert_eq!(cpu.index_register, 0xA * 5);
+}
+
+#[test]
+fn ld_fx33_stores_bcd() {
+    let cpu = CpuBuilder::new()
+        .i(0x300)
+        .reg(1, 254)
+        .program(&[0xF133])
+        .run(1);
+    assert_eq!(&cpu.memory[0x300..0x303], &[2, 5, 4]);
+    assert_eq!(cpu.index_register, 0x300);
+}
+
+#[test]
+fn ld_fx33_past_end_of_memory_faults() {
+    let mut cpu = CpuBuilder::new().i(0xFFE).program(&[0xF133]).build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::MemoryOutOfBounds {
+            pc: 0x200,
+            opcode: 0xF133,
+            address: 0x1000
+        })
+    );
+    assert_eq!(cpu.memory[0xFFE], 0);
+}
+
+// FX55 / FX65
+
+#[test]
+fn ld_fx55_stores_registers_and_increments_i() {
+    let cpu = CpuBuilder::new()
+        .i(0x300)
+        .reg(0, 1)
+        .reg(1, 2)
+        .reg(2, 3)
+        .reg(3, 4)
+        .program(&[0xF255])
+        .run(1);
+    assert_eq!(&cpu.memory[0x300..0x304], &[1, 2, 3, 0]);
+    assert_eq!(cpu.index_register, 0x303);
+}
+
+#[test]
+fn ld_fx65_loads_registers_and_increments_i() {
+    let cpu = CpuBuilder::new()
+        .i(0x300)
+        .mem(0x300, &[9, 8, 7, 6])
+        .program(&[0xF265])
+        .run(1);
+    assert_regs(&cpu, &[(0, 9), (1, 8), (2, 7), (3, 0)]);
+    assert_eq!(cpu.index_register, 0x303);
+}
+
+#[test]
+fn load_store_quirk_controls_i() {
+    let mut quirks = Quirks {
+        load_store: LoadStore::Unchanged,
+        ..Quirks::default()
+    };
+    let cpu = CpuBuilder::new()
+        .quirks(quirks)
+        .i(0x300)
+        .program(&[0xF255])
+        .run(1);
+    assert_eq!(cpu.index_register, 0x300);
+
+    quirks.load_store = LoadStore::IncrementByX;
+    let cpu = CpuBuilder::new()
+        .quirks(quirks)
+        .i(0x300)
+        .program(&[0xF265])
+        .run(1);
+    assert_eq!(cpu.index_register, 0x302);
+}
+
+#[test]
+fn ld_fx55_past_end_of_memory_faults() {
+    let mut cpu = CpuBuilder::new().i(0xFFC).program(&[0xFF55]).build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::MemoryOutOfBounds {
+            pc: 0x200,
+            opcode: 0xFF55,
+            address: 0x1000
+        })
+    );
+}
+
+#[test]
+fn unknown_fxnn_is_unknown() {
+    let mut cpu = CpuBuilder::new().program(&[0xF1FF]).build();
+    assert_eq!(
+        cpu.step(),
+        Err(CpuError::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0xF1FF
+        })
+    );
+}
+
+#[test]
+fn pc_past_end_of_memory_faults() {
+    let mut cpu = CpuBuilder::new().pc(0xFFF).build();
+    assert_eq!(cpu.step(), Err(CpuError::PcOutOfBounds { pc: 0xFFF }));
+}
+
+#[test]
+fn pc_wraps_at_end_of_xo_chip_memory() {
+    let cpu = CpuBuilder::new()
+        .platform(Platform::XoChip)
+        .program_at(0xFFFE, &[0x6001])
+        .pc(0xFFFE)
+        .run(1);
+    assert_eq!((cpu.pc, cpu.registers[0]), (0, 1));
+
+    let cpu = CpuBuilder::new()
+        .platform(Platform::XoChip)
+        .program(&[0x00EE])
+        .stack(&[0xFFFE])
+        .run(1);
+    assert_eq!(cpu.pc, 0);
+}
+
+// SUPER-CHIP: 00CN / 00FB / 00FC / 00FD / 00FE / 00FF / DXY0 / FX30 / FX75 / FX85
+
+fn schip() -> CpuBuilder {
+    CpuBuilder::new().platform(Platform::SuperChip)
+}
+
+#[test]
+fn scd_00cn_scrolls_down() {
+    // font glyph "0" at the top left, then down 2 rows
+    let cpu = schip().i(0).program(&[0xD015, 0x00C2]).run(2);
+    assert!(!pixel(&cpu, 0, 0) && !pixel(&cpu, 0, 1));
+    assert!(pixel(&cpu, 0, 2) && pixel(&cpu, 3, 6));
+    assert!(cpu.draw);
+
+    // rows scrolled off the bottom are lost
+    let cpu = schip().i(0).reg(1, 30).program(&[0xD015, 0x00C4]).run(2);
+    assert!(cpu.vram.iter().all(|&p| p == 0));
+}
+
+#[test]
+fn scr_00fb_scrolls_right_4_pixels() {
+    let cpu = schip().i(0).program(&[0xD015, 0x00FB]).run(2);
+    assert!(!pixel(&cpu, 0, 0) && !pixel(&cpu, 3, 0));
+    assert!(pixel(&cpu, 4, 0) && pixel(&cpu, 7, 0) && !pixel(&cpu, 8, 0));
+}
+
+#[test]
+fn scl_00fc_scrolls_left_4_pixels() {
+    let cpu = schip().i(0).program(&[0xD015, 0x00FB, 0x00FC]).run(3);
+    assert!(pixel(&cpu, 0, 0) && pixel(&cpu, 3, 0) && !pixel(&cpu, 4, 0));
+
+    let cpu = schip().i(0).program(&[0xD015, 0x00FC]).run(2);
+    assert!(cpu.vram.iter().all(|&p| p == 0));
+}
+
+#[test]
+fn exit_00fd_stops_the_cpu() {
+    let cpu = schip().program(&[0x00FD, 0x6001]).run(2);
+    assert!(cpu.exited);
+    assert_eq!((cpu.pc, cpu.registers[0]), (0x200, 0));
+}
+
+#[test]
+fn high_00ff_and_low_00fe_switch_resolution() {
+    let cpu = schip().program(&[0x00FF]).run(1);
+    assert!(cpu.hires);
+    assert_eq!((cpu.width, cpu.height), (128, 64));
+    assert_eq!(cpu.vram.len(), 128 * 64);
+
+    // switching back clears the screen
+    let cpu = schip().i(0).program(&[0x00FF, 0xD015, 0x00FE]).run(3);
+    assert!(!cpu.hires);
+    assert_eq!((cpu.width, cpu.height), (64, 32));
+    assert_eq!(cpu.vram.len(), 64 * 32);
+    assert!(cpu.vram.iter().all(|&p| p == 0));
+}
+
+#[test]
+fn drw_dxy0_draws_16x16_sprite() {
+    // a 16x16 sprite with only its left and right columns set
+    let cpu = schip()
+        .mem(0x300, &[0x80, 0x01].repeat(16))
+        .i(0x300)
+        .reg(1, 10)
+        .reg(2, 5)
+        .program(&[0x00FF, 0xD120])
+        .run(2);
+    assert!(pixel(&cpu, 10, 5) && pixel(&cpu, 25, 5));
+    assert!(pixel(&cpu, 10, 20) && pixel(&cpu, 25, 20));
+    assert!(!pixel(&cpu, 11, 5) && !pixel(&cpu, 26, 5) && !pixel(&cpu, 10, 21));
+    assert_regs(&cpu, &[(0xF, 0)]);
+}
+
+#[test]
+fn drw_dxy0_collision_sets_vf_and_erases() {
+    let cpu = schip()
+        .mem(0x300, &[0x80, 0x01].repeat(16))
+        .i(0x300)
+        .program(&[0x00FF, 0xD010, 0xD010])
+        .run(3);
+    assert_regs(&cpu, &[(0xF, 1)]);
+    assert!(cpu.vram.iter().all(|&p| p == 0));
+}
+
+#[test]
+fn ld_fx30_points_at_big_font_glyph() {
+    let cpu = schip().reg(3, 0x17).program(&[0xF330]).run(1);
+    assert_eq!(cpu.index_register, 0x50 + 7 * 10);
+    // big "7": FF FF 03 06 0C 18 30 60 60 60
+    assert_eq!(
+        cpu.memory[cpu.index_register as usize..][..10],
+        [0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60]
+    );
+}
+
+#[test]
+fn ld_fx75_and_fx85_save_and_restore_rpl_flags() {
+    let cpu = schip()
+        .reg(0, 1)
+        .reg(1, 2)
+        .reg(2, 3)
+        .program(&[0xF275, 0x6000, 0x6100, 0x6200, 0xF185])
+        .run(5);
+    assert_eq!(cpu.rpl[..4], [1, 2, 3, 0]);
+    assert_regs(&cpu, &[(0, 1), (1, 2), (2, 0)]);
+}
+
+// XO-CHIP: FN01 / DXYN planes / 5XY2 / 5XY3 / F000 NNNN / F002 / FX3A / 00DN
+
+fn xo_chip() -> CpuBuilder {
+    CpuBuilder::new().platform(Platform::XoChip)
+}
+
+#[test]
+fn plane_fn01_selects_bitplanes() {
+    assert_eq!(xo_chip().build().planes, 1);
+    for (opcode, planes) in [(0xF001, 0), (0xF101, 1), (0xF201, 2), (0xF301, 3)] {
+        assert_eq!(xo_chip().program(&[opcode]).run(1).planes, planes);
+    }
+}
+
+#[test]
+fn drw_dxyn_draws_to_the_selected_planes() {
+    let cpu = xo_chip().i(0).program(&[0xD015]).run(1);
+    assert_eq!(cpu.vram[0], 1);
+
+    let cpu = xo_chip().i(0).program(&[0xF201, 0xD015]).run(2);
+    assert_eq!(cpu.vram[0], 2);
+
+    // plane 3 reads 2 rows for plane 1, then 2 rows for plane 2
+    let cpu = xo_chip()
+        .mem(0x300, &[0x80, 0x80, 0xC0, 0xC0])
+        .i(0x300)
+        .program(&[0xF301, 0xD012])
+        .run(2);
+    assert_eq!(cpu.vram[0], 3);
+    assert_eq!(cpu.vram[1], 2);
+    assert_eq!(cpu.vram[cpu.width], 3);
+    assert_eq!(cpu.vram[cpu.width + 1], 2);
+    assert_eq!(cpu.vram[2 * cpu.width], 0);
+}
+
+#[test]
+fn planes_collide_and_clear_independently() {
+    // plane 2 drawn over plane 1 does not collide
+    let cpu = xo_chip().i(0).program(&[0xD015, 0xF201, 0xD015]).run(3);
+    assert_eq!(cpu.vram[0], 3);
+    assert_regs(&cpu, &[(0xF, 0)]);
+
+    let cpu = xo_chip()
+        .i(0)
+        .program(&[0xD015, 0xF201, 0xD015, 0xD015])
+        .run(4);
+    assert_eq!(cpu.vram[0], 1);
+    assert_regs(&cpu, &[(0xF, 1)]);
+
+    // 00E0 clears only the selected planes
+    let cpu = xo_chip()
+        .i(0)
+        .program(&[0xF301, 0xD015, 0xF201, 0x00E0])
+        .run(4);
+    assert!(cpu.vram.iter().all(|&p| p & 2 == 0));
+    assert!(cpu.vram.contains(&1));
+}
+
+#[test]
+fn save_5xy2_stores_a_register_range() {
+    let cpu = xo_chip()
+        .reg(1, 1)
+        .reg(2, 2)
+        .reg(3, 3)
+        .i(0x300)
+        .program(&[0x5132])
+        .run(1);
+    assert_eq!(cpu.memory[0x300..0x304], [1, 2, 3, 0]);
+    assert_eq!(cpu.index_register, 0x300);
+
+    // X > Y stores the registers in reverse
+    let cpu = xo_chip()
+        .reg(1, 1)
+        .reg(2, 2)
+        .reg(3, 3)
+        .i(0x300)
+        .program(&[0x5312])
+        .run(1);
+    assert_eq!(cpu.memory[0x300..0x304], [3, 2, 1, 0]);
+}
+
+#[test]
+fn load_5xy3_loads_a_register_range() {
+    let cpu = xo_chip()
+        .mem(0x300, &[7, 8, 9])
+        .i(0x300)
+        .program(&[0x5133])
+        .run(1);
+    assert_regs(&cpu, &[(0, 0), (1, 7), (2, 8), (3, 9), (4, 0)]);
+    assert_eq!(cpu.index_register, 0x300);
+
+    let cpu = xo_chip()
+        .mem(0x300, &[7, 8, 9])
+        .i(0x300)
+        .program(&[0x5313])
+        .run(1);
+    assert_regs(&cpu, &[(1, 9), (2, 8), (3, 7)]);
+}
+
+#[test]
+fn ld_f000_nnnn_loads_a_16_bit_address() {
+    let cpu = xo_chip().program(&[0xF000, 0xABCD, 0x6001]).run(2);
+    assert_eq!(cpu.index_register, 0xABCD);
+    assert_eq!((cpu.pc, cpu.registers[0]), (0x206, 1));
+}
+
+#[test]
+fn skip_steps_over_all_four_bytes_of_f000_nnnn() {
+    // V0 == 0, so 3000 skips the long load
+    let cpu = xo_chip().program(&[0x3000, 0xF000, 0xABCD, 0x6001]).run(2);
+    assert_eq!(cpu.index_register, 0);
+    assert_eq!((cpu.pc, cpu.registers[0]), (0x208, 1));
+}
+
+#[test]
+fn audio_f002_loads_the_pattern() {
+    let pattern: Vec<u8> = (0..16).collect();
+    let cpu = xo_chip()
+        .mem(0x300, &pattern)
+        .i(0x300)
+        .program(&[0xF002])
+        .run(1);
+    assert_eq!(cpu.audio_pattern[..], pattern[..]);
+}
+
+#[test]
+fn pitch_fx3a_sets_the_playback_rate() {
+    assert_eq!(xo_chip().build().audio_playback_rate(), 4000.0);
+    let cpu = xo_chip().reg(5, 112).program(&[0xF53A]).run(1);
+    assert_eq!(cpu.pitch, 112);
+    // 48 steps up is an octave
+    assert_eq!(cpu.audio_playback_rate(), 8000.0);
+}
+
+#[test]
+fn scu_00dn_scrolls_up() {
+    let cpu = xo_chip().i(0).reg(1, 4).program(&[0xD015, 0x00D2]).run(2);
+    assert!(pixel(&cpu, 0, 2) && pixel(&cpu, 3, 6));
+    assert!(!pixel(&cpu, 0, 7) && !pixel(&cpu, 0, 8));
+
+    // only the selected planes scroll
+    let cpu = xo_chip()
+        .i(0)
+        .reg(1, 4)
+        .program(&[0xD015, 0xF201, 0x00D2])
+        .run(3);
+    assert!(pixel(&cpu, 0, 4) && !pixel(&cpu, 0, 2));
+}
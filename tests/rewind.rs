@@ -0,0 +1,113 @@
+mod common;
+
+use chip8_core::{Platform, Rewind};
+use common::{rom_cpu, CpuBuilder};
+
+#[test]
+fn rewinds_frame_by_frame() {
+    let mut cpu = rom_cpu("test_opcode.ch8");
+    let mut rewind = Rewind::default();
+    let mut history = vec![cpu.save_state()];
+    rewind.push(&cpu);
+    for _ in 0..100 {
+        cpu.run_frame(10).unwrap();
+        rewind.push(&cpu);
+        history.push(cpu.save_state());
+    }
+    assert_eq!(rewind.len(), 100);
+
+    history.pop();
+    while let Some(expected) = history.pop() {
+        assert!(rewind.rewind(&mut cpu));
+        assert_eq!(cpu.save_state(), expected);
+    }
+    assert!(rewind.is_empty());
+    assert!(!rewind.rewind(&mut cpu));
+}
+
+#[test]
+fn resumes_after_rewinding() {
+    let mut cpu = rom_cpu("ibm.ch8");
+    let mut rewind = Rewind::default();
+    rewind.push(&cpu);
+    for _ in 0..10 {
+        cpu.run_frame(10).unwrap();
+        rewind.push(&cpu);
+    }
+    let at_5 = {
+        let mut probe = rom_cpu("ibm.ch8");
+        for _ in 0..5 {
+            probe.run_frame(10).unwrap();
+        }
+        probe.save_state()
+    };
+
+    for _ in 0..5 {
+        rewind.rewind(&mut cpu);
+    }
+    assert_eq!(cpu.save_state(), at_5);
+
+    // Playing on from the rewound frame records new history over the old.
+    cpu.run_frame(10).unwrap();
+    rewind.push(&cpu);
+    assert_eq!(rewind.len(), 6);
+    rewind.rewind(&mut cpu);
+    assert_eq!(cpu.save_state(), at_5);
+}
+
+#[test]
+fn drops_oldest_frames_past_capacity() {
+    let mut cpu = CpuBuilder::new().program(&[0x7001, 0x1200]).build();
+    let mut rewind = Rewind::new(3);
+    rewind.push(&cpu);
+    for _ in 0..5 {
+        cpu.step().unwrap();
+        rewind.push(&cpu);
+    }
+    assert_eq!(rewind.len(), 3);
+
+    while rewind.rewind(&mut cpu) {}
+    // Five steps rewound by three leaves the CPU after the jump of step two.
+    assert_eq!(cpu.registers[0], 1);
+    assert_eq!(cpu.pc, 0x200);
+}
+
+#[test]
+fn rewinds_across_resolution_changes() {
+    let mut cpu = CpuBuilder::new()
+        .platform(Platform::SuperChip)
+        .program(&[0x00FF, 0x00FE])
+        .build();
+    let mut rewind = Rewind::default();
+    let lores = cpu.save_state();
+    rewind.push(&cpu);
+    cpu.step().unwrap();
+    let hires = cpu.save_state();
+    rewind.push(&cpu);
+    cpu.step().unwrap();
+    rewind.push(&cpu);
+
+    rewind.rewind(&mut cpu);
+    assert_eq!(cpu.save_state(), hires);
+    assert!(cpu.hires);
+    rewind.rewind(&mut cpu);
+    assert_eq!(cpu.save_state(), lores);
+}
+
+#[test]
+fn deltas_are_much_smaller_than_states() {
+    let mut cpu = rom_cpu("PONG");
+    let mut rewind = Rewind::default();
+    rewind.push(&cpu);
+    for _ in 0..600 {
+        cpu.run_frame(10).unwrap();
+        rewind.push(&cpu);
+    }
+    let full = cpu.save_state().len() * 601;
+    assert!(
+        rewind.memory_usage() * 10 < full,
+        "{} bytes for {} bytes of states",
+        rewind.memory_usage(),
+        full
+    );
+}
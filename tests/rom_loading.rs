@@ -0,0 +1,55 @@
+use chip8_core::{Platform, RomError, CPU};
+use std::env;
+use std::fs;
+use std::process;
+
+#[test]
+fn missing_file_is_not_found() {
+    let mut cpu = CPU::new();
+    match cpu.load_rom("does-not-exist.ch8") {
+        Err(RomError::NotFound(path)) => assert_eq!(path, "does-not-exist.ch8"),
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn empty_rom_is_rejected() {
+    let path = env::temp_dir().join(format!("chip8-rom-{}-empty.ch8", process::id()));
+    fs::write(&path, b"").unwrap();
+    let mut cpu = CPU::new();
+    let result = cpu.load_rom(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(RomError::Empty)), "{:?}", result);
+
+    let result = cpu.load_rom_bytes(&[]);
+    assert!(matches!(result, Err(RomError::Empty)), "{:?}", result);
+    assert_eq!(cpu.rom_size, 0);
+}
+
+#[test]
+fn oversized_rom_reports_size_and_limit() {
+    let mut cpu = CPU::new();
+    let error = cpu.load_rom_bytes(&[0; 0xE01]).unwrap_err();
+    match error {
+        RomError::TooLarge { size, max } => assert_eq!((size, max), (0xE01, 0xE00)),
+        ref other => panic!("expected TooLarge, got {:?}", other),
+    }
+    assert_eq!(
+        error.to_string(),
+        "max ROM size is 3584 bytes, but ROM loaded was 3585 bytes"
+    );
+
+    // XO-CHIP's 64 KB of memory takes the same ROM
+    cpu.set_platform(Platform::XoChip);
+    assert_eq!(cpu.load_rom_bytes(&[0; 0xE01]).unwrap(), 0xE01);
+}
+
+#[test]
+fn loads_from_a_byte_slice() {
+    let mut cpu = CPU::new();
+    assert_eq!(cpu.load_rom_bytes(&[0x60, 0x2A, 0x12, 0x02]).unwrap(), 4);
+    assert_eq!(&cpu.memory[0x200..0x205], [0x60, 0x2A, 0x12, 0x02, 0x00]);
+    assert_eq!(cpu.rom_size, 4);
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers[0], 0x2A);
+}
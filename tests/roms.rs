@@ -0,0 +1,112 @@
+//! Golden-image tests: each test ROM in `programs/` runs headless for a fixed
+//! number of frames and the resulting framebuffer is compared with the image
+//! checked in under `tests/golden/`.
+//!
+//! After an intended change in behaviour, regenerate the images with
+//! `UPDATE_GOLDEN=1 cargo test --no-default-features --test roms` and review the diff.
+
+use chip8_core::headless::render_text;
+use chip8_core::{Platform, Quirks, CPU};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const CYCLES_PER_FRAME: u32 = 10;
+
+fn run_rom(rom: &str, frames: u32, quirks: Quirks) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.quirks = quirks;
+    cpu.load_rom(&format!("{}/programs/{}", env!("CARGO_MANIFEST_DIR"), rom))
+        .unwrap();
+    for frame in 0..frames {
+        if let Err(e) = cpu.run_frame(CYCLES_PER_FRAME) {
+            panic!("{} faulted on frame {}: {}", rom, frame, e);
+        }
+    }
+    cpu
+}
+
+/// Runs `rom` with the quirks it was written for and compares its screen with the golden
+/// image.
+fn check_golden(rom: &str, frames: u32, quirks: Quirks) {
+    let cpu = run_rom(rom, frames, quirks);
+    let actual = render_text(&cpu);
+    let stem = Path::new(rom).file_stem().unwrap().to_str().unwrap();
+    let golden = format!("{}/tests/golden/{}.txt", env!("CARGO_MANIFEST_DIR"), stem);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|e| {
+        panic!(
+            "missing golden image {} ({}), run with UPDATE_GOLDEN=1",
+            golden, e
+        )
+    });
+    assert!(
+        actual == expected,
+        "{} framebuffer differs from {} after {} frames\n--- expected\n{}--- actual\n{}",
+        rom,
+        golden,
+        frames,
+        expected,
+        actual
+    );
+}
+
+/// Runs every ROM in `programs/` on every platform with the keypad being mashed.
+/// Faults such as unknown opcodes are fine, this only checks nothing panics, which
+/// in a debug build includes arithmetic overflow.
+#[test]
+fn all_roms_run_without_panicking() {
+    let dir = format!("{}/programs", env!("CARGO_MANIFEST_DIR"));
+    let mut roms: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty());
+
+    for rom in &roms {
+        for platform in [Platform::Chip8, Platform::SuperChip, Platform::XoChip] {
+            let mut cpu = CPU::new();
+            cpu.set_platform(platform);
+            cpu.load_rom(rom.to_str().unwrap()).unwrap();
+            for frame in 0..300 {
+                cpu.key = [0; 16];
+                cpu.key[frame % 16] = (frame / 16 % 2) as u8;
+                if cpu.run_frame(CYCLES_PER_FRAME).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn ibm_logo() {
+    check_golden("ibm.ch8", 60, Quirks::default());
+}
+
+#[test]
+fn test_opcode() {
+    check_golden("test_opcode.ch8", 120, Quirks::default());
+}
+
+/// Shows "OK" on success. Expects FX55/FX65 to leave I alone and BNNN to jump
+/// to NNN + V0.
+#[test]
+fn c8_test() {
+    let quirks = Quirks {
+        jump: false,
+        ..Quirks::super_chip()
+    };
+    check_golden("c8_test.c8", 120, quirks);
+}
+
+/// Shows "BON" on success. Expects FX55/FX65 to leave I alone.
+#[test]
+fn bc_test() {
+    check_golden("BC_test.ch8", 120, Quirks::super_chip());
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use chip8_core::cpu::{STATE_MAGIC, STATE_VERSION};
+use chip8_core::{Platform, Quirks, Rng, StateError, Watchpoint, CPU};
+use common::{rom_cpu, CpuBuilder};
+
+fn assert_same(a: &CPU, b: &CPU) {
+    assert_eq!(a.save_state(), b.save_state());
+}
+
+#[test]
+fn round_trip_preserves_everything() {
+    let mut cpu = CpuBuilder::new()
+        .quirks(Quirks::cosmac_vip())
+        .reg(3, 0x42)
+        .i(0x345)
+        .stack(&[0x222, 0x444])
+        .key(0xA)
+        .delay(17)
+        .program(&[0x6001])
+        .build();
+    cpu.audio_timer = 5;
+    cpu.rpl[2] = 9;
+    cpu.vram[100] = 1;
+
+    let mut restored = CPU::new();
+    restored.load_state(&cpu.save_state()).unwrap();
+    assert_same(&cpu, &restored);
+    assert_eq!(restored.registers[3], 0x42);
+    assert_eq!(restored.index_register, 0x345);
+    assert_eq!(restored.sp, 2);
+    assert_eq!(restored.quirks, Quirks::cosmac_vip());
+
+    cpu.step().unwrap();
+    restored.step().unwrap();
+    assert_same(&cpu, &restored);
+}
+
+#[test]
+fn load_keeps_watchpoints() {
+    let state = CPU::new().save_state();
+    let mut cpu = CPU::new();
+    cpu.watchpoints.push(Watchpoint::writes(0x300, 0x3FF));
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.watchpoints, [Watchpoint::writes(0x300, 0x3FF)]);
+}
+
+#[test]
+fn restored_run_matches_uninterrupted_run() {
+    let mut cpu = rom_cpu("test_opcode.ch8");
+    for _ in 0..30 {
+        cpu.run_frame(10).unwrap();
+    }
+    let state = cpu.save_state();
+    for _ in 0..60 {
+        cpu.run_frame(10).unwrap();
+    }
+
+    let mut resumed = CPU::new();
+    resumed.load_state(&state).unwrap();
+    for _ in 0..60 {
+        resumed.run_frame(10).unwrap();
+    }
+    assert_same(&cpu, &resumed);
+    assert_eq!(cpu.vram, resumed.vram);
+}
+
+#[test]
+fn round_trip_hires_xo_chip() {
+    let mut cpu = CpuBuilder::new()
+        .platform(Platform::XoChip)
+        .program(&[0x00FF, 0xF201])
+        .run(2);
+    cpu.memory[0xFFFF] = 0x77;
+    cpu.vram[128 * 64 - 1] = 3;
+
+    let mut restored = CPU::new();
+    restored.load_state(&cpu.save_state()).unwrap();
+    assert_same(&cpu, &restored);
+    assert_eq!(restored.memory.len(), 0x10000);
+    assert_eq!((restored.width, restored.height), (128, 64));
+    assert_eq!(restored.planes, 2);
+}
+
+#[test]
+fn header_is_magic_and_version() {
+    let state = CPU::new().save_state();
+    assert_eq!(state[..4], STATE_MAGIC);
+    assert_eq!(state[4..6], STATE_VERSION.to_le_bytes());
+}
+
+#[test]
+fn round_trip_continues_the_random_sequence() {
+    for rng in [Rng::new(7), Rng::cosmac_vip(7)] {
+        let mut cpu = CpuBuilder::new()
+            .rng(rng)
+            .program(&[0xC1FF, 0x1200])
+            .run(10);
+        let mut restored = CPU::new();
+        restored.load_state(&cpu.save_state()).unwrap();
+        assert_eq!(restored.rng, cpu.rng);
+        for _ in 0..20 {
+            cpu.step().unwrap();
+            restored.step().unwrap();
+            assert_eq!(cpu.registers[1], restored.registers[1]);
+        }
+    }
+}
+
+#[test]
+fn loads_version_1_states_with_default_rng() {
+    let cpu = CpuBuilder::new().rng(Rng::new(99)).reg(5, 0x55).build();
+    let mut v1 = cpu.save_state();
+    // Version 1 had no RNG, the 9 bytes right after rom_size.
+    v1.drain(132..141);
+    v1[4..6].copy_from_slice(&1u16.to_le_bytes());
+
+    let mut restored = CPU::new();
+    restored.load_state(&v1).unwrap();
+    assert_eq!(restored.registers[5], 0x55);
+    assert_eq!(restored.rng, Rng::default());
+    assert_eq!(restored.memory, cpu.memory);
+}
+
+#[test]
+fn rejects_bad_states_and_leaves_cpu_untouched() {
+    let good = rom_cpu("ibm.ch8").save_state();
+    let mut cpu = CpuBuilder::new().reg(0, 0x99).build();
+    let before = cpu.save_state();
+
+    let mut bad_magic = good.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(cpu.load_state(&bad_magic), Err(StateError::BadMagic));
+
+    let mut future = good.clone();
+    future[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+    assert_eq!(
+        cpu.load_state(&future),
+        Err(StateError::UnsupportedVersion(STATE_VERSION + 1))
+    );
+
+    assert_eq!(
+        cpu.load_state(&good[..good.len() - 1]),
+        Err(StateError::Truncated)
+    );
+    assert_eq!(cpu.load_state(&[]), Err(StateError::Truncated));
+
+    let mut bad_platform = good.clone();
+    bad_platform[6] = 7;
+    assert_eq!(
+        cpu.load_state(&bad_platform),
+        Err(StateError::Invalid("platform"))
+    );
+
+    assert_eq!(cpu.save_state(), before);
+}
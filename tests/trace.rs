@@ -0,0 +1,158 @@
+mod common;
+
+use chip8_core::trace::{line, Format, Trace};
+use chip8_core::Debugger;
+use common::CpuBuilder;
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::rc::Rc;
+
+/// A writer whose contents the test can still read after handing it over.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Failing;
+
+impl Write for Failing {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn line_shows_the_state_before_the_instruction() {
+    let cpu = CpuBuilder::new()
+        .program(&[0x8014])
+        .reg(0, 0xFF)
+        .reg(1, 0x01)
+        .reg(0xF, 0x0A)
+        .i(0x123)
+        .stack(&[0x300, 0x400])
+        .delay(7)
+        .build();
+    assert_eq!(
+        line(&cpu),
+        "000000 PC:0200 OP:8014 ADD V0, V1            \
+         V:FF 01 00 00 00 00 00 00 00 00 00 00 00 00 00 0A I:0123 SP:02 DT:07 ST:00"
+    );
+
+    let cpu = CpuBuilder::new().program(&[0xF000, 0x1234]).build();
+    assert!(line(&cpu).contains(" OP:F000 ??? "), "{}", line(&cpu));
+}
+
+#[test]
+fn cadmium_format_matches_its_state_line() {
+    let cpu = CpuBuilder::new()
+        .program(&[0xD01F])
+        .reg(0, 0xAB)
+        .reg(0xF, 1)
+        .i(0x2E0)
+        .stack(&[0x300])
+        .build();
+    assert_eq!(
+        Format::Cadmium.line(&cpu),
+        "V0:ab V1:00 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 \
+         VE:00 VF:01 I:02e0 SP:1 PC:0200 O:d01f"
+    );
+    assert_eq!(Format::Native.line(&cpu), line(&cpu));
+    assert_eq!(Format::from_name("Cadmium"), Some(Format::Cadmium));
+    assert_eq!(Format::from_name("bochs"), None);
+
+    let mut cpu = CpuBuilder::new().program(&[0x6005, 0x1202]).build();
+    let mut trace = Trace::with_format(Vec::new(), Format::Cadmium);
+    for _ in 0..2 {
+        trace.record(&cpu);
+        cpu.step().unwrap();
+    }
+    let out = String::from_utf8(trace.finish().unwrap()).unwrap();
+    let ops: Vec<&str> = out.lines().map(|l| &l[l.len() - 14..]).collect();
+    assert_eq!(ops, ["PC:0200 O:6005", "PC:0202 O:1202"]);
+}
+
+#[test]
+fn trace_writes_one_line_per_step() {
+    let mut cpu = CpuBuilder::new().program(&[0x6005, 0x7001, 0x1202]).build();
+    let mut trace = Trace::new(Vec::new());
+    for _ in 0..4 {
+        trace.record(&cpu);
+        cpu.step().unwrap();
+    }
+    let out = String::from_utf8(trace.finish().unwrap()).unwrap();
+    let pcs: Vec<&str> = out.lines().map(|l| &l[7..14]).collect();
+    assert_eq!(pcs, ["PC:0200", "PC:0202", "PC:0204", "PC:0202"]);
+    assert!(out.lines().nth(3).unwrap().contains("V:06 "));
+
+    let mut trace = Trace::new(Failing);
+    trace.record(&cpu);
+    trace.record(&cpu);
+    assert_eq!(trace.finish().unwrap_err().to_string(), "disk full");
+}
+
+#[test]
+fn debugger_traces_frames_and_steps() {
+    let mut cpu = CpuBuilder::new().program(&[0x7001, 0x1200]).build();
+    let out = Shared::default();
+    let mut debugger = Debugger::new();
+    debugger.set_trace(Some(Trace::new(Box::new(out.clone()))));
+
+    debugger.run_frame(&mut cpu, 10).unwrap();
+    debugger.step(&mut cpu).unwrap();
+    assert!(debugger.set_trace(None).unwrap().finish().is_ok());
+    debugger.step(&mut cpu).unwrap();
+
+    let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 11);
+    assert!(
+        lines[10].starts_with("000010 PC:0200 OP:7001"),
+        "{}",
+        lines[10]
+    );
+}
+
+#[test]
+fn repl_trace_command_writes_trace_lines() {
+    let path = env::temp_dir().join(format!("chip8-trace-{}-repl.txt", process::id()));
+    let mut cpu = CpuBuilder::new().program(&[0x7001, 0x1200]).build();
+    let mut debugger = Debugger::new();
+    let on = format!("trace on {}", path.display());
+
+    assert_eq!(debugger.execute(&mut cpu, &on), Ok("tracing on".to_owned()));
+    debugger.execute(&mut cpu, "s").unwrap();
+    debugger.execute(&mut cpu, "s").unwrap();
+    assert_eq!(
+        debugger.execute(&mut cpu, "trace off"),
+        Ok("tracing off".to_owned())
+    );
+    debugger.execute(&mut cpu, "s").unwrap();
+    assert!(debugger.execute(&mut cpu, "trace maybe").is_err());
+
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(
+        lines[1].starts_with("000001 PC:0202 OP:1200 JP #200"),
+        "{}",
+        lines[1]
+    );
+}